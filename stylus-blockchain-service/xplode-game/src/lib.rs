@@ -2,14 +2,15 @@
 extern crate alloc;
 
 use stylus_sdk::{
-    alloy_primitives::{U256, FixedBytes},
+    alloy_primitives::{U256, U8, FixedBytes},
     alloy_sol_types::sol,
     prelude::*,
     storage::{StorageMap, StorageU256, StorageBool},
 };
 
 sol! {
-    event GameInitialized(bytes32 indexed gameId, uint256 gridSize, address gameServer);
+    event GameInitialized(bytes32 indexed gameId, uint256 width, uint256 height, uint8 topology, bytes32 serverSeedHash, address gameServer);
+    event GameRevealed(bytes32 indexed gameId, bytes32 serverSeed);
     event MoveMade(bytes32 indexed gameId, string playerName, uint256 x, uint256 y, uint256 timestamp);
     event GameDelegated(bytes32 indexed gameId, address gameServer);
     event GameCommitted(bytes32 indexed gameId, address gameServer);
@@ -19,12 +20,21 @@ sol_storage! {
     #[entrypoint]
     pub struct XplodeGame {
         mapping(bytes32 => bool) game_exists;
-        mapping(bytes32 => uint256) grid_sizes;
+        // Board dimensions, topology 0 is Square, 1 Hex and 2 Torus
+        mapping(bytes32 => uint256) widths;
+        mapping(bytes32 => uint256) heights;
+        mapping(bytes32 => uint8) topologies;
         mapping(bytes32 => address) game_servers;
         mapping(bytes32 => bool) is_delegated;
         mapping(bytes32 => uint256) move_counts;
         mapping(bytes32 => uint256) bomb_counts;
-        
+
+        // Commitment to the server seed, the seed and bombs are only stored once the
+        // game is over
+        mapping(bytes32 => bytes32) seed_hashes;
+        mapping(bytes32 => bytes32) server_seeds;
+        mapping(bytes32 => bool) is_revealed;
+
         // Bomb positions: game_id => index => x or y
         mapping(bytes32 => mapping(uint256 => uint256)) bomb_x;
         mapping(bytes32 => mapping(uint256 => uint256)) bomb_y;
@@ -50,8 +60,10 @@ impl XplodeGame {
     pub fn initialize_game(
         &mut self,
         game_id: String,
-        grid_size: U256,
-        bomb_positions: Vec<(U256, U256)>,
+        width: U256,
+        height: U256,
+        topology: u8,
+        server_seed_hash: FixedBytes<32>,
     ) {
         let sender = self.vm().msg_sender();
         let game_id_bytes = string_to_bytes32(&game_id);
@@ -60,25 +72,65 @@ impl XplodeGame {
             return; // Game already exists
         }
 
+        if width == U256::ZERO || height == U256::ZERO || topology > 2 {
+            return; // Invalid board
+        }
+
         self.game_exists.setter(game_id_bytes).set(true);
-        self.grid_sizes.setter(game_id_bytes).set(grid_size);
+        self.widths.setter(game_id_bytes).set(width);
+        self.heights.setter(game_id_bytes).set(height);
+        self.topologies.setter(game_id_bytes).set(U8::from(topology));
         self.game_servers.setter(game_id_bytes).set(sender);
         self.is_delegated.setter(game_id_bytes).set(false);
         self.move_counts.setter(game_id_bytes).set(U256::ZERO);
+        self.seed_hashes.setter(game_id_bytes).set(server_seed_hash);
+
+        stylus_sdk::stylus_core::log(self.vm(), GameInitialized {
+            gameId: game_id_bytes,
+            width,
+            height,
+            topology,
+            serverSeedHash: server_seed_hash,
+            gameServer: sender,
+        });
+    }
+
+    // Publishes the seed and the bombs it placed, once the game is over
+    pub fn reveal_game(
+        &mut self,
+        game_id: String,
+        server_seed: FixedBytes<32>,
+        bomb_positions: Vec<(U256, U256)>,
+    ) {
+        let game_id_bytes = string_to_bytes32(&game_id);
+
+        if !self.game_exists.get(game_id_bytes) {
+            return; // Game doesn't exist
+        }
+
+        if self.game_servers.get(game_id_bytes) != self.vm().msg_sender() {
+            return; // Unauthorized
+        }
+
+        if self.is_revealed.get(game_id_bytes) {
+            return; // Already revealed
+        }
+
+        self.is_revealed.setter(game_id_bytes).set(true);
+        self.server_seeds.setter(game_id_bytes).set(server_seed);
 
         let bomb_count = U256::from(bomb_positions.len());
         self.bomb_counts.setter(game_id_bytes).set(bomb_count);
-        
+
         for (i, (x, y)) in bomb_positions.iter().enumerate() {
             let index = U256::from(i);
             self.bomb_x.setter(game_id_bytes).setter(index).set(*x);
             self.bomb_y.setter(game_id_bytes).setter(index).set(*y);
         }
 
-        stylus_sdk::stylus_core::log(self.vm(), GameInitialized {
+        stylus_sdk::stylus_core::log(self.vm(), GameRevealed {
             gameId: game_id_bytes,
-            gridSize: grid_size,
-            gameServer: sender,
+            serverSeed: server_seed,
         });
     }
 
@@ -153,9 +205,13 @@ impl XplodeGame {
         self.game_exists.get(game_id_bytes)
     }
 
-    pub fn get_grid_size(&self, game_id: String) -> U256 {
+    pub fn get_geometry(&self, game_id: String) -> (U256, U256, u8) {
         let game_id_bytes = string_to_bytes32(&game_id);
-        self.grid_sizes.get(game_id_bytes)
+        (
+            self.widths.get(game_id_bytes),
+            self.heights.get(game_id_bytes),
+            self.topologies.get(game_id_bytes).to::<u8>(),
+        )
     }
 
     pub fn get_move_count(&self, game_id: String) -> U256 {
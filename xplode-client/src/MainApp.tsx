@@ -13,6 +13,8 @@ import { useWalletStore } from './stores/walletStore';
 import { QueryClient, QueryClientProvider } from '@tanstack/react-query';
 import '@rainbow-me/rainbowkit/styles.css';
 import NFTGallery from './pages/NFTGallery/NFTGallery';
+import { fromLedgerUnits } from './utils/amountUtils';
+import { sessionHeaders, setSessionToken } from './utils/sessionUtils';
 
 const queryClient = new QueryClient();
 
@@ -85,7 +87,7 @@ const UsernameRequiredWrapper = ({
 };
 
 const MainApp: React.FC = () => {
-  const { authenticated, user } = usePrivy();
+  const { authenticated, user, getAccessToken } = usePrivy();
   const [userData, setUserData] = useState<UserData | undefined>();
   const [isLoading, setIsLoading] = useState(true);
   const [isUsernameModalOpen, setIsUsernameModalOpen] = useState(false);
@@ -136,10 +138,17 @@ const MainApp: React.FC = () => {
 
       while (retryCount <= maxRetries) {
         try {
+          // The wallet server only issues a session for the Privy user's own token
+          const accessToken = await getAccessToken();
+          if (!accessToken) {
+            throw new Error('No Privy access token available');
+          }
+
           const userDetailsResponse = await fetch(import.meta.env.VITE_USER_DETAILS_ENDPOINT_URL, {
             method: 'POST',
             headers: {
               'Content-Type': 'application/json',
+              Authorization: `Bearer ${accessToken}`,
             },
             body: JSON.stringify(newUserData),
           });
@@ -152,7 +161,8 @@ const MainApp: React.FC = () => {
 
           // Check if all required fields are present
           if (userDetailsData.id === undefined ||
-            typeof userDetailsData.balance !== 'number') {
+            typeof userDetailsData.balance !== 'number' ||
+            typeof userDetailsData.session_token !== 'string') {
 
             if (retryCount < maxRetries) {
               retryCount++;
@@ -164,12 +174,14 @@ const MainApp: React.FC = () => {
           }
 
           // If we reached here, we have valid data
-          setBalance(userDetailsData.balance);
+          setSessionToken(userDetailsData.session_token);
+          const balance = fromLedgerUnits(userDetailsData.balance, newUserData.currency);
+          setBalance(balance);
 
           const updatedUserData = {
             ...newUserData,
             id: userDetailsData.id,
-            wallet_balance: userDetailsData.balance,
+            wallet_balance: balance,
             deposit_address: userDetailsData.wallet_address || "",
             name: userDetailsData.name || "",
             gif_ids: userDetailsData.gif_ids || []
@@ -215,7 +227,7 @@ const MainApp: React.FC = () => {
     };
 
     fetchUserData();
-  }, [authenticated, user, setBalance]);
+  }, [authenticated, user, getAccessToken, setBalance]);
 
   // Handle username setting
   const handleUsernameSet = async (username: string) => {
@@ -239,6 +251,7 @@ const MainApp: React.FC = () => {
         method: 'POST',
         headers: {
           'Content-Type': 'application/json',
+          ...sessionHeaders(),
         },
         body: JSON.stringify(updateRequest),
       });
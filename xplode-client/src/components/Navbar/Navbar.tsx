@@ -1,6 +1,7 @@
 import React, { useState, useRef, useEffect } from 'react';
 import { Link, useLocation } from 'react-router-dom';
 import { usePrivy } from '@privy-io/react-auth';
+import { clearSessionToken } from '../../utils/sessionUtils';
 import { GamepadIcon, Users, User,  Menu, X, Diamond, Trophy, Store, ChevronDown } from 'lucide-react';
 import { WalletDropdown } from '../PaymentUI/WalletDropdown';
 import { motion, AnimatePresence } from 'framer-motion';
@@ -212,6 +213,7 @@ const Navbar: React.FC<NavbarProps> = ({ userData }) => {
                             {authenticated && (
                               <button
                                 onClick={() => {
+                                  clearSessionToken();
                                   logout();
                                   setIsGameHubOpen(false);
                                 }}
@@ -1,4 +1,5 @@
 import { useState } from "react";
+import { fromLedgerUnits, toLedgerUnits } from "../utils/amountUtils";
 
 interface UseArbitrumPaymentProps {
   userId?: number;
@@ -200,7 +201,7 @@ export const useArbitrumPayment = ({
 
       const depositData = {
         user_id: userId,
-        amount: Number(amount),
+        amount: toLedgerUnits(amount, "SOL"),
         currency: "SOL", // Keep as SOL for now to not break backend
         tx_hash: txHash,
         tx_type: "DEPOSIT"
@@ -225,7 +226,7 @@ export const useArbitrumPayment = ({
       const result = await response.json();
 
       if (typeof result.balance === "number") {
-        onPaymentComplete(result.balance);
+        onPaymentComplete(fromLedgerUnits(result.balance, "SOL"));
         setPaymentStatus("completed");
       } else {
         throw new Error("Invalid balance received from server");
@@ -23,7 +23,8 @@ import { gifNfts } from "../data/gifData"; // Import the gifNfts array
 
 // Constants
 const quicknodeEndpoint = import.meta.env.VITE_SOLANA_RPC_URL;
-const treasury = publicKey(import.meta.env.VITE_TREASURY);
+// Mints are paid here, not to the treasury, whose incoming transfers are deposits
+const mintPaymentAddress = publicKey(import.meta.env.VITE_MINT_PAYMENT_ADDRESS);
 
 // Get all collection IDs from env variables
 const getAllCollectionIds = () => {
@@ -107,7 +108,7 @@ export const useNftMint = () => {
               collectionMint: candyMachine.collectionMint,
               collectionUpdateAuthority: candyMachine.authority,
               mintArgs: {
-                solPayment: some({ destination: treasury }),
+                solPayment: some({ destination: mintPaymentAddress }),
               },
             })
           );
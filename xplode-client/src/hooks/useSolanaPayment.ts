@@ -8,6 +8,7 @@ import {
   LAMPORTS_PER_SOL,
   clusterApiUrl,
 } from "@solana/web3.js";
+import { fromLedgerUnits, toLedgerUnits } from "../utils/amountUtils";
 
 interface UseSolanaPaymentProps {
   userId?: number;
@@ -194,7 +195,7 @@ export const useSolanaPayment = ({
 
       const depositData = {
         user_id: userId,
-        amount: Number(amount),
+        amount: toLedgerUnits(amount, "SOL"),
         currency: "SOL",
         tx_hash: txHash,
         tx_type: "DEPOSIT"
@@ -221,7 +222,7 @@ export const useSolanaPayment = ({
       const result = await response.json();
 
       if (typeof result.balance === "number") {
-        onPaymentComplete(result.balance);
+        onPaymentComplete(fromLedgerUnits(result.balance, "SOL"));
         setPaymentStatus("completed");
       } else {
         throw new Error("Invalid balance received from server");
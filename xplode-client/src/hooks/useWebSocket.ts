@@ -1,5 +1,6 @@
 import { useCallback, useEffect, useRef, useState } from "react";
 import { GameMessage, GameState } from "../types/gameTypes";
+import { withSessionToken } from "../utils/sessionUtils";
 
 const INITIAL_RECONNECT_DELAY = 1000;
 const MAX_RECONNECT_DELAY = 30000;
@@ -107,7 +108,7 @@ const useWebSocket = ({
         // console.log("WebSocket URL:", wsUrl);
 
         // Create WebSocket connection
-        const ws = new WebSocket(wsUrl);
+        const ws = new WebSocket(withSessionToken(wsUrl));
         wsRef.current = ws;
         ws.binaryType = "arraybuffer";
 
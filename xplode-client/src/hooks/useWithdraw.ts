@@ -1,5 +1,8 @@
 // src/hooks/useWithdraw.ts
 import { useState } from "react";
+import { fromLedgerUnits, toLedgerUnits } from "../utils/amountUtils";
+import { sessionHeaders } from "../utils/sessionUtils";
+import { useWalletStore } from "../stores/walletStore";
 
 interface UseWithdrawProps {
   userId?: number;
@@ -7,6 +10,24 @@ interface UseWithdrawProps {
   onWithdrawFailed: (error: string) => void;
 }
 
+const WITHDRAWAL_POLL_MS = 5000;
+
+// Polls GET /withdrawal/{id} with the session until the withdrawal is confirmed or failed
+const waitForWithdrawal = async (id: number) => {
+  const url = `${import.meta.env.VITE_API_BASE_URL || ""}/withdrawal/${id}`;
+  for (;;) {
+    await new Promise(resolve => setTimeout(resolve, WITHDRAWAL_POLL_MS));
+    const response = await fetch(url, { headers: sessionHeaders() });
+    if (!response.ok) {
+      throw new Error(`Failed to fetch withdrawal: ${await response.text()}`);
+    }
+    const { withdrawal } = await response.json();
+    if (withdrawal.status === "CONFIRMED" || withdrawal.status === "FAILED") {
+      return withdrawal;
+    }
+  }
+};
+
 export const useWithdraw = ({
   userId,
   onWithdrawComplete,
@@ -30,7 +51,7 @@ export const useWithdraw = ({
 
       const withdrawData = {
         user_id: userId,
-        amount: Number(amount),
+        amount: toLedgerUnits(amount, "SOL"),
         currency: "SOL",
         withdraw_address: withdrawAddress,
       };
@@ -43,6 +64,7 @@ export const useWithdraw = ({
           method: "POST",
           headers: {
             "Content-Type": "application/json",
+            ...sessionHeaders(),
           },
           body: JSON.stringify(withdrawData),
         }
@@ -53,14 +75,25 @@ export const useWithdraw = ({
         throw new Error(`Withdrawal failed: ${errorText}`);
       }
 
-      const result = await response.json();
+      // The withdrawal is queued, the worker sends it
+      const withdrawal = await response.json();
+      if (typeof withdrawal.id !== "number") {
+        throw new Error("Invalid withdrawal received from server");
+      }
+      // An admin decides on withdrawals in review, that can take a while
+      if (withdrawal.status === "REVIEW") {
+        setWithdrawStatus("waiting for review");
+        return;
+      }
+      setWithdrawStatus("processing");
 
-      if (typeof result.balance === "number") {
-        onWithdrawComplete(result.balance);
-        setWithdrawStatus("completed");
-      } else {
-        throw new Error("Invalid balance received from server");
+      const settled = await waitForWithdrawal(withdrawal.id);
+      if (settled.status === "FAILED") {
+        throw new Error(`Withdrawal failed: ${settled.error ?? "unknown error"}`);
       }
+      const { balance } = useWalletStore.getState();
+      onWithdrawComplete(balance - fromLedgerUnits(settled.amount, "SOL"));
+      setWithdrawStatus("completed");
     } catch (error) {
       setWithdrawStatus("failed");
       onWithdrawFailed(
@@ -2,6 +2,7 @@ import React, { useState, useEffect } from 'react';
 import { motion } from 'framer-motion';
 import { Trophy, Coins, ArrowUpDown, Users, TrendingUp, Clock } from 'lucide-react';
 import { useParticles } from '../../components/LandingPage/Particles';
+import { fromLedgerUnits } from '../../utils/amountUtils';
 
 interface LeaderboardEntry {
   id: number;
@@ -49,9 +50,12 @@ const LeaderboardPage: React.FC = () => {
         throw new Error(`HTTP error! Status: ${response.status}`);
       }
       
-      const data = await response.json();
+      const data: LeaderboardEntry[] = await response.json();
     //   console.log("################################: ", data);
-      setLeaderboardData(data);
+      setLeaderboardData(data.map(entry => ({
+        ...entry,
+        total_profit: fromLedgerUnits(entry.total_profit, currency),
+      })));
     } catch (error) {
       console.error('Error fetching leaderboard data:', error);
       // Set empty array to prevent errors in rendering
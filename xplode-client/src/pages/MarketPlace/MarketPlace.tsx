@@ -5,6 +5,9 @@ import { usePayment } from '../../hooks/usePayment';
 import { useNftMint } from '../../hooks/useNftMint';
 import { motion, AnimatePresence } from 'framer-motion';
 import { gifNfts, GifNft } from '../../data/gifData'; // Import from our new file
+import { fromLedgerUnits, toLedgerUnits } from '../../utils/amountUtils';
+import { sessionHeaders, setSessionToken } from '../../utils/sessionUtils';
+import { usePrivy } from '@privy-io/react-auth';
 
 export default function CosmicGifMarketplace() {
   const [currentPage, setCurrentPage] = useState(0);
@@ -15,6 +18,7 @@ export default function CosmicGifMarketplace() {
   // Add a new state to track which NFT is currently being processed
   const [processingNftId, setProcessingNftId] = useState<number | null>(null);
   const Particles = useParticles();
+  const { getAccessToken } = usePrivy();
 
   // Get userId as number from userData stored in localStorage
   const [userId, setUserId] = useState<number | undefined>(undefined);
@@ -175,8 +179,7 @@ export default function CosmicGifMarketplace() {
           }
 
           const mintData = {
-            user_id: userId,
-            mint_amount: Number(selectedGif.price),
+            mint_amount: toLedgerUnits(selectedGif.price, "SOL"),
             currency: "SOL",
             tx_type: "PURCHASE",
             gif_id: selectedGif.id,
@@ -192,6 +195,7 @@ export default function CosmicGifMarketplace() {
               method: "POST",
               headers: {
                 "Content-Type": "application/json",
+                ...sessionHeaders(),
               },
               body: JSON.stringify(mintData),
             }
@@ -223,10 +227,13 @@ export default function CosmicGifMarketplace() {
           console.log("Fetching updated user details after mint");
 
           // Call the user details endpoint to get updated data
+          const accessToken = await getAccessToken();
+          if (!accessToken) throw new Error("No Privy access token available");
           const userDetailsResponse = await fetch(import.meta.env.VITE_USER_DETAILS_ENDPOINT_URL, {
             method: 'POST',
             headers: {
               'Content-Type': 'application/json',
+              Authorization: `Bearer ${accessToken}`,
             },
             body: JSON.stringify(userDetailsPayload),
           });
@@ -236,12 +243,13 @@ export default function CosmicGifMarketplace() {
           }
 
           const userDetailsData = await userDetailsResponse.json();
+          setSessionToken(userDetailsData.session_token);
           // console.log("Received updated user details:", userDetailsData);
 
           // Update user data in localStorage with the new data
           const updatedUserData = {
             ...storedUserData,
-            wallet_balance: userDetailsData.balance,
+            wallet_balance: fromLedgerUnits(userDetailsData.balance, "SOL"),
             gif_ids: userDetailsData.gif_ids || []
           };
 
@@ -274,7 +282,7 @@ export default function CosmicGifMarketplace() {
       setTransactionResult('failed');
       setProcessingNftId(null); // Clear processing state on error
     }
-  }, [selectedGif, userId, isNftWalletConnected, mintNft]);
+  }, [selectedGif, userId, isNftWalletConnected, mintNft, getAccessToken]);
 
 
   const handleCancelTransaction = useCallback(() => {
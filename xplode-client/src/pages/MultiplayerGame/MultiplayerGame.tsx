@@ -17,6 +17,7 @@ import GifTauntFeature from '../../components/GameComponents/GifTauntFeature/Gif
 import BlockchainNotification from '../../components/GameComponents/BlockchainNotification/BlockchainNotification';
 
 import { useWalletStore } from '../../stores/walletStore';
+import { toLedgerUnits } from '../../utils/amountUtils';
 
 const MOVE_TIMEOUT = 30000; // 30 seconds
 const LOCK_PHASE_TIMEOUT = 5000; // 5 seconds
@@ -1239,7 +1240,7 @@ useEffect(() => {
     sendMessage({
       Play: {
         player_id: userData.id.toString(),
-        single_bet_size: betAmount,
+        single_bet_size: toLedgerUnits(betAmount, "SOL"),
         name: userData.name.toString(),
         grid: gridSize,
         bombs,
@@ -1284,7 +1285,7 @@ useEffect(() => {
     sendMessage({
       Play: {
         player_id: userData.id.toString(),
-        single_bet_size: betAmount,
+        single_bet_size: toLedgerUnits(betAmount, "SOL"),
         name: userData.name.toString(),
         grid: gridSize,
         bombs,
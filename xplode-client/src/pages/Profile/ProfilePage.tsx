@@ -20,6 +20,7 @@ import {
 } from 'lucide-react';
 import { useParticles } from '../../components/LandingPage/Particles';
 import { useWalletStore } from '../../stores/walletStore';
+import { fromLedgerUnits } from '../../utils/amountUtils';
 
 interface GamePnl {
   id: number;
@@ -166,7 +167,10 @@ const ProfilePage: React.FC = () => {
         throw new Error(`HTTP error! Status: ${response.status}`);
       }
       
-      const data: GamePnl[] = await response.json();
+      const data: GamePnl[] = (await response.json()).map((game: GamePnl) => ({
+        ...game,
+        profit: fromLedgerUnits(game.profit, game.currency),
+      }));
       // console.log("Game PnL data received:", data);
       setUserGamePnl(data);
       
@@ -0,0 +1,25 @@
+// The servers exchange money as whole ledger units of the currency (lamports for
+// SOL), these decimals must match `Currency::decimals` in xplode-engine
+const LEDGER_DECIMALS: Record<string, number> = {
+  INR: 2,
+  SOL: 9,
+  USDC: 6,
+  MON: 9,
+  ETH: 9,
+};
+
+const decimalsOf = (currency: string): number => {
+  const decimals = LEDGER_DECIMALS[currency];
+  if (decimals === undefined) {
+    throw new Error(`Unknown currency ${currency}`);
+  }
+  return decimals;
+};
+
+// Decimal amount typed by the user, e.g. "0.5", as ledger units
+export const toLedgerUnits = (amount: number | string, currency: string): number =>
+  Math.round(Number(amount) * 10 ** decimalsOf(currency));
+
+// Ledger units sent by the servers as a decimal amount for display
+export const fromLedgerUnits = (units: number, currency: string): number =>
+  units / 10 ** decimalsOf(currency);
@@ -0,0 +1,26 @@
+// Session issued by the wallet server's /user-details for a Privy access token.
+// The game server and the wallet's per-user endpoints only accept requests
+// carrying it.
+const SESSION_KEY = 'sessionToken';
+
+export const getSessionToken = (): string | null => localStorage.getItem(SESSION_KEY);
+
+export const setSessionToken = (token: string) => localStorage.setItem(SESSION_KEY, token);
+
+export const clearSessionToken = () => localStorage.removeItem(SESSION_KEY);
+
+// `Authorization` header for the wallet server
+export const sessionHeaders = (): Record<string, string> => {
+  const token = getSessionToken();
+  return token ? { Authorization: `Bearer ${token}` } : {};
+};
+
+// Browsers can't set headers on a websocket upgrade, the game server also reads
+// the session from the `token` query parameter
+export const withSessionToken = (url: string): string => {
+  const token = getSessionToken();
+  if (!token) return url;
+  const withToken = new URL(url, window.location.href);
+  withToken.searchParams.set('token', token);
+  return withToken.toString();
+};
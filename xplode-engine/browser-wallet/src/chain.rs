@@ -0,0 +1,166 @@
+use std::{collections::HashMap, env, str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use common::utils::{Currency, Network};
+use tracing::info;
+
+use crate::{evm::EvmAdapter, solana::SolanaAdapter};
+
+/// A signed treasury transfer that hasn't necessarily been sent yet
+pub struct SignedTransfer {
+    pub tx_hash: String,
+    // Chain specific encoding, hex
+    pub raw: String,
+    // Solana: last block height it can land in. EVM: its nonce.
+    pub valid_until: u64,
+}
+
+pub enum TransferStatus {
+    // Not landed or not deep enough yet, sending it again is harmless
+    Pending,
+    Confirmed,
+    // Can never pay out, the reserved funds go back to the user
+    Failed(String),
+}
+
+/// Everything the wallet service does on a chain for one asset, in the asset's
+/// smallest on-chain unit
+#[async_trait]
+pub trait ChainAdapter: Send + Sync {
+    fn network(&self) -> Network;
+
+    /// Decimals of the asset's smallest on-chain unit, e.g. 18 for wei
+    fn decimals(&self) -> u32;
+
+    fn validate_address(&self, address: &str) -> bool;
+
+    /// Whether `signature` is `address` signing `message` the way its wallets sign
+    /// messages, which proves the address belongs to whoever sent it
+    fn verify_address_owner(&self, address: &str, message: &str, signature: &str) -> bool;
+
+    /// Signs a transfer from the treasury without sending it, so it can be
+    /// stored first. Sending the same signed transfer again can't pay twice.
+    async fn sign_transfer(&self, to_address: &str, amount: u128)
+        -> anyhow::Result<SignedTransfer>;
+
+    async fn broadcast(&self, raw: &str) -> anyhow::Result<()>;
+
+    /// Checks that `tx_hash` is a successful transfer from `sender` to the treasury
+    /// with enough confirmations, and returns how much it moved.
+    async fn verify_incoming_transfer(&self, tx_hash: &str, sender: &str) -> anyhow::Result<u128>;
+
+    /// Like `verify_incoming_transfer`, for a transfer to the mint payment address.
+    /// Mints are paid there rather than to the treasury so that deposit watchers
+    /// never credit them.
+    async fn verify_mint_payment(&self, tx_hash: &str, sender: &str) -> anyhow::Result<u128>;
+
+    /// Confirmations of a landed transaction, None while it hasn't landed
+    async fn confirmations(&self, tx_hash: &str) -> anyhow::Result<Option<u64>>;
+
+    /// What the treasury holds of the asset
+    async fn treasury_balance(&self) -> anyhow::Result<u128>;
+
+    /// Where a transfer signed by `sign_transfer` stands
+    async fn transfer_status(
+        &self,
+        tx_hash: &str,
+        valid_until: u64,
+    ) -> anyhow::Result<TransferStatus>;
+}
+
+/// Chain adapters by the currency they move
+#[derive(Clone, Default)]
+pub struct ChainRegistry {
+    adapters: HashMap<Currency, Arc<dyn ChainAdapter>>,
+    // The EVM ones again, their deposits are also found by watching blocks
+    evm_adapters: Vec<(Currency, Arc<EvmAdapter>)>,
+}
+
+impl ChainRegistry {
+    /// Registers every chain whose RPC url is configured, and USDC on USDC_NETWORK
+    /// as the token at `<NETWORK>_USDC_TOKEN`, e.g. SOLANA_USDC_TOKEN
+    pub async fn from_env() -> anyhow::Result<Self> {
+        let mut registry = ChainRegistry::default();
+        if env::var("SOLANA_RPC_URL").is_ok() {
+            registry.register(Currency::SOL, SolanaAdapter::from_env()?);
+        }
+        // EVM chains only differ by their settings, read with the network as prefix
+        for (currency, network) in [
+            (Currency::MON, Network::MONAD),
+            (Currency::ETH, Network::ARBITRUM),
+        ] {
+            if env::var(format!("{}_RPC_URL", network)).is_ok() {
+                registry.register_evm(currency, EvmAdapter::from_env(network)?);
+            }
+        }
+
+        if let Ok(network) = env::var("USDC_NETWORK") {
+            let network = Network::from_str(&network)?;
+            let token_var = format!("{}_USDC_TOKEN", network);
+            let token = env::var(&token_var).map_err(|_| anyhow!("{} is not set", token_var))?;
+            match network {
+                Network::SOLANA => registry.register(
+                    Currency::USDC,
+                    SolanaAdapter::from_env()?.with_token(&token).await?,
+                ),
+                _ => registry.register_evm(
+                    Currency::USDC,
+                    EvmAdapter::from_env(network)?.with_token(&token).await?,
+                ),
+            }
+        }
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, currency: Currency, adapter: impl ChainAdapter + 'static) {
+        self.insert(currency, Arc::new(adapter));
+    }
+
+    pub fn register_evm(&mut self, currency: Currency, adapter: EvmAdapter) {
+        let adapter = Arc::new(adapter);
+        self.evm_adapters.push((currency, adapter.clone()));
+        self.insert(currency, adapter);
+    }
+
+    fn insert(&mut self, currency: Currency, adapter: Arc<dyn ChainAdapter>) {
+        info!(
+            "Registering {} on {} with {} decimals",
+            currency,
+            adapter.network(),
+            adapter.decimals()
+        );
+        self.adapters.insert(currency, adapter);
+    }
+
+    pub fn get(&self, currency: Currency) -> Option<Arc<dyn ChainAdapter>> {
+        self.adapters.get(&currency).cloned()
+    }
+
+    /// Every registered currency with its adapter
+    pub fn iter(&self) -> impl Iterator<Item = (Currency, &Arc<dyn ChainAdapter>)> {
+        self.adapters
+            .iter()
+            .map(|(currency, adapter)| (*currency, adapter))
+    }
+
+    pub fn evm_adapters(&self) -> &[(Currency, Arc<EvmAdapter>)] {
+        &self.evm_adapters
+    }
+}
+
+/// Where NFT mints are paid on `network`, from `<NETWORK>_MINT_PAYMENT_ADDRESS`.
+/// None disables mints paid on it.
+pub fn mint_payment_address(network: &Network) -> Option<String> {
+    env::var(format!("{}_MINT_PAYMENT_ADDRESS", network)).ok()
+}
+
+/// Confirmations a transfer on `network` needs, from `<NETWORK>_MIN_CONFIRMATIONS`.
+/// At least one, a transfer has to be in a block.
+pub fn min_confirmations(network: &Network, default: u64) -> u64 {
+    env::var(format!("{}_MIN_CONFIRMATIONS", network))
+        .ok()
+        .and_then(|confirmations| confirmations.parse().ok())
+        .unwrap_or(default)
+        .max(1)
+}
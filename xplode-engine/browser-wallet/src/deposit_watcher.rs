@@ -0,0 +1,155 @@
+use std::{env, sync::Arc, time::Duration};
+
+use common::{
+    db, telegram,
+    utils::{Amount, Currency},
+};
+use evm_deposits::TreasuryDeposit;
+use sqlx::{Pool, Postgres};
+use tracing::{error, info};
+
+use crate::{chain::ChainAdapter, evm::EvmAdapter};
+
+// Bounds one poll, so catching up after downtime still commits as it goes
+const MAX_BLOCKS_PER_POLL: u64 = 500;
+
+/// Credits transfers into the treasury of an EVM chain by following its blocks, so
+/// deposits land even when the client never posts them to /deposit. A block is only
+/// processed once it has the chain's minimum confirmations, and each block is
+/// processed in the transaction that moves the cursor past it.
+pub async fn run_deposit_watcher(pool: Pool<Postgres>, currency: Currency, chain: Arc<EvmAdapter>) {
+    let cursor_name = format!("{}:{}", chain.network(), currency);
+    let poll_interval = Duration::from_secs(
+        env::var("DEPOSIT_POLL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(5),
+    );
+    info!("Watching {} deposits on {}", currency, chain.network());
+
+    loop {
+        if let Err(e) = process_blocks(&pool, currency, &chain, &cursor_name).await {
+            error!("Failed to process {} blocks: {}", cursor_name, e);
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+async fn process_blocks(
+    pool: &Pool<Postgres>,
+    currency: Currency,
+    chain: &EvmAdapter,
+    cursor_name: &str,
+) -> anyhow::Result<()> {
+    let head = chain.latest_block_number().await?;
+    // Block `n` has `head - n + 1` confirmations
+    let Some(confirmed) = (head + 1).checked_sub(chain.min_confirmations()) else {
+        return Ok(());
+    };
+
+    let (mut next, mut parent_hash) = match db::get_chain_cursor(pool, cursor_name).await? {
+        Some(cursor) => (
+            u64::try_from(cursor.block_number)? + 1,
+            Some(cursor.block_hash),
+        ),
+        // A new watcher starts at the tip, older deposits can still be posted to /deposit
+        None => (confirmed, None),
+    };
+    let last = confirmed.min(next.saturating_add(MAX_BLOCKS_PER_POLL - 1));
+
+    while next <= last {
+        let block = chain.scan_block(next).await?;
+        if parent_hash.is_some_and(|hash| hash != block.parent_hash) {
+            return rewind(pool, chain, cursor_name, next - 1).await;
+        }
+
+        let mut tx = pool.begin().await?;
+        for deposit in &block.deposits {
+            credit(&mut tx, currency, chain, deposit).await?;
+        }
+        db::set_chain_cursor(
+            &mut tx,
+            cursor_name,
+            i64::try_from(block.number)?,
+            &block.hash,
+        )
+        .await?;
+        tx.commit().await?;
+
+        parent_hash = Some(block.hash);
+        next += 1;
+    }
+    Ok(())
+}
+
+async fn credit(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    currency: Currency,
+    chain: &EvmAdapter,
+    deposit: &TreasuryDeposit,
+) -> anyhow::Result<()> {
+    let Some(user_id) = db::find_wallet_owner(tx, currency, &deposit.sender).await? else {
+        info!(
+            "Ignoring deposit {} from unregistered address {}",
+            deposit.tx_hash, deposit.sender
+        );
+        return Ok(());
+    };
+    let amount = match Amount::from_chain_units(deposit.amount, currency, chain.decimals()) {
+        Some(amount) if amount > Amount::ZERO => amount,
+        // Dust below the ledger's precision, or too large to hold
+        _ => {
+            error!(
+                "Can't credit deposit {} of {}",
+                deposit.tx_hash, deposit.amount
+            );
+            return Ok(());
+        }
+    };
+
+    if db::credit_deposit(tx, user_id, currency, amount, &deposit.tx_hash).await? {
+        info!(
+            "Credited {} {} to user {} for {}",
+            amount.to_decimal(currency),
+            currency,
+            user_id,
+            deposit.tx_hash
+        );
+    }
+    Ok(())
+}
+
+// The processed block `replaced` is no longer on the chain, a reorg went deeper than
+// the minimum confirmations. Blocks from a few confirmations further back are scanned
+// again, which credits each transaction at most once, but credits from the replaced
+// blocks can't be taken back automatically.
+async fn rewind(
+    pool: &Pool<Postgres>,
+    chain: &EvmAdapter,
+    cursor_name: &str,
+    replaced: u64,
+) -> anyhow::Result<()> {
+    let rewind_to = replaced.saturating_sub(chain.min_confirmations() * 2);
+    let message = format!(
+        "⚠️ Reorg on {}: block {} was replaced, rescanning from {}. Check its deposits.",
+        cursor_name, replaced, rewind_to
+    );
+    error!("{}", message);
+    if env::var("TESTING").unwrap_or_else(|_| "false".to_string()) == "false" {
+        if let Err(e) = telegram::send_telegram_message(&message).await {
+            error!("Failed to send Telegram notification: {}", e);
+        }
+    }
+
+    let block = chain.scan_block(rewind_to).await?;
+    let mut tx = pool.begin().await?;
+    db::set_chain_cursor(
+        &mut tx,
+        cursor_name,
+        i64::try_from(block.number)?,
+        &block.hash,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use common::utils::Network;
+use evm_deposits::{Asset, EvmChain, ScannedBlock};
+
+use crate::chain::{
+    min_confirmations, mint_payment_address, ChainAdapter, SignedTransfer, TransferStatus,
+};
+
+/// Moves the native coin, or an ERC-20 token when built with `with_token`
+pub struct EvmAdapter {
+    network: Network,
+    chain: EvmChain,
+    asset: Asset,
+    decimals: u32,
+    min_confirmations: u64,
+    mint_payment_address: Option<String>,
+}
+
+impl EvmAdapter {
+    /// Reads the network's settings, e.g. MONAD_RPC_URL and MONAD_ACCOUNT_PRIVATE_KEY
+    pub fn from_env(network: Network) -> anyhow::Result<Self> {
+        let chain = EvmChain::from_env(&network.to_string())?;
+        let mint_payment_address = mint_payment_address(&network);
+        if let Some(address) = &mint_payment_address {
+            if !evm_deposits::is_valid_address(address)
+                || address.eq_ignore_ascii_case(&chain.treasury_address().to_string())
+            {
+                return Err(anyhow!(
+                    "{}_MINT_PAYMENT_ADDRESS must be an address other than the treasury",
+                    network
+                ));
+            }
+        }
+        Ok(EvmAdapter {
+            chain,
+            asset: Asset::Native,
+            decimals: 18,
+            min_confirmations: min_confirmations(&network, 3),
+            mint_payment_address,
+            network,
+        })
+    }
+
+    /// Moves the ERC-20 token at `token_address`, its decimals are read from the token
+    pub async fn with_token(mut self, token_address: &str) -> anyhow::Result<Self> {
+        self.asset = Asset::erc20(token_address)?;
+        self.decimals = self.chain.decimals(self.asset).await?;
+        Ok(self)
+    }
+
+    pub fn min_confirmations(&self) -> u64 {
+        self.min_confirmations
+    }
+
+    pub async fn latest_block_number(&self) -> anyhow::Result<u64> {
+        self.chain.latest_block_number().await
+    }
+
+    pub async fn scan_block(&self, number: u64) -> anyhow::Result<ScannedBlock> {
+        self.chain.scan_block(self.asset, number).await
+    }
+}
+
+#[async_trait]
+impl ChainAdapter for EvmAdapter {
+    fn network(&self) -> Network {
+        self.network
+    }
+
+    fn decimals(&self) -> u32 {
+        self.decimals
+    }
+
+    fn validate_address(&self, address: &str) -> bool {
+        evm_deposits::is_valid_address(address)
+    }
+
+    fn verify_address_owner(&self, address: &str, message: &str, signature: &str) -> bool {
+        evm_deposits::is_signed_by(address, message, signature)
+    }
+
+    async fn sign_transfer(
+        &self,
+        to_address: &str,
+        amount: u128,
+    ) -> anyhow::Result<SignedTransfer> {
+        let signed = self
+            .chain
+            .sign_transfer(self.asset, to_address, amount)
+            .await?;
+        Ok(SignedTransfer {
+            tx_hash: signed.tx_hash,
+            raw: signed.raw,
+            valid_until: signed.nonce,
+        })
+    }
+
+    async fn broadcast(&self, raw: &str) -> anyhow::Result<()> {
+        self.chain.broadcast_transfer(raw).await
+    }
+
+    async fn verify_incoming_transfer(&self, tx_hash: &str, sender: &str) -> anyhow::Result<u128> {
+        self.chain
+            .verify_deposit(self.asset, tx_hash, sender, self.min_confirmations)
+            .await
+    }
+
+    async fn verify_mint_payment(&self, tx_hash: &str, sender: &str) -> anyhow::Result<u128> {
+        let recipient = self
+            .mint_payment_address
+            .as_deref()
+            .ok_or_else(|| anyhow!("Mints are not paid on {}", self.network))?;
+        self.chain
+            .verify_transfer(
+                self.asset,
+                tx_hash,
+                sender,
+                recipient,
+                self.min_confirmations,
+            )
+            .await
+    }
+
+    async fn confirmations(&self, tx_hash: &str) -> anyhow::Result<Option<u64>> {
+        Ok(self
+            .chain
+            .transfer_receipt(tx_hash)
+            .await?
+            .map(|receipt| receipt.confirmations))
+    }
+
+    async fn treasury_balance(&self) -> anyhow::Result<u128> {
+        self.chain.treasury_balance(self.asset).await
+    }
+
+    async fn transfer_status(
+        &self,
+        tx_hash: &str,
+        valid_until: u64,
+    ) -> anyhow::Result<TransferStatus> {
+        // Read before the receipt, a transfer mined in between still has its receipt below
+        let mined_nonces = self.chain.treasury_nonce().await?;
+
+        Ok(match self.chain.transfer_receipt(tx_hash).await? {
+            Some(receipt) if receipt.confirmations < self.min_confirmations => {
+                TransferStatus::Pending
+            }
+            Some(receipt) if receipt.succeeded => TransferStatus::Confirmed,
+            Some(_) => TransferStatus::Failed("Transfer reverted".to_string()),
+            // The nonce is `valid_until`, once it's used this transfer can't land anymore
+            None if mined_nonces > valid_until => {
+                TransferStatus::Failed("Transfer nonce was used by another transfer".to_string())
+            }
+            None => TransferStatus::Pending,
+        })
+    }
+}
@@ -4,35 +4,47 @@ use actix_cors::Cors;
 use actix_web::{
     middleware::Logger,
     web::{self, Path},
-    App, HttpResponse, HttpServer, Responder,
+    App, HttpRequest, HttpResponse, HttpServer, Responder,
 };
 use common::{
-    db,
-    models::{GamePnl, LeaderboardEntry, User, UserNetworkPnl, Wallet},
+    auth,
+    db::{self, WithdrawalDecision},
+    models::{GamePnl, User, UserNetworkPnl, Wallet},
     telegram,
     utils::{
-        self, Currency, DepositRequest, MintNftRequest, UpdateUserDetailsRequest,
-        UserDetailsRequest, UserDetailsResponse, WalletType, WithdrawRequest,
+        Amount, Currency, DepositRequest, MintNftRequest, UpdateUserDetailsRequest,
+        UserDetailsRequest, UserDetailsResponse, WithdrawRequest, WithdrawalLimits,
+        WithdrawalStatus,
     },
 };
 use db::establish_connection;
 use dotenv::dotenv;
 
+mod chain;
+mod deposit_watcher;
+mod evm;
 mod solana;
+mod solvency;
+mod withdrawals;
 
-use evm_deposits::transfer_funds;
+use chain::ChainRegistry;
 use serde_json::json;
-use solana::withdraw_funds_to_user;
-use solana_sdk::pubkey::Pubkey;
 use sqlx::{Pool, Postgres};
 use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
-use utils::TxType;
 
-const USD_TO_LAMPORTS: u64 = 1_000_000_000;
+// Token sent as `Authorization: Bearer <token>`
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+}
 
+// Sessions are only handed to whoever holds a Privy access token for the user
 #[actix_web::post("/user-details")]
 async fn fetch_or_create_user(
+    http_req: HttpRequest,
     req: web::Json<UserDetailsRequest>,
     app_state: web::Data<AppState>,
 ) -> impl Responder {
@@ -41,44 +53,110 @@ async fn fetch_or_create_user(
         req.privy_id
     );
     info!("Fetching or creating user details request: {:?}", req);
-    let AppState { pool } = &**app_state;
-    let mut tx = pool.begin().await.expect("Failed to start transaction");
+    let privy_id = match bearer_token(&http_req).map(auth::verify_privy_token) {
+        Some(Ok(privy_id)) => privy_id,
+        Some(Err(e)) => {
+            info!("Rejected Privy access token: {}", e);
+            return HttpResponse::Unauthorized().body("Invalid Privy access token");
+        }
+        None => return HttpResponse::Unauthorized().body("Missing Privy access token"),
+    };
+    if privy_id != req.privy_id {
+        return HttpResponse::Unauthorized().body("Privy access token is for another user");
+    }
+    let AppState { pool, chains } = &**app_state;
+
+    // Deposits are credited by sender, an address is only stored once its owner
+    // signed for it
+    let currency = req.currency.unwrap_or(Currency::MON);
+    let wallet_address = req.wallet_address.as_deref().filter(|a| !a.is_empty());
+    if let Some(address) = wallet_address {
+        let Some(chain) = chains.get(currency) else {
+            return HttpResponse::BadRequest().body("Invalid currency");
+        };
+        let message = auth::address_ownership_message(&req.privy_id, address);
+        let signed = req
+            .wallet_signature
+            .as_deref()
+            .is_some_and(|signature| chain.verify_address_owner(address, &message, signature));
+        if !signed {
+            return HttpResponse::Unauthorized().body("Wallet address isn't signed by its owner");
+        }
+    }
+
+    let Ok(mut tx) = pool.begin().await else {
+        return HttpResponse::InternalServerError().body("Failed to fetch user");
+    };
 
     // Check if the user already exists
-    let existing_user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE privy_id = $1")
-        .bind(&req.privy_id)
-        .fetch_optional(&mut *tx)
-        .await
-        .expect("Error fetching user");
+    let existing_user: Option<User> =
+        match sqlx::query_as("SELECT * FROM users WHERE privy_id = $1")
+            .bind(&req.privy_id)
+            .fetch_optional(&mut *tx)
+            .await
+        {
+            Ok(user) => user,
+            Err(e) => {
+                error!("Failed to fetch user {}: {}", req.privy_id, e);
+                return HttpResponse::InternalServerError().body("Failed to fetch user");
+            }
+        };
+
+    if let Some(address) = wallet_address {
+        let user_id = existing_user.as_ref().map(|user| user.id);
+        match db::wallet_address_taken(&mut tx, user_id, currency, address).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return HttpResponse::Conflict().body("Wallet address belongs to another user")
+            }
+            Err(e) => {
+                error!("Failed to check wallet address: {}", e);
+                return HttpResponse::InternalServerError().body("Failed to check wallet address");
+            }
+        }
+    }
 
     match existing_user {
         Some(user) => {
-            let wallet: Wallet =
-                sqlx::query_as("SELECT * FROM wallet WHERE user_id = $1 AND currency = $2")
-                    .bind(user.id)
-                    .bind(req.currency.unwrap_or(Currency::MON).to_string())
-                    .fetch_one(&mut *tx)
-                    .await
-                    .expect("Error fetching wallet");
-
-            tx.commit().await.expect("Failed to commit transaction");
+            let wallet =
+                match db::get_or_create_wallet(&mut tx, user.id, currency, wallet_address).await {
+                    Ok(wallet) => wallet,
+                    Err(e) => {
+                        error!("Failed to fetch wallet of user {}: {}", user.id, e);
+                        return HttpResponse::InternalServerError().body("Failed to fetch wallet");
+                    }
+                };
+
+            if let Err(e) = tx.commit().await {
+                error!("Failed to commit wallet of user {}: {}", user.id, e);
+                return HttpResponse::InternalServerError().body("Failed to fetch wallet");
+            }
+
+            let session_token = match auth::issue_session_token(user.id) {
+                Ok(token) => token,
+                Err(e) => {
+                    error!("Failed to issue session token: {}", e);
+                    return HttpResponse::InternalServerError().body("Failed to issue session");
+                }
+            };
 
             let user_details_response = UserDetailsResponse {
                 id: user.id,
-                currency: Some(req.currency.unwrap_or(Currency::MON)),
+                currency: Some(currency),
                 name: user.name,
                 email: user.email,
                 balance: wallet.balance,
                 privy_id: user.privy_id,
                 wallet_address: wallet.wallet_address,
                 gif_ids: user.gif_ids,
+                session_token,
             };
 
             HttpResponse::Ok().json(user_details_response)
         }
         None => {
             // Create new user
-            let created_user: User = sqlx::query_as(
+            let created_user: User = match sqlx::query_as(
                 "INSERT INTO users (privy_id, email, name) VALUES ($1, $2, $3) RETURNING *",
             )
             .bind(&req.privy_id)
@@ -86,22 +164,38 @@ async fn fetch_or_create_user(
             .bind(&req.name)
             .fetch_one(&mut *tx)
             .await
-            .expect("Error creating new user");
+            {
+                Ok(user) => user,
+                Err(e) => {
+                    error!("Failed to create user {}: {}", req.privy_id, e);
+                    return HttpResponse::InternalServerError().body("Failed to create user");
+                }
+            };
 
             // Create wallet with direct type
-            let wallet: Wallet = sqlx::query_as(
-                "INSERT INTO wallet (user_id, currency, balance, wallet_type, wallet_address) VALUES ($1, $2, $3, $4, $5) RETURNING *",
-            )
-            .bind(created_user.id)
-            .bind(req.currency.unwrap_or(Currency::MON).to_string())
-            .bind(0.0)
-            .bind(WalletType::DIRECT.to_string())
-            .bind(req.wallet_address.clone().unwrap_or_else(|| "".to_string()))
-            .fetch_one(&mut *tx)
-            .await
-            .expect("Failed to create wallet");
-
-            tx.commit().await.expect("Failed to commit transaction");
+            let wallet =
+                match db::get_or_create_wallet(&mut tx, created_user.id, currency, wallet_address)
+                    .await
+                {
+                    Ok(wallet) => wallet,
+                    Err(e) => {
+                        error!("Failed to create wallet of user {}: {}", created_user.id, e);
+                        return HttpResponse::InternalServerError().body("Failed to create user");
+                    }
+                };
+
+            if let Err(e) = tx.commit().await {
+                error!("Failed to commit user {}: {}", created_user.id, e);
+                return HttpResponse::InternalServerError().body("Failed to create user");
+            }
+
+            let session_token = match auth::issue_session_token(created_user.id) {
+                Ok(token) => token,
+                Err(e) => {
+                    error!("Failed to issue session token: {}", e);
+                    return HttpResponse::InternalServerError().body("Failed to issue session");
+                }
+            };
 
             let user_details_response = UserDetailsResponse {
                 id: created_user.id,
@@ -110,8 +204,9 @@ async fn fetch_or_create_user(
                 balance: wallet.balance,
                 privy_id: created_user.privy_id,
                 wallet_address: wallet.wallet_address,
-                currency: Some(req.currency.unwrap_or(Currency::MON)),
+                currency: Some(currency),
                 gif_ids: vec![],
+                session_token,
             };
 
             HttpResponse::Created().json(user_details_response)
@@ -119,34 +214,58 @@ async fn fetch_or_create_user(
     }
 }
 
+// Only the session's own user can be updated
 #[actix_web::post("/user-details/{user_id}")]
 async fn update_user_details(
+    http_req: HttpRequest,
     path: web::Path<i32>,
     req: web::Json<UpdateUserDetailsRequest>,
     app_state: web::Data<AppState>,
 ) -> impl Responder {
     let user_id = path.into_inner();
-    let AppState { pool } = &**app_state;
+    let AppState { pool, .. } = &**app_state;
+    let Some(claims) =
+        bearer_token(&http_req).and_then(|token| auth::verify_session_token(token).ok())
+    else {
+        return HttpResponse::Unauthorized().body("Invalid session");
+    };
+    if claims.user_id != user_id {
+        return HttpResponse::Forbidden().body("Can't update another user");
+    }
 
-    let mut tx = pool.begin().await.expect("Failed to start transaction");
+    let Ok(mut tx) = pool.begin().await else {
+        return HttpResponse::InternalServerError().body("Failed to update user details");
+    };
 
-    let existing_user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+    let existing_user: Option<User> = match sqlx::query_as("SELECT * FROM users WHERE id = $1")
         .bind(user_id)
         .fetch_optional(&mut *tx)
         .await
-        .expect("Error fetching user");
+    {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Failed to fetch user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().body("Failed to update user details");
+        }
+    };
 
     match existing_user {
         Some(user) => {
-            sqlx::query("UPDATE users SET name = $1, email = $2 WHERE id = $3")
+            let updated = sqlx::query("UPDATE users SET name = $1, email = $2 WHERE id = $3")
                 .bind(req.name.clone().unwrap_or(user.name))
                 .bind(req.email.clone().unwrap_or(user.email))
                 .bind(user_id)
                 .execute(&mut *tx)
-                .await
-                .expect("Error updating user details");
+                .await;
+            if let Err(e) = updated {
+                error!("Failed to update details of user {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().body("Failed to update user details");
+            }
 
-            tx.commit().await.expect("Failed to commit transaction");
+            if let Err(e) = tx.commit().await {
+                error!("Failed to commit details of user {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().body("Failed to update user details");
+            }
 
             HttpResponse::Ok().body("User details updated successfully")
         }
@@ -160,30 +279,44 @@ async fn get_user_stats(
     app_state: web::Data<AppState>,
 ) -> impl Responder {
     let (user_id, currency) = path.into_inner();
-    let AppState { pool } = &**app_state;
+    let AppState { pool, .. } = &**app_state;
 
-    let stats: UserNetworkPnl =
-        sqlx::query_as("SELECT * FROM user_network_pnl WHERE user_id = $1 AND currency = $2")
+    let stats: Option<UserNetworkPnl> =
+        match sqlx::query_as("SELECT * FROM user_network_pnl WHERE user_id = $1 AND currency = $2")
             .bind(user_id)
             .bind(currency)
-            .fetch_one(pool)
+            .fetch_optional(pool)
             .await
-            .expect("Error fetching user stats");
-
-    HttpResponse::Ok().json(stats)
+        {
+            Ok(stats) => stats,
+            Err(e) => {
+                error!("Failed to fetch stats of user {}: {}", user_id, e);
+                return HttpResponse::InternalServerError().body("Failed to fetch user stats");
+            }
+        };
+
+    match stats {
+        Some(stats) => HttpResponse::Ok().json(stats),
+        None => HttpResponse::NotFound().body("User stats not found"),
+    }
 }
 
 #[actix_web::get("/game_pnl/{user_id}")]
 async fn get_game_pnl(path: Path<i32>, app_state: web::Data<AppState>) -> impl Responder {
     let user_id = path.into_inner();
-    let AppState { pool } = &**app_state;
+    let AppState { pool, .. } = &**app_state;
 
-    let game_pnls: Vec<GamePnl> = sqlx::query_as("SELECT * FROM game_pnl where user_id = $1")
+    match sqlx::query_as::<_, GamePnl>("SELECT * FROM game_pnl where user_id = $1")
         .bind(user_id)
         .fetch_all(pool)
         .await
-        .expect("Failed to fetch game pnl of user");
-    HttpResponse::Ok().json(game_pnls)
+    {
+        Ok(game_pnls) => HttpResponse::Ok().json(game_pnls),
+        Err(e) => {
+            error!("Failed to fetch game pnl of user {}: {}", user_id, e);
+            HttpResponse::InternalServerError().body("Failed to fetch game pnl")
+        }
+    }
 }
 
 #[actix_web::get("/leaderboard/{currency}/{timeframe}")]
@@ -192,19 +325,25 @@ async fn get_leaderboard(
     app_state: web::Data<AppState>,
 ) -> impl Responder {
     let (currency, timeframe) = path.into_inner();
-    let AppState { pool } = &**app_state;
+    let AppState { pool, .. } = &**app_state;
+    // Every currency is its own leaderboard
+    let Ok(currency) = Currency::from_str(&currency) else {
+        return HttpResponse::BadRequest().body("Invalid currency");
+    };
 
-    let leaders: Vec<LeaderboardEntry> = match timeframe.as_str() {
-        "24h" => db::get_leaderboard_24h(pool, &currency, 100)
-            .await
-            .expect("Failed to fetch leaderboard"),
-        "all" => db::get_leaderboard_all_time(pool, &currency, 100)
-            .await
-            .expect("Failed to fetch leaderboard"),
+    let leaders = match timeframe.as_str() {
+        "24h" => db::get_leaderboard_24h(pool, currency, 100).await,
+        "all" => db::get_leaderboard_all_time(pool, currency, 100).await,
         _ => return HttpResponse::BadRequest().body("Invalid timeframe"),
     };
 
-    HttpResponse::Ok().json(leaders)
+    match leaders {
+        Ok(leaders) => HttpResponse::Ok().json(leaders),
+        Err(e) => {
+            error!("Failed to fetch leaderboard: {}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch leaderboard")
+        }
+    }
 }
 
 #[actix_web::get("/health")]
@@ -218,55 +357,146 @@ async fn deposit(
     deposit_request: web::Json<DepositRequest>,
     app_state: web::Data<AppState>,
 ) -> impl Responder {
-    let AppState { pool } = &**app_state;
+    let AppState { pool, chains } = &**app_state;
     let deposit_request = deposit_request.into_inner();
     info!("Deposit request arrived");
     info!("Deposit request: {:?}", deposit_request);
 
-    let mut tx = pool.begin().await.expect("Failed to start transaction");
+    let Ok(mut tx) = pool.begin().await else {
+        return HttpResponse::InternalServerError().body("Failed to credit deposit");
+    };
 
-    let wallet: Wallet =
-        sqlx::query_as("SELECT * FROM wallet WHERE user_id = $1 AND currency = $2")
+    let wallet: Option<Wallet> =
+        match sqlx::query_as("SELECT * FROM wallet WHERE user_id = $1 AND currency = $2")
             .bind(deposit_request.user_id)
             .bind(deposit_request.currency.to_string())
-            .fetch_one(&mut *tx)
+            .fetch_optional(&mut *tx)
             .await
-            .expect("Error fetching wallet");
+        {
+            Ok(wallet) => wallet,
+            Err(e) => {
+                error!(
+                    "Failed to fetch wallet of user {}: {}",
+                    deposit_request.user_id, e
+                );
+                return HttpResponse::InternalServerError().body("Failed to credit deposit");
+            }
+        };
+    let Some(wallet) = wallet else {
+        return HttpResponse::NotFound().body("Wallet not found");
+    };
 
-    let new_balance = deposit_request.amount + wallet.balance;
+    if deposit_request.amount <= Amount::ZERO {
+        return HttpResponse::BadRequest().body("Deposit amount must be positive");
+    }
+    // The same on-chain transfer can only be credited once
+    let already_recorded: Result<Option<(i32,)>, _> =
+        sqlx::query_as("SELECT id FROM transactions WHERE tx_hash = $1")
+            .bind(&deposit_request.tx_hash)
+            .fetch_optional(&mut *tx)
+            .await;
+    match already_recorded {
+        Ok(None) => {}
+        Ok(Some(_)) => return HttpResponse::Conflict().body("Transaction already recorded"),
+        Err(e) => {
+            error!(
+                "Failed to fetch transaction {}: {}",
+                deposit_request.tx_hash, e
+            );
+            return HttpResponse::InternalServerError().body("Failed to credit deposit");
+        }
+    }
 
-    sqlx::query(
-        "UPDATE wallet SET balance = $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3",
-    )
-    .bind(new_balance)
-    .bind(deposit_request.user_id)
-    .bind(deposit_request.currency.to_string())
-    .execute(&mut *tx)
-    .await
-    .expect("Error updating wallet balance");
+    let sender = match wallet.wallet_address.as_deref() {
+        Some(sender) if !sender.is_empty() => sender,
+        _ => return HttpResponse::BadRequest().body("No wallet address registered"),
+    };
+    // Addresses registered before ownership was checked may be shared, nobody can
+    // tell whose deposit a transfer from one is
+    match db::find_wallet_owner(&mut tx, deposit_request.currency, sender).await {
+        Ok(Some(owner)) if owner == deposit_request.user_id => {}
+        Ok(_) => {
+            return HttpResponse::Conflict().body("Wallet address is registered to several users")
+        }
+        Err(e) => {
+            error!("Failed to find the wallet's owner: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to verify deposit");
+        }
+    }
+    let Some(chain) = chains.get(deposit_request.currency) else {
+        return HttpResponse::BadRequest().body("Invalid currency");
+    };
+    let verified = match chain
+        .verify_incoming_transfer(&deposit_request.tx_hash, sender)
+        .await
+    {
+        Ok(verified) => verified,
+        Err(e) => {
+            error!("Deposit verification failed: {}", e);
+            return HttpResponse::BadRequest().body(format!("Deposit verification failed: {}", e));
+        }
+    };
+    if Amount::from_chain_units(verified, deposit_request.currency, chain.decimals())
+        != Some(deposit_request.amount)
+    {
+        return HttpResponse::BadRequest().body("Deposit amount doesn't match the transaction");
+    }
 
-    // Record the transaction
-    sqlx::query(
-        "INSERT INTO transactions (user_id, amount, currency, tx_type, tx_hash) 
-         VALUES ($1, $2, $3, $4, $5)",
+    if wallet.balance.checked_add(deposit_request.amount).is_none() {
+        return HttpResponse::BadRequest().body("Deposit amount is too large");
+    }
+
+    // The deposit watcher may have credited it in the meantime
+    match db::credit_deposit(
+        &mut tx,
+        deposit_request.user_id,
+        deposit_request.currency,
+        deposit_request.amount,
+        &deposit_request.tx_hash,
     )
-    .bind(deposit_request.user_id)
-    .bind(deposit_request.amount)
-    .bind(deposit_request.currency.to_string())
-    .bind(TxType::DEPOSIT.to_string())
-    .bind(&deposit_request.tx_hash)
-    .execute(&mut *tx)
     .await
-    .expect("Error recording transaction");
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Conflict().body("Transaction already recorded"),
+        Err(e) => {
+            error!(
+                "Failed to credit deposit {}: {}",
+                deposit_request.tx_hash, e
+            );
+            return HttpResponse::InternalServerError().body("Failed to credit deposit");
+        }
+    }
+    let new_balance: Result<(Amount,), _> =
+        sqlx::query_as("SELECT balance FROM wallet WHERE user_id = $1 AND currency = $2")
+            .bind(deposit_request.user_id)
+            .bind(deposit_request.currency.to_string())
+            .fetch_one(&mut *tx)
+            .await;
+    let new_balance = match new_balance {
+        Ok((new_balance,)) => new_balance,
+        Err(e) => {
+            error!(
+                "Failed to fetch wallet of user {}: {}",
+                deposit_request.user_id, e
+            );
+            return HttpResponse::InternalServerError().body("Failed to credit deposit");
+        }
+    };
 
-    tx.commit().await.expect("Failed to commit transaction");
+    if let Err(e) = tx.commit().await {
+        error!(
+            "Failed to commit deposit {}: {}",
+            deposit_request.tx_hash, e
+        );
+        return HttpResponse::InternalServerError().body("Failed to credit deposit");
+    }
 
     if env::var("TESTING").unwrap_or_else(|_| "false".to_string()) == "false" {
         // Send Telegram notification about the deposit
         let message = format!(
             "💰 New Deposit!\nUser ID: {}\nAmount: {} {:?}\nTransaction Hash: {}",
             deposit_request.user_id,
-            deposit_request.amount,
+            deposit_request.amount.to_decimal(deposit_request.currency),
             deposit_request.currency,
             deposit_request.tx_hash
         );
@@ -284,152 +514,233 @@ async fn deposit(
     }))
 }
 
+/// Queues a withdrawal from the session user's account and reserves its amount, the
+/// withdrawal worker sends it
 #[actix_web::post("/withdraw")]
 async fn withdraw(
+    req: HttpRequest,
     withdraw_req: web::Json<WithdrawRequest>,
     app_state: web::Data<AppState>,
 ) -> impl Responder {
-    let AppState { pool } = &**app_state;
+    let AppState { pool, chains } = &**app_state;
+    let Some(claims) = bearer_token(&req).and_then(|token| auth::verify_session_token(token).ok())
+    else {
+        return HttpResponse::Unauthorized().body("Invalid session");
+    };
     let withdraw_req = withdraw_req.into_inner();
-    info!("Attempting to withdraw");
-
-    let mut tx = pool.begin().await.expect("Failed to start transaction");
-
-    let wallet: Wallet =
-        sqlx::query_as("SELECT * FROM wallet WHERE user_id = $1 AND currency = $2")
-            .bind(withdraw_req.user_id)
-            .bind(withdraw_req.currency.to_string())
-            .fetch_one(&mut *tx)
-            .await
-            .expect("Error fetching wallet");
+    info!("Attempting to withdraw for user {}", claims.user_id);
 
-    if withdraw_req.amount > wallet.balance {
-        return HttpResponse::BadRequest().body("Insufficient balance");
+    if withdraw_req.amount <= Amount::ZERO {
+        return HttpResponse::BadRequest().body("Withdrawal amount must be positive");
+    }
+    let Some(chain) = chains.get(withdraw_req.currency) else {
+        return HttpResponse::BadRequest().body("Invalid currency");
+    };
+    if !chain.validate_address(&withdraw_req.withdraw_address) {
+        return HttpResponse::BadRequest().body("Invalid withdrawal address");
+    }
+    if withdraw_req
+        .amount
+        .to_chain_units(withdraw_req.currency, chain.decimals())
+        .is_none()
+    {
+        return HttpResponse::BadRequest().body("Invalid withdrawal amount");
     }
 
-    let tx_hash = match withdraw_req.currency {
-        Currency::MON => transfer_funds(&withdraw_req.withdraw_address, withdraw_req.amount)
-            .await
-            .map_err(|e| {
-                HttpResponse::InternalServerError().body(format!("Transfer failed: {}", e))
-            })
-            .unwrap(),
-        Currency::SOL => {
-            let withdraw_address_pubkey = Pubkey::from_str(&withdraw_req.withdraw_address)
-                .map_err(|_| HttpResponse::BadRequest().body("Invalid Solana address"))
-                .unwrap();
-
-            withdraw_funds_to_user(
-                withdraw_address_pubkey,
-                (withdraw_req.amount * USD_TO_LAMPORTS as f64) as u64,
-            )
-            .await
-            .map_err(|e| {
-                HttpResponse::InternalServerError().body(format!("Transfer failed: {}", e))
-            })
-            .unwrap()
-        }
-        _ => {
-            return HttpResponse::BadRequest().body("Invalid currency");
+    let limits = match WithdrawalLimits::from_env(withdraw_req.currency) {
+        Ok(limits) => limits,
+        Err(e) => {
+            error!("Invalid withdrawal limits: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to create withdrawal");
         }
     };
 
-    let new_balance = wallet.balance - withdraw_req.amount;
-
-    // Update the user's wallet balance
-    sqlx::query(
-        "UPDATE wallet SET balance = $1, updated_at = NOW() WHERE user_id = $2 AND currency = $3",
+    // Stakes of running games and other withdrawals can't be withdrawn
+    match db::create_withdrawal(
+        pool,
+        claims.user_id,
+        withdraw_req.currency,
+        withdraw_req.amount,
+        &withdraw_req.withdraw_address,
+        &limits,
     )
-    .bind(new_balance)
-    .bind(withdraw_req.user_id)
-    .bind(withdraw_req.currency.to_string())
-    .execute(&mut *tx)
     .await
-    .expect("Error updating wallet balance");
+    {
+        Ok(WithdrawalDecision::Created(withdrawal)) => {
+            if withdrawal.status == WithdrawalStatus::REVIEW.to_string()
+                && env::var("TESTING").unwrap_or_else(|_| "false".to_string()) == "false"
+            {
+                let message = format!(
+                    "🔎 Withdrawal waiting for review!\nWithdrawal ID: {}\nUser ID: {}\nAmount: {} {:?}\nReason: {}",
+                    withdrawal.id,
+                    withdrawal.user_id,
+                    withdrawal.amount.to_decimal(withdraw_req.currency),
+                    withdraw_req.currency,
+                    withdrawal.review_reason.as_deref().unwrap_or_default()
+                );
+                if let Err(e) = telegram::send_telegram_message(&message).await {
+                    error!("Failed to send Telegram notification: {}", e);
+                }
+            }
+            HttpResponse::Accepted().json(withdrawal)
+        }
+        Ok(WithdrawalDecision::InsufficientBalance) => {
+            HttpResponse::BadRequest().body("Insufficient balance")
+        }
+        Ok(WithdrawalDecision::Rejected(reason)) => HttpResponse::Forbidden().body(reason),
+        Err(e) => {
+            error!("Failed to create withdrawal: {}", e);
+            HttpResponse::InternalServerError().body("Failed to create withdrawal")
+        }
+    }
+}
 
-    // Record the transaction
-    sqlx::query(
-        "INSERT INTO transactions (user_id, amount, currency, tx_type, tx_hash) 
-         VALUES ($1, $2, $3, $4, $5)",
-    )
-    .bind(withdraw_req.user_id)
-    .bind(withdraw_req.amount)
-    .bind(withdraw_req.currency.to_string())
-    .bind(TxType::WITHDRAWAL.to_string())
-    .bind(&tx_hash)
-    .execute(&mut *tx)
-    .await
-    .expect("Error recording transaction");
+// Other users' withdrawals look like they don't exist
+#[actix_web::get("/withdrawal/{id}")]
+async fn get_withdrawal(
+    req: HttpRequest,
+    path: Path<i32>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let AppState { pool, chains } = &**app_state;
+    let Some(claims) = bearer_token(&req).and_then(|token| auth::verify_session_token(token).ok())
+    else {
+        return HttpResponse::Unauthorized().body("Invalid session");
+    };
+    let withdrawal = match db::get_withdrawal(pool, path.into_inner()).await {
+        Ok(Some(withdrawal)) if withdrawal.user_id == claims.user_id => withdrawal,
+        Ok(_) => return HttpResponse::NotFound().body("Withdrawal not found"),
+        Err(e) => {
+            error!("Failed to fetch withdrawal: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch withdrawal");
+        }
+    };
 
-    tx.commit().await.expect("Failed to commit transaction");
+    // Progress of a transfer that was sent but isn't settled yet
+    let mut confirmations = None;
+    if let (Some(tx_hash), Ok(currency)) = (
+        &withdrawal.tx_hash,
+        Currency::from_str(&withdrawal.currency),
+    ) {
+        if let Some(chain) = chains.get(currency) {
+            confirmations = chain.confirmations(tx_hash).await.unwrap_or_else(|e| {
+                error!("Failed to fetch confirmations of {}: {}", tx_hash, e);
+                None
+            });
+        }
+    }
 
     HttpResponse::Ok().json(json!({
-        "user_id": withdraw_req.user_id,
-        "currency": withdraw_req.currency,
-        "balance": new_balance,
-        "tx_hash": tx_hash,
-        "withdraw_address": withdraw_req.withdraw_address
+        "withdrawal": withdrawal,
+        "confirmations": confirmations
     }))
 }
 
+// The mint is paid by a transfer from the session user's registered wallet to the
+// network's mint payment address, it's only booked once the chain shows it
 #[actix_web::post("/mint-nft")]
 async fn mint_nft(
-    req: web::Json<MintNftRequest>,
+    req: HttpRequest,
+    mint_req: web::Json<MintNftRequest>,
     app_state: web::Data<AppState>,
 ) -> impl Responder {
-    let AppState { pool } = &**app_state;
-    let req = req.into_inner();
+    let AppState { pool, chains } = &**app_state;
+    let Some(claims) = bearer_token(&req).and_then(|token| auth::verify_session_token(token).ok())
+    else {
+        return HttpResponse::Unauthorized().body("Invalid session");
+    };
+    let user_id = claims.user_id;
+    let req = mint_req.into_inner();
     info!("Mint NFT request arrived");
     info!("Mint NFT request: {:?}", req);
 
-    let mut tx = pool.begin().await.expect("Failed to start transaction");
-
     // Validate gif_id is between 0-15
     if req.gif_id < 0 || req.gif_id > 15 {
         return HttpResponse::BadRequest().body("Invalid gif_id. Must be between 0 and 15");
     }
+    if req.mint_amount <= Amount::ZERO {
+        return HttpResponse::BadRequest().body("Mint amount must be positive");
+    }
+    let Some(chain) = chains.get(req.currency) else {
+        return HttpResponse::BadRequest().body("Invalid currency");
+    };
+
+    let Ok(mut tx) = pool.begin().await else {
+        return HttpResponse::InternalServerError().body("Failed to record the mint");
+    };
 
     // Check if user exists
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = $1")
-        .bind(req.user_id)
+    let user: Option<User> = match sqlx::query_as("SELECT * FROM users WHERE id = $1")
+        .bind(user_id)
         .fetch_optional(&mut *tx)
         .await
-        .expect("Error fetching user");
-
+    {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Failed to fetch user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().body("Failed to record the mint");
+        }
+    };
     if user.is_none() {
         return HttpResponse::NotFound().body("User not found");
     }
 
-    // Add gif_id to user's gif_ids array if not already present
-    sqlx::query(
-        "UPDATE users SET gif_ids = array_append(gif_ids, $1) WHERE id = $2 AND NOT ($1 = ANY(gif_ids))",
-    )
-    .bind(req.gif_id)
-    .bind(req.user_id)
-    .execute(&mut *tx)
-    .await
-    .expect("Error updating user gif_ids");
+    let sender = match db::get_user_wallet(&mut tx, user_id, req.currency).await {
+        Ok(wallet) => wallet.wallet_address.filter(|address| !address.is_empty()),
+        Err(e) => {
+            error!("Failed to fetch wallet of user {}: {}", user_id, e);
+            None
+        }
+    };
+    let Some(sender) = sender else {
+        return HttpResponse::BadRequest().body("No wallet address registered");
+    };
+    let paid = match chain.verify_mint_payment(&req.tx_hash, &sender).await {
+        Ok(paid) => paid,
+        Err(e) => {
+            error!("Mint verification failed: {}", e);
+            return HttpResponse::BadRequest().body(format!("Mint verification failed: {}", e));
+        }
+    };
+    if Amount::from_chain_units(paid, req.currency, chain.decimals()) != Some(req.mint_amount) {
+        return HttpResponse::BadRequest().body("Mint amount doesn't match the transaction");
+    }
 
-    // Record the transaction
-    sqlx::query(
-        "INSERT INTO transactions (user_id, amount, currency, tx_type, tx_hash) 
-         VALUES ($1, $2, $3, $4, $5)",
+    // A transfer that was already recorded, as a mint or a deposit, can't pay again
+    match db::record_mint(
+        &mut tx,
+        user_id,
+        req.gif_id,
+        req.currency,
+        req.mint_amount,
+        &req.tx_hash,
     )
-    .bind(req.user_id)
-    .bind(req.gif_id)
-    .bind(req.currency.to_string())
-    .bind(TxType::MINT.to_string())
-    .bind(&req.tx_hash)
-    .execute(&mut *tx)
     .await
-    .expect("Error recording transaction");
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("Mint {} was already recorded", req.tx_hash);
+            return HttpResponse::Conflict().body("Transaction already recorded");
+        }
+        Err(e) => {
+            error!("Failed to book mint {}: {}", req.tx_hash, e);
+            return HttpResponse::InternalServerError().body("Failed to record the mint");
+        }
+    }
 
-    tx.commit().await.expect("Failed to commit transaction");
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit mint {}: {}", req.tx_hash, e);
+        return HttpResponse::InternalServerError().body("Failed to record the mint");
+    }
 
     // Send Telegram notification about the NFT mint
     let message = format!(
         "🎨 New NFT Mint!\nUser ID: {}\nGIF ID: {}\nAmount: {} {:?}\nTransaction Hash: {}",
-        req.user_id, req.gif_id, req.mint_amount, req.currency, req.tx_hash
+        user_id,
+        req.gif_id,
+        req.mint_amount.to_decimal(req.currency),
+        req.currency,
+        req.tx_hash
     );
 
     if let Err(e) = telegram::send_telegram_message(&message).await {
@@ -437,15 +748,211 @@ async fn mint_nft(
     }
 
     HttpResponse::Ok().json(json!({
-        "user_id": req.user_id,
+        "user_id": user_id,
         "gif_id": req.gif_id,
         "currency": req.currency,
         "tx_hash": req.tx_hash
     }))
 }
 
+// Admin endpoints must send ADMIN_API_KEY in the X-Admin-Key header
+fn is_admin(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("X-Admin-Key")
+        .and_then(|key| key.to_str().ok())
+        .is_some_and(|key| auth::verify_admin_key(key).is_ok())
+}
+
+#[actix_web::get("/admin/withdrawals/review")]
+async fn get_withdrawals_in_review(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().body("Invalid admin key");
+    }
+    let AppState { pool, .. } = &**app_state;
+    match db::get_withdrawals_by_status(pool, WithdrawalStatus::REVIEW).await {
+        Ok(withdrawals) => HttpResponse::Ok().json(withdrawals),
+        Err(e) => {
+            error!("Failed to fetch withdrawals in review: {}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch withdrawals")
+        }
+    }
+}
+
+#[actix_web::post("/admin/withdrawals/{id}/approve")]
+async fn approve_withdrawal(
+    req: HttpRequest,
+    path: Path<i32>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().body("Invalid admin key");
+    }
+    let AppState { pool, .. } = &**app_state;
+    let id = path.into_inner();
+    match db::approve_withdrawal(pool, id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({ "id": id, "status": "PENDING" })),
+        Ok(false) => HttpResponse::Conflict().body("Withdrawal is not waiting for review"),
+        Err(e) => {
+            error!("Failed to approve withdrawal {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to approve withdrawal")
+        }
+    }
+}
+
+#[actix_web::post("/admin/withdrawals/{id}/reject")]
+async fn reject_withdrawal(
+    req: HttpRequest,
+    path: Path<i32>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().body("Invalid admin key");
+    }
+    let AppState { pool, .. } = &**app_state;
+    let id = path.into_inner();
+    // Only from REVIEW, an approved withdrawal may already be getting signed
+    match db::reject_withdrawal(pool, id, "Rejected by an admin").await {
+        Ok(true) => HttpResponse::Ok().json(json!({ "id": id, "status": "FAILED" })),
+        Ok(false) => HttpResponse::Conflict().body("Withdrawal is not waiting for review"),
+        Err(e) => {
+            error!("Failed to reject withdrawal {}: {}", id, e);
+            HttpResponse::InternalServerError().body("Failed to reject withdrawal")
+        }
+    }
+}
+
+#[actix_web::get("/proof-of-reserves/{currency}")]
+async fn get_proof_of_reserves(
+    path: Path<String>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let AppState { pool, .. } = &**app_state;
+    let Ok(currency) = Currency::from_str(&path.into_inner()) else {
+        return HttpResponse::BadRequest().body("Invalid currency");
+    };
+    match db::get_latest_reserve_report(pool, currency).await {
+        Ok(Some(report)) => HttpResponse::Ok().json(report),
+        Ok(None) => HttpResponse::NotFound().body("No proof of reserves published yet"),
+        Err(e) => {
+            error!("Failed to fetch proof of reserves: {}", e);
+            HttpResponse::InternalServerError().body("Failed to fetch proof of reserves")
+        }
+    }
+}
+
+// Only the user themselves may fetch their leaf, it holds their balance and salt
+#[actix_web::get("/proof-of-reserves/{currency}/proof")]
+async fn get_reserve_inclusion_proof(
+    req: HttpRequest,
+    path: Path<String>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let AppState { pool, .. } = &**app_state;
+    let claims = bearer_token(&req).and_then(|token| auth::verify_session_token(token).ok());
+    let Some(claims) = claims else {
+        return HttpResponse::Unauthorized().body("Invalid session");
+    };
+    let Ok(currency) = Currency::from_str(&path.into_inner()) else {
+        return HttpResponse::BadRequest().body("Invalid currency");
+    };
+
+    let report = match db::get_latest_reserve_report(pool, currency).await {
+        Ok(Some(report)) => report,
+        Ok(None) => return HttpResponse::NotFound().body("No proof of reserves published yet"),
+        Err(e) => {
+            error!("Failed to fetch proof of reserves: {}", e);
+            return HttpResponse::InternalServerError().body("Failed to fetch proof of reserves");
+        }
+    };
+    match solvency::inclusion_proof(pool, &report, claims.user_id).await {
+        Ok(Some(proof)) => HttpResponse::Ok().json(proof),
+        Ok(None) => HttpResponse::NotFound().body("No balance in this proof of reserves"),
+        Err(e) => {
+            error!("Failed to build inclusion proof: {}", e);
+            HttpResponse::InternalServerError().body("Failed to build inclusion proof")
+        }
+    }
+}
+
+#[actix_web::get("/admin/solvency")]
+async fn get_solvency(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().body("Invalid admin key");
+    }
+    let AppState { pool, chains } = &**app_state;
+    match solvency::check_solvency(pool, chains).await {
+        Ok(coverage) => HttpResponse::Ok().json(coverage),
+        Err(e) => {
+            error!("Failed to check solvency: {}", e);
+            HttpResponse::InternalServerError().body("Failed to check solvency")
+        }
+    }
+}
+
+#[actix_web::get("/admin/ledger")]
+async fn verify_ledger(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().body("Invalid admin key");
+    }
+    let AppState { pool, .. } = &**app_state;
+    match db::verify_ledger(pool).await {
+        Ok(drift) => HttpResponse::Ok().json(json!({ "drift": drift })),
+        Err(e) => {
+            error!("Failed to verify the ledger: {}", e);
+            HttpResponse::InternalServerError().body("Failed to verify the ledger")
+        }
+    }
+}
+
+#[actix_web::post("/admin/proof-of-reserves")]
+async fn publish_proof_of_reserves(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().body("Invalid admin key");
+    }
+    let AppState { pool, chains } = &**app_state;
+    let published = match solvency::report_signer() {
+        Ok(signer) => solvency::publish_proof_of_reserves(pool, chains, &signer).await,
+        Err(e) => Err(e),
+    };
+    match published {
+        Ok(reports) => HttpResponse::Ok().json(reports),
+        Err(e) => {
+            error!("Failed to publish proof of reserves: {}", e);
+            HttpResponse::InternalServerError().body("Failed to publish proof of reserves")
+        }
+    }
+}
+
+// `browser-wallet solvency`, `browser-wallet proof-of-reserves` and
+// `browser-wallet verify-ledger` print their result and exit instead of starting
+// the service
+async fn run_command(
+    command: &str,
+    pool: &Pool<Postgres>,
+    chains: &ChainRegistry,
+) -> anyhow::Result<()> {
+    let output = match command {
+        "solvency" => json!(solvency::check_solvency(pool, chains).await?),
+        "proof-of-reserves" => {
+            let signer = solvency::report_signer()?;
+            json!(solvency::publish_proof_of_reserves(pool, chains, &signer).await?)
+        }
+        "verify-ledger" => json!(db::verify_ledger(pool).await?),
+        _ => return Err(anyhow::anyhow!("Unknown command {}", command)),
+    };
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
 struct AppState {
     pool: Pool<Postgres>,
+    chains: ChainRegistry,
 }
 
 #[actix_web::main]
@@ -459,7 +966,30 @@ async fn main() -> std::io::Result<()> {
 
     info!("Starting the wallet service");
     let pool = establish_connection().await;
-    let app_state = web::Data::new(AppState { pool });
+    let chains = ChainRegistry::from_env()
+        .await
+        .expect("Failed to set up chains");
+    if let Some(command) = env::args().nth(1) {
+        if let Err(e) = run_command(&command, &pool, &chains).await {
+            error!("{} failed: {}", command, e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    actix_web::rt::spawn(withdrawals::run_withdrawal_worker(
+        pool.clone(),
+        chains.clone(),
+    ));
+    for (currency, chain) in chains.evm_adapters() {
+        actix_web::rt::spawn(deposit_watcher::run_deposit_watcher(
+            pool.clone(),
+            *currency,
+            chain.clone(),
+        ));
+    }
+    actix_web::rt::spawn(solvency::run_solvency_monitor(pool.clone(), chains.clone()));
+    let app_state = web::Data::new(AppState { pool, chains });
 
     info!("Starting HTTP server on 0.0.0.0:8080");
     HttpServer::new(move || {
@@ -470,6 +1000,15 @@ async fn main() -> std::io::Result<()> {
             .service(health_check)
             .service(deposit)
             .service(withdraw)
+            .service(get_withdrawal)
+            .service(get_withdrawals_in_review)
+            .service(approve_withdrawal)
+            .service(reject_withdrawal)
+            .service(get_solvency)
+            .service(verify_ledger)
+            .service(publish_proof_of_reserves)
+            .service(get_proof_of_reserves)
+            .service(get_reserve_inclusion_proof)
             .service(fetch_or_create_user)
             .service(get_user_stats)
             .service(get_leaderboard)
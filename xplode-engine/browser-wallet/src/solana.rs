@@ -1,55 +1,410 @@
-use std::env;
+use std::{env, str::FromStr};
 
-use solana_client::nonblocking::rpc_client::RpcClient;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use common::utils::Network;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    instruction::{AccountMeta, CompiledInstruction, Instruction},
+    program_utils::limited_deserialize,
+    pubkey,
     pubkey::Pubkey,
-    signature::{read_keypair_file, Signer},
-    system_instruction,
+    signature::{read_keypair_file, Keypair, Signature, Signer},
+    system_instruction::{self, SystemInstruction},
+    system_program,
     transaction::Transaction,
 };
+use solana_transaction_status_client_types::{TransactionStatus, UiTransactionEncoding};
 
-pub async fn withdraw_funds_to_user(
-    recipient_address: Pubkey,
-    amount_in_lamports: u64,
-) -> anyhow::Result<String> {
-    // Configure the client to use the Solana devnet or mainnet
-    let rpc_url = env::var("SOLANA_RPC_URL").unwrap(); // or "https://api.devnet.solana.com" for devnet
-    let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
-
-    let working_dir = env::current_dir().unwrap();
-    let keypair_path = working_dir.join("treasury-keypair.json");
-    // println!("Keypair path: {}", keypair_path.display());
-    // // Load your keypair from file
-    let sender_keypair = read_keypair_file(keypair_path).expect("Failed to load keypair");
-
-    // Get the recent blockhash
-    let recent_blockhash = client
-        .get_latest_blockhash()
-        .await
-        .expect("Failed to get recent blockhash");
-
-    // Create a transfer instruction
-    let instruction = system_instruction::transfer(
-        &sender_keypair.pubkey(),
-        &recipient_address,
-        amount_in_lamports,
-    );
-
-    // Create a transaction
-    let transaction = Transaction::new_signed_with_payer(
-        &[instruction],
-        Some(&sender_keypair.pubkey()),
-        &[&sender_keypair],
-        recent_blockhash,
-    );
-
-    // // Send and confirm transaction
-    // match client.send_and_confirm_transaction(&transaction) {
-    //     Ok(signature) => Ok(signature.to_string()),
-    //     Err(err) => Err(anyhow::anyhow!("Transaction failed: {}", err)),
-    // }
-
-    let tx_hash = client.send_and_confirm_transaction(&transaction).await?;
-    Ok(tx_hash.to_string())
+use crate::chain::{
+    min_confirmations, mint_payment_address, ChainAdapter, SignedTransfer, TransferStatus,
+};
+
+// Confirmations of a rooted slot, which the RPC no longer counts
+const ROOTED_CONFIRMATIONS: u64 = 32;
+
+const ASSOCIATED_TOKEN_PROGRAM: Pubkey = pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+// SPL token instruction tags, the same for Token and Token-2022
+const TOKEN_TRANSFER: u8 = 3;
+const TOKEN_TRANSFER_CHECKED: u8 = 12;
+// Offset of `decimals` in a mint account, after the mint authority and the supply
+const MINT_DECIMALS_OFFSET: usize = 44;
+
+/// An SPL token mint and the token program that owns it
+struct SplToken {
+    mint: Pubkey,
+    program: Pubkey,
+    decimals: u8,
+}
+
+impl SplToken {
+    fn associated_account(&self, owner: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[owner.as_ref(), self.program.as_ref(), self.mint.as_ref()],
+            &ASSOCIATED_TOKEN_PROGRAM,
+        )
+        .0
+    }
+
+    // The transfer amount if `instruction` moves this token from `sender` to `recipient`
+    fn deposited(
+        &self,
+        keys: &[Pubkey],
+        instruction: &CompiledInstruction,
+        sender: &Pubkey,
+        recipient: &Pubkey,
+    ) -> Option<u64> {
+        if keys.get(instruction.program_id_index as usize) != Some(&self.program) {
+            return None;
+        }
+        let account = |i: usize| {
+            instruction
+                .accounts
+                .get(i)
+                .and_then(|&key| keys.get(key as usize))
+        };
+        let (&tag, data) = instruction.data.split_first()?;
+        let amount = u64::from_le_bytes(data.get(..8)?.try_into().ok()?);
+        let (to, authority) = match tag {
+            TOKEN_TRANSFER => (account(1)?, account(2)?),
+            TOKEN_TRANSFER_CHECKED if account(1)? == &self.mint => (account(2)?, account(3)?),
+            _ => return None,
+        };
+        (to == &self.associated_account(recipient) && authority == sender).then_some(amount)
+    }
+}
+
+/// Moves SOL, or an SPL token when built with `with_token`
+pub struct SolanaAdapter {
+    client: RpcClient,
+    treasury: Keypair,
+    min_confirmations: u64,
+    token: Option<SplToken>,
+    mint_payment_address: Option<Pubkey>,
+}
+
+impl SolanaAdapter {
+    /// Reads SOLANA_RPC_URL and the treasury keypair, from SOLANA_TREASURY_KEYPAIR
+    /// or treasury-keypair.json in the working directory
+    pub fn from_env() -> anyhow::Result<Self> {
+        let rpc_url = env::var("SOLANA_RPC_URL")?;
+        let keypair_path = match env::var("SOLANA_TREASURY_KEYPAIR") {
+            Ok(path) => path.into(),
+            Err(_) => env::current_dir()?.join("treasury-keypair.json"),
+        };
+        let treasury = read_keypair_file(&keypair_path)
+            .map_err(|e| anyhow!("Failed to load {}: {}", keypair_path.display(), e))?;
+        let mint_payment_address = mint_payment_address(&Network::SOLANA)
+            .map(|address| Pubkey::from_str(&address))
+            .transpose()?;
+        if mint_payment_address == Some(treasury.pubkey()) {
+            return Err(anyhow!(
+                "SOLANA_MINT_PAYMENT_ADDRESS must be an address other than the treasury"
+            ));
+        }
+
+        Ok(SolanaAdapter {
+            client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed()),
+            treasury,
+            min_confirmations: min_confirmations(&Network::SOLANA, ROOTED_CONFIRMATIONS),
+            token: None,
+            mint_payment_address,
+        })
+    }
+
+    /// Moves the token of `mint` instead of SOL, its decimals are read from the mint
+    pub async fn with_token(mut self, mint: &str) -> anyhow::Result<Self> {
+        let mint = Pubkey::from_str(mint)?;
+        let account = self.client.get_account(&mint).await?;
+        let decimals = *account
+            .data
+            .get(MINT_DECIMALS_OFFSET)
+            .ok_or_else(|| anyhow!("{} is not a token mint", mint))?;
+        self.token = Some(SplToken {
+            mint,
+            program: account.owner,
+            decimals,
+        });
+        Ok(self)
+    }
+
+    fn transfer_instructions(&self, recipient: &Pubkey, amount: u64) -> Vec<Instruction> {
+        let treasury = self.treasury.pubkey();
+        let Some(token) = &self.token else {
+            return vec![system_instruction::transfer(&treasury, recipient, amount)];
+        };
+
+        let recipient_account = token.associated_account(recipient);
+        // CreateIdempotent, a no-op when the recipient already has the account
+        let create_account = Instruction::new_with_bytes(
+            ASSOCIATED_TOKEN_PROGRAM,
+            &[1],
+            vec![
+                AccountMeta::new(treasury, true),
+                AccountMeta::new(recipient_account, false),
+                AccountMeta::new_readonly(*recipient, false),
+                AccountMeta::new_readonly(token.mint, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(token.program, false),
+            ],
+        );
+        let mut data = vec![TOKEN_TRANSFER_CHECKED];
+        data.extend_from_slice(&amount.to_le_bytes());
+        data.push(token.decimals);
+        let transfer = Instruction::new_with_bytes(
+            token.program,
+            &data,
+            vec![
+                AccountMeta::new(token.associated_account(&treasury), false),
+                AccountMeta::new_readonly(token.mint, false),
+                AccountMeta::new(recipient_account, false),
+                AccountMeta::new_readonly(treasury, true),
+            ],
+        );
+        vec![create_account, transfer]
+    }
+
+    // The lamports `instruction` moves from `sender` to `recipient`, if any
+    fn deposited_lamports(
+        &self,
+        keys: &[Pubkey],
+        instruction: &CompiledInstruction,
+        sender: &Pubkey,
+        recipient: &Pubkey,
+    ) -> Option<u64> {
+        if keys.get(instruction.program_id_index as usize) != Some(&system_program::id()) {
+            return None;
+        }
+        let Ok(SystemInstruction::Transfer { lamports }) = limited_deserialize(&instruction.data)
+        else {
+            return None;
+        };
+        let from = instruction
+            .accounts
+            .first()
+            .and_then(|&i| keys.get(i as usize));
+        let to = instruction
+            .accounts
+            .get(1)
+            .and_then(|&i| keys.get(i as usize));
+        (from == Some(sender) && to == Some(recipient)).then_some(lamports)
+    }
+
+    // Checks that `tx_hash` is a successful transfer from `sender` to `recipient` with
+    // enough confirmations, and returns how much it moved
+    async fn verify_transfer(
+        &self,
+        tx_hash: &str,
+        sender: &str,
+        recipient: &Pubkey,
+    ) -> anyhow::Result<u128> {
+        let sender = Pubkey::from_str(sender)?;
+        let signature = Signature::from_str(tx_hash)?;
+
+        let status = self
+            .signature_status(&signature)
+            .await?
+            .ok_or_else(|| anyhow!("Transaction {} not found", signature))?;
+        if let Some(err) = status.err {
+            return Err(anyhow!("Transaction {} failed: {}", signature, err));
+        }
+        if confirmations(&status) < self.min_confirmations {
+            return Err(anyhow!(
+                "Transaction {} has {} of {} confirmations",
+                signature,
+                confirmations(&status),
+                self.min_confirmations
+            ));
+        }
+
+        let transaction = self
+            .client
+            .get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?
+            .transaction
+            .transaction
+            .decode()
+            .ok_or_else(|| anyhow!("Could not decode transaction {}", signature))?;
+
+        // Sums the transfers from the sender to the recipient
+        let keys = transaction.message.static_account_keys();
+        let mut deposited = 0_u64;
+        for instruction in transaction.message.instructions() {
+            let amount = match &self.token {
+                Some(token) => token.deposited(keys, instruction, &sender, recipient),
+                None => self.deposited_lamports(keys, instruction, &sender, recipient),
+            };
+            if let Some(amount) = amount {
+                deposited = deposited
+                    .checked_add(amount)
+                    .ok_or_else(|| anyhow!("Transaction {} overflows", signature))?;
+            }
+        }
+
+        if deposited == 0 {
+            return Err(anyhow!(
+                "Transaction {} has no transfer from {} to {}",
+                signature,
+                sender,
+                recipient
+            ));
+        }
+        Ok(u128::from(deposited))
+    }
+
+    async fn signature_status(
+        &self,
+        signature: &Signature,
+    ) -> anyhow::Result<Option<TransactionStatus>> {
+        Ok(self
+            .client
+            .get_signature_statuses_with_history(&[*signature])
+            .await?
+            .value
+            .remove(0))
+    }
+}
+
+// No confirmation count means the slot is already rooted
+fn confirmations(status: &TransactionStatus) -> u64 {
+    status
+        .confirmations
+        .map_or(ROOTED_CONFIRMATIONS, |confirmations| confirmations as u64)
+}
+
+#[async_trait]
+impl ChainAdapter for SolanaAdapter {
+    fn network(&self) -> Network {
+        Network::SOLANA
+    }
+
+    fn decimals(&self) -> u32 {
+        self.token
+            .as_ref()
+            .map_or(9, |token| u32::from(token.decimals))
+    }
+
+    fn validate_address(&self, address: &str) -> bool {
+        match Pubkey::from_str(address) {
+            // Token accounts are derived from a wallet, an address off the curve is
+            // likely a token account itself and tokens sent to its own account are lost
+            Ok(address) => self.token.is_none() || address.is_on_curve(),
+            Err(_) => false,
+        }
+    }
+
+    // Wallets sign the message bytes as they are with the address's ed25519 key
+    fn verify_address_owner(&self, address: &str, message: &str, signature: &str) -> bool {
+        match (Pubkey::from_str(address), Signature::from_str(signature)) {
+            (Ok(address), Ok(signature)) => signature.verify(address.as_ref(), message.as_bytes()),
+            _ => false,
+        }
+    }
+
+    async fn sign_transfer(
+        &self,
+        to_address: &str,
+        amount: u128,
+    ) -> anyhow::Result<SignedTransfer> {
+        let recipient = Pubkey::from_str(to_address)?;
+        let amount = u64::try_from(amount)?;
+        let (recent_blockhash, last_valid_block_height) = self
+            .client
+            .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+            .await?;
+
+        let transaction = Transaction::new_signed_with_payer(
+            &self.transfer_instructions(&recipient, amount),
+            Some(&self.treasury.pubkey()),
+            &[&self.treasury],
+            recent_blockhash,
+        );
+
+        Ok(SignedTransfer {
+            tx_hash: transaction.signatures[0].to_string(),
+            raw: hex::encode(bincode::serialize(&transaction)?),
+            valid_until: last_valid_block_height,
+        })
+    }
+
+    async fn broadcast(&self, raw: &str) -> anyhow::Result<()> {
+        let transaction: Transaction = bincode::deserialize(&hex::decode(raw)?)?;
+        self.client.send_transaction(&transaction).await?;
+        Ok(())
+    }
+
+    async fn verify_incoming_transfer(&self, tx_hash: &str, sender: &str) -> anyhow::Result<u128> {
+        self.verify_transfer(tx_hash, sender, &self.treasury.pubkey())
+            .await
+    }
+
+    async fn verify_mint_payment(&self, tx_hash: &str, sender: &str) -> anyhow::Result<u128> {
+        let recipient = self
+            .mint_payment_address
+            .ok_or_else(|| anyhow!("Mints are not paid on {}", Network::SOLANA))?;
+        self.verify_transfer(tx_hash, sender, &recipient).await
+    }
+
+    async fn confirmations(&self, tx_hash: &str) -> anyhow::Result<Option<u64>> {
+        let status = self
+            .signature_status(&Signature::from_str(tx_hash)?)
+            .await?;
+        Ok(status.as_ref().map(confirmations))
+    }
+
+    async fn treasury_balance(&self) -> anyhow::Result<u128> {
+        let treasury = self.treasury.pubkey();
+        let Some(token) = &self.token else {
+            return Ok(u128::from(self.client.get_balance(&treasury).await?));
+        };
+        // The treasury's token account only exists once it has received the token
+        let account = token.associated_account(&treasury);
+        if self
+            .client
+            .get_account_with_commitment(&account, CommitmentConfig::confirmed())
+            .await?
+            .value
+            .is_none()
+        {
+            return Ok(0);
+        }
+        let balance = self.client.get_token_account_balance(&account).await?;
+        Ok(balance.amount.parse()?)
+    }
+
+    async fn transfer_status(
+        &self,
+        tx_hash: &str,
+        valid_until: u64,
+    ) -> anyhow::Result<TransferStatus> {
+        let signature = Signature::from_str(tx_hash)?;
+
+        // Read before the status, a transfer landing in between is still found below
+        let finalized_height = self
+            .client
+            .get_block_height_with_commitment(CommitmentConfig::finalized())
+            .await?;
+
+        Ok(match self.signature_status(&signature).await? {
+            None if finalized_height > valid_until => {
+                TransferStatus::Failed("Transfer expired before landing".to_string())
+            }
+            None => TransferStatus::Pending,
+            Some(status) if confirmations(&status) < self.min_confirmations => {
+                TransferStatus::Pending
+            }
+            Some(status) => match status.err {
+                Some(err) => TransferStatus::Failed(format!("Transfer failed: {}", err)),
+                None => TransferStatus::Confirmed,
+            },
+        })
+    }
 }
@@ -0,0 +1,237 @@
+use std::{env, time::Duration};
+
+use anyhow::anyhow;
+use common::{
+    db,
+    models::ReserveReport,
+    reserves::{self, MerkleSumTree, ReserveLeaf, SignedReport},
+    telegram,
+    utils::{Amount, Currency},
+};
+use rand::RngCore;
+use serde::Serialize;
+use serde_json::json;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use sqlx::{Pool, Postgres};
+use tracing::{error, info};
+
+use crate::chain::ChainRegistry;
+
+/// What the treasury holds of a currency against what the users hold
+#[derive(Debug, Serialize)]
+pub struct Coverage {
+    pub currency: Currency,
+    pub liabilities: Amount,
+    pub reserves: Amount,
+}
+
+impl Coverage {
+    /// Reserves as a percentage of liabilities, None when nothing is owed
+    pub fn percent(&self) -> Option<f64> {
+        (self.liabilities > Amount::ZERO)
+            .then(|| self.reserves.units() as f64 * 100.0 / self.liabilities.units() as f64)
+    }
+
+    pub fn is_covered(&self, min_percent: i64) -> bool {
+        i128::from(self.reserves.units()) * 100
+            >= i128::from(self.liabilities.units()) * i128::from(min_percent)
+    }
+}
+
+fn min_coverage_percent() -> i64 {
+    env::var("SOLVENCY_MIN_COVERAGE_PCT")
+        .ok()
+        .and_then(|percent| percent.parse().ok())
+        .unwrap_or(100)
+}
+
+async fn treasury_reserves(chains: &ChainRegistry, currency: Currency) -> anyhow::Result<Amount> {
+    let chain = chains
+        .get(currency)
+        .ok_or_else(|| anyhow!("{} has no chain", currency))?;
+    let units = chain.treasury_balance().await?;
+    Amount::from_chain_units(units, currency, chain.decimals())
+        .ok_or_else(|| anyhow!("{} treasury balance {} doesn't fit", currency, units))
+}
+
+/// Compares every registered currency's treasury balance with the users' balances
+pub async fn check_solvency(
+    pool: &Pool<Postgres>,
+    chains: &ChainRegistry,
+) -> anyhow::Result<Vec<Coverage>> {
+    let mut coverage = Vec::new();
+    for (currency, _) in chains.iter() {
+        coverage.push(Coverage {
+            currency,
+            liabilities: db::get_liabilities(pool, currency).await?,
+            reserves: treasury_reserves(chains, currency).await?,
+        });
+    }
+    coverage.sort_by_key(|coverage| coverage.currency.to_string());
+    Ok(coverage)
+}
+
+/// Sends `message` to Telegram, and to SOLVENCY_WEBHOOK_URL when it's set
+async fn alert(message: &str) {
+    error!("{}", message);
+    if env::var("TESTING").unwrap_or_else(|_| "false".to_string()) == "false" {
+        if let Err(e) = telegram::send_telegram_message(message).await {
+            error!("Failed to send Telegram notification: {}", e);
+        }
+    }
+    if let Ok(url) = env::var("SOLVENCY_WEBHOOK_URL") {
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&json!({ "text": message }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = response {
+            error!("Failed to call the solvency webhook: {}", e);
+        }
+    }
+}
+
+/// Checks solvency every SOLVENCY_CHECK_SECS and alerts for each currency whose
+/// reserves are below SOLVENCY_MIN_COVERAGE_PCT of its liabilities, and when the
+/// cached balances drift from the ledger
+pub async fn run_solvency_monitor(pool: Pool<Postgres>, chains: ChainRegistry) {
+    let min_percent = min_coverage_percent();
+    let poll_interval = Duration::from_secs(
+        env::var("SOLVENCY_CHECK_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(300),
+    );
+    info!("Monitoring solvency, alerting below {}%", min_percent);
+
+    loop {
+        match check_solvency(&pool, &chains).await {
+            Ok(coverage) => {
+                for coverage in coverage.iter().filter(|c| !c.is_covered(min_percent)) {
+                    alert(&format!(
+                        "🚨 {} treasury holds {} against {} of user balances ({:.2}%)",
+                        coverage.currency,
+                        coverage.reserves.to_decimal(coverage.currency),
+                        coverage.liabilities.to_decimal(coverage.currency),
+                        coverage.percent().unwrap_or_default()
+                    ))
+                    .await;
+                }
+            }
+            Err(e) => error!("Failed to check solvency: {}", e),
+        }
+        match db::verify_ledger(&pool).await {
+            Ok(drift) if !drift.is_empty() => {
+                alert(&format!(
+                    "🚨 Ledger drift in {} places, first: {:?}",
+                    drift.len(),
+                    drift[0]
+                ))
+                .await;
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to verify the ledger: {}", e),
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// Loads the key reports are signed with, from PROOF_OF_RESERVES_KEYPAIR
+pub fn report_signer() -> anyhow::Result<Keypair> {
+    let path = env::var("PROOF_OF_RESERVES_KEYPAIR")
+        .map_err(|_| anyhow!("PROOF_OF_RESERVES_KEYPAIR is not set"))?;
+    read_keypair_file(&path).map_err(|e| anyhow!("Failed to load {}: {}", path, e))
+}
+
+fn random_salt() -> String {
+    let mut salt = [0_u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    hex::encode(salt)
+}
+
+/// Publishes a signed Merkle-sum proof of reserves for every registered currency
+pub async fn publish_proof_of_reserves(
+    pool: &Pool<Postgres>,
+    chains: &ChainRegistry,
+    signer: &Keypair,
+) -> anyhow::Result<Vec<ReserveReport>> {
+    let mut published = Vec::new();
+    for (currency, _) in chains.iter() {
+        let leaves: Vec<ReserveLeaf> = db::get_wallet_balances(pool, currency)
+            .await?
+            .into_iter()
+            .map(|(user_id, balance)| ReserveLeaf {
+                user_id,
+                balance,
+                salt: random_salt(),
+            })
+            .collect();
+        let tree = MerkleSumTree::build(&leaves)?;
+        let reserves = treasury_reserves(chains, currency).await?;
+        let created_at = chrono::Utc::now();
+
+        let message = reserves::report_message(currency, tree.root(), reserves, created_at);
+        let report = SignedReport {
+            currency,
+            root: tree.root().clone(),
+            reserves,
+            created_at,
+            signer: signer.pubkey().to_string(),
+            signature: signer.sign_message(message.as_bytes()).to_string(),
+        };
+        let stored = db::insert_reserve_report(pool, &report, &leaves).await?;
+        info!(
+            "Published proof of reserves {} for {}: {} against {}",
+            stored.id,
+            currency,
+            reserves.to_decimal(currency),
+            report.root.sum.to_decimal(currency)
+        );
+        published.push(stored);
+    }
+    Ok(published)
+}
+
+/// The user's leaf in the report and the path from it to the root
+pub async fn inclusion_proof(
+    pool: &Pool<Postgres>,
+    report: &ReserveReport,
+    user_id: i32,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let leaves = db::get_reserve_report_leaves(pool, report.id).await?;
+    let Some(index) = leaves.iter().position(|leaf| leaf.user_id == user_id) else {
+        return Ok(None);
+    };
+    let tree = MerkleSumTree::build(&leaves)?;
+    Ok(Some(json!({
+        "report_id": report.id,
+        "leaf": leaves[index],
+        "proof": tree.proof(index),
+        "root": tree.root(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_is_compared_without_rounding() {
+        let coverage = Coverage {
+            currency: Currency::SOL,
+            liabilities: Amount::from_units(1_000_000_001),
+            reserves: Amount::from_units(1_000_000_000),
+        };
+        assert!(!coverage.is_covered(100));
+        assert!(coverage.is_covered(99));
+
+        let nothing_owed = Coverage {
+            liabilities: Amount::ZERO,
+            reserves: Amount::ZERO,
+            ..coverage
+        };
+        assert!(nothing_owed.is_covered(100));
+        assert_eq!(nothing_owed.percent(), None);
+    }
+}
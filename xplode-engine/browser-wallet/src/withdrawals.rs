@@ -0,0 +1,124 @@
+use std::{env, str::FromStr, time::Duration};
+
+use anyhow::anyhow;
+use common::{
+    db,
+    models::Withdrawal,
+    utils::{Currency, WithdrawalStatus},
+};
+use sqlx::{Pool, Postgres};
+use tracing::{error, info};
+
+use crate::chain::{ChainRegistry, TransferStatus};
+
+/// Moves withdrawals along PENDING -> BROADCAST -> CONFIRMED or FAILED. Every step is
+/// a conditional status update, so rerunning one after a crash can't pay twice.
+pub async fn run_withdrawal_worker(pool: Pool<Postgres>, chains: ChainRegistry) {
+    let poll_interval = Duration::from_secs(
+        env::var("WITHDRAWAL_POLL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(5),
+    );
+    info!("Starting the withdrawal worker");
+
+    loop {
+        if let Err(e) = process_withdrawals(&pool, &chains).await {
+            error!("Failed to process withdrawals: {}", e);
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+async fn process_withdrawals(pool: &Pool<Postgres>, chains: &ChainRegistry) -> anyhow::Result<()> {
+    for withdrawal in db::get_withdrawals_by_status(pool, WithdrawalStatus::PENDING).await? {
+        if let Err(e) = submit(pool, chains, &withdrawal).await {
+            error!("Failed to submit withdrawal {}: {}", withdrawal.id, e);
+        }
+    }
+    for withdrawal in db::get_withdrawals_by_status(pool, WithdrawalStatus::BROADCAST).await? {
+        if let Err(e) = reconcile(pool, chains, &withdrawal).await {
+            error!("Failed to reconcile withdrawal {}: {}", withdrawal.id, e);
+        }
+    }
+    Ok(())
+}
+
+// Signs the transfer and stores it before sending, so it can always be followed up
+async fn submit(
+    pool: &Pool<Postgres>,
+    chains: &ChainRegistry,
+    withdrawal: &Withdrawal,
+) -> anyhow::Result<()> {
+    let currency = Currency::from_str(&withdrawal.currency)?;
+    let Some(chain) = chains.get(currency) else {
+        db::fail_withdrawal(pool, withdrawal.id, "Unsupported currency").await?;
+        return Ok(());
+    };
+    let Some(chain_amount) = withdrawal.amount.to_chain_units(currency, chain.decimals()) else {
+        db::fail_withdrawal(pool, withdrawal.id, "Invalid amount").await?;
+        return Ok(());
+    };
+    if !chain.validate_address(&withdrawal.withdraw_address) {
+        db::fail_withdrawal(pool, withdrawal.id, "Invalid withdrawal address").await?;
+        return Ok(());
+    }
+
+    let signed = chain
+        .sign_transfer(&withdrawal.withdraw_address, chain_amount)
+        .await?;
+
+    // Another worker got here first
+    if !db::mark_withdrawal_broadcast(
+        pool,
+        withdrawal.id,
+        &signed.tx_hash,
+        &signed.raw,
+        i64::try_from(signed.valid_until)?,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+    info!(
+        "Broadcasting withdrawal {} as {}",
+        withdrawal.id, signed.tx_hash
+    );
+    chain.broadcast(&signed.raw).await
+}
+
+async fn reconcile(
+    pool: &Pool<Postgres>,
+    chains: &ChainRegistry,
+    withdrawal: &Withdrawal,
+) -> anyhow::Result<()> {
+    let currency = Currency::from_str(&withdrawal.currency)?;
+    let chain = chains
+        .get(currency)
+        .ok_or_else(|| anyhow!("No chain is registered for {}", currency))?;
+    let (Some(tx_hash), Some(raw), Some(valid_until)) = (
+        &withdrawal.tx_hash,
+        &withdrawal.signed_tx,
+        withdrawal.valid_until,
+    ) else {
+        return Err(anyhow!(
+            "Withdrawal {} has no signed transfer",
+            withdrawal.id
+        ));
+    };
+
+    match chain.transfer_status(tx_hash, valid_until as u64).await? {
+        TransferStatus::Confirmed => {
+            db::confirm_withdrawal(pool, withdrawal.id).await?;
+        }
+        TransferStatus::Failed(reason) => {
+            db::fail_withdrawal(pool, withdrawal.id, &reason).await?;
+        }
+        TransferStatus::Pending => {
+            if let Err(e) = chain.broadcast(raw).await {
+                info!("Resending withdrawal {}: {}", withdrawal.id, e);
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,19 @@
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgPool, Pool, Postgres};
-use std::env;
+use std::{collections::BTreeMap, env, str::FromStr};
 use tracing::info;
 
 use crate::{
-    models::{LeaderboardEntry, Wallet},
-    utils::Currency,
+    models::{
+        ChainCursor, DepositAddress, LeaderboardEntry, PendingEscrowAction, ReserveReport, Wallet,
+        Withdrawal,
+    },
+    reserves::{ReserveLeaf, SignedReport},
+    utils::{
+        Amount, Currency, JournalKind, LedgerAccount, Payout, TxType, WalletType, WithdrawalLimits,
+        WithdrawalStatus,
+    },
 };
 
 pub async fn establish_connection() -> Pool<Postgres> {
@@ -17,37 +25,551 @@ pub async fn establish_connection() -> Pool<Postgres> {
 }
 
 pub async fn get_user_wallet(
-    pool: &Pool<Postgres>,
+    tx: &mut sqlx::Transaction<'_, Postgres>,
     user_id: i32,
     currency: Currency,
 ) -> Result<Wallet> {
     sqlx::query_as::<_, Wallet>("SELECT * FROM wallet WHERE user_id = $1 AND currency = $2")
         .bind(user_id)
         .bind(currency.to_string())
-        .fetch_one(pool)
+        .fetch_one(&mut **tx)
         .await
         .map_err(Error::from)
 }
 
-pub async fn update_user_wallet(
-    pool: &Pool<Postgres>,
+// Wallets of currencies added after the user signed up are created on first use
+pub async fn get_or_create_wallet(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
     user_id: i32,
     currency: Currency,
-    new_balance: f64,
-) -> Result<()> {
-    info!("Updating user wallet: {}", user_id);
+    wallet_address: Option<&str>,
+) -> Result<Wallet> {
     sqlx::query(
-        "UPDATE wallet SET balance = $1, updated_at = CURRENT_TIMESTAMP 
-         WHERE user_id = $2 AND currency = $3",
+        "INSERT INTO wallet (user_id, currency, balance, wallet_type, wallet_address)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (user_id, currency) DO NOTHING",
     )
-    .bind(new_balance)
     .bind(user_id)
     .bind(currency.to_string())
-    .execute(pool)
+    .bind(Amount::ZERO)
+    .bind(WalletType::DIRECT.to_string())
+    .bind(wallet_address.unwrap_or_default())
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query_as::<_, Wallet>("SELECT * FROM wallet WHERE user_id = $1 AND currency = $2")
+        .bind(user_id)
+        .bind(currency.to_string())
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(Error::from)
+}
+
+/// One side of a journal entry, `amount` is added to the account
+#[derive(Debug, Clone, Copy)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    // Set for USER accounts only
+    pub user_id: Option<i32>,
+    pub amount: Amount,
+}
+
+impl Posting {
+    pub fn user(user_id: i32, amount: Amount) -> Self {
+        Posting {
+            account: LedgerAccount::USER,
+            user_id: Some(user_id),
+            amount,
+        }
+    }
+
+    pub fn to(account: LedgerAccount, amount: Amount) -> Self {
+        Posting {
+            account,
+            user_id: None,
+            amount,
+        }
+    }
+}
+
+/// Records a balanced journal entry and applies its USER postings to the cached
+/// `wallet.balance`, which nothing else may change. Returns false when an entry of
+/// this kind and reference was already posted.
+pub async fn post_entry(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    kind: JournalKind,
+    reference: &str,
+    currency: Currency,
+    postings: &[Posting],
+) -> Result<bool> {
+    let mut total = Amount::ZERO;
+    for posting in postings {
+        if (posting.account == LedgerAccount::USER) != posting.user_id.is_some() {
+            return Err(anyhow!("Only USER postings belong to a user"));
+        }
+        total = total
+            .checked_add(posting.amount)
+            .ok_or_else(|| anyhow!("{} entry {} overflows", kind, reference))?;
+    }
+    if total != Amount::ZERO {
+        return Err(anyhow!("{} entry {} is unbalanced", kind, reference));
+    }
+
+    let entry_id: Option<(i64,)> = sqlx::query_as(
+        "INSERT INTO journal_entries (kind, reference, currency) VALUES ($1, $2, $3)
+         ON CONFLICT (kind, reference) DO NOTHING
+         RETURNING id",
+    )
+    .bind(kind.to_string())
+    .bind(reference)
+    .bind(currency.to_string())
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some((entry_id,)) = entry_id else {
+        return Ok(false);
+    };
+
+    for posting in postings {
+        sqlx::query(
+            "INSERT INTO postings (entry_id, account, user_id, amount) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(entry_id)
+        .bind(posting.account.to_string())
+        .bind(posting.user_id)
+        .bind(posting.amount)
+        .execute(&mut **tx)
+        .await?;
+
+        let Some(user_id) = posting.user_id else {
+            continue;
+        };
+        let balance: Option<(Amount,)> = sqlx::query_as(
+            "UPDATE wallet SET balance = balance + $1, updated_at = CURRENT_TIMESTAMP
+             WHERE user_id = $2 AND currency = $3
+             RETURNING balance",
+        )
+        .bind(posting.amount)
+        .bind(user_id)
+        .bind(currency.to_string())
+        .fetch_optional(&mut **tx)
+        .await?;
+        match balance {
+            None => return Err(anyhow!("User {} has no {} wallet", user_id, currency)),
+            Some((balance,)) if balance.is_negative() => {
+                return Err(anyhow!(
+                    "{} entry {} would leave user {} with a negative balance",
+                    kind,
+                    reference,
+                    user_id
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(true)
+}
+
+/// Where the ledger disagrees with itself
+#[derive(Debug, PartialEq, Serialize)]
+pub enum LedgerDrift {
+    // The entry's postings don't sum to zero
+    UnbalancedEntry {
+        entry_id: i64,
+        currency: String,
+        sum: Amount,
+    },
+    // The cached balance isn't the sum of the user's postings
+    WalletDrift {
+        user_id: i32,
+        currency: String,
+        cached: Amount,
+        journal: Amount,
+    },
+}
+
+/// Recomputes every balance from the journal and lists where it drifted
+pub async fn verify_ledger(pool: &Pool<Postgres>) -> Result<Vec<LedgerDrift>> {
+    let unbalanced: Vec<(i64, String, Amount)> = sqlx::query_as(
+        "SELECT e.id, e.currency, COALESCE(SUM(p.amount), 0)::BIGINT
+         FROM journal_entries e
+         LEFT JOIN postings p ON p.entry_id = e.id
+         GROUP BY e.id
+         HAVING COALESCE(SUM(p.amount), 0) <> 0
+         ORDER BY e.id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let drifted: Vec<(i32, String, Amount, Amount)> = sqlx::query_as(
+        "SELECT w.user_id, w.currency, w.balance, COALESCE(j.total, 0)::BIGINT
+         FROM wallet w
+         LEFT JOIN (
+             SELECT p.user_id, e.currency, SUM(p.amount) AS total
+             FROM postings p
+             JOIN journal_entries e ON e.id = p.entry_id
+             WHERE p.account = $1
+             GROUP BY p.user_id, e.currency
+         ) j ON j.user_id = w.user_id AND j.currency = w.currency
+         WHERE w.balance <> COALESCE(j.total, 0)
+         ORDER BY w.user_id, w.currency",
+    )
+    .bind(LedgerAccount::USER.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(unbalanced
+        .into_iter()
+        .map(|(entry_id, currency, sum)| LedgerDrift::UnbalancedEntry {
+            entry_id,
+            currency,
+            sum,
+        })
+        .chain(
+            drifted
+                .into_iter()
+                .map(|(user_id, currency, cached, journal)| LedgerDrift::WalletDrift {
+                    user_id,
+                    currency,
+                    cached,
+                    journal,
+                }),
+        )
+        .collect())
+}
+
+/// Credits a deposit identified by its on-chain `tx_hash`, at most once. Returns
+/// false when it was already credited.
+pub async fn credit_deposit(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: i32,
+    currency: Currency,
+    amount: Amount,
+    tx_hash: &str,
+) -> Result<bool> {
+    let recorded = sqlx::query(
+        "INSERT INTO transactions (user_id, amount, currency, tx_type, tx_hash)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (tx_hash) DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(amount)
+    .bind(currency.to_string())
+    .bind(TxType::DEPOSIT.to_string())
+    .bind(tx_hash)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if recorded == 0 {
+        return Ok(false);
+    }
+
+    let external = amount
+        .checked_neg()
+        .ok_or_else(|| anyhow!("Deposit {} overflows", tx_hash))?;
+    post_entry(
+        tx,
+        JournalKind::DEPOSIT,
+        tx_hash,
+        currency,
+        &[
+            Posting::user(user_id, amount),
+            Posting::to(LedgerAccount::EXTERNAL, external),
+        ],
+    )
+    .await
+}
+
+/// Books an NFT mint the user paid on chain as revenue and adds the gif to the
+/// user's, at most once per `tx_hash`. Returns false when the transfer was already
+/// recorded, as a mint or credited as a deposit, it can't pay for both.
+pub async fn record_mint(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: i32,
+    gif_id: i32,
+    currency: Currency,
+    amount: Amount,
+    tx_hash: &str,
+) -> Result<bool> {
+    let recorded = sqlx::query(
+        "INSERT INTO transactions (user_id, amount, currency, tx_type, tx_hash)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (tx_hash) DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(amount)
+    .bind(currency.to_string())
+    .bind(TxType::MINT.to_string())
+    .bind(tx_hash)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if recorded == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "UPDATE users SET gif_ids = array_append(gif_ids, $1)
+         WHERE id = $2 AND NOT ($1 = ANY(gif_ids))",
+    )
+    .bind(gif_id)
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+
+    let external = amount
+        .checked_neg()
+        .ok_or_else(|| anyhow!("Mint {} overflows", tx_hash))?;
+    post_entry(
+        tx,
+        JournalKind::MINT,
+        tx_hash,
+        currency,
+        &[
+            Posting::to(LedgerAccount::SALES, amount),
+            Posting::to(LedgerAccount::EXTERNAL, external),
+        ],
+    )
+    .await
+}
+
+pub async fn get_deposit_addresses(pool: &Pool<Postgres>) -> Result<Vec<DepositAddress>> {
+    sqlx::query_as::<_, DepositAddress>("SELECT * FROM deposit_addresses ORDER BY user_id")
+        .fetch_all(pool)
+        .await
+        .map_err(Error::from)
+}
+
+/// Registers a PDA deposit address, returns false if it already was
+pub async fn insert_deposit_address(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    user_pda: &str,
+    seed: &str,
+) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let inserted = sqlx::query(
+        "INSERT INTO deposit_addresses (user_pda, user_id, seed) VALUES ($1, $2, $3)
+         ON CONFLICT (user_pda) DO NOTHING",
+    )
+    .bind(user_pda)
+    .bind(user_id)
+    .bind(seed)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query("UPDATE users SET user_pda = $1 WHERE id = $2")
+        .bind(user_pda)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(inserted == 1)
+}
+
+// Moves the watcher's cursor, in the transaction that credits up to `last_signature`
+pub async fn set_deposit_cursor(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_pda: &str,
+    last_signature: &str,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE deposit_addresses SET last_signature = $2, updated_at = NOW()
+         WHERE user_pda = $1",
+    )
+    .bind(user_pda)
+    .bind(last_signature)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// The user whose `currency` wallet is registered with `address`, if exactly one is.
+/// EVM addresses are compared case-insensitively.
+pub async fn find_wallet_owner(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    currency: Currency,
+    address: &str,
+) -> Result<Option<i32>> {
+    let owners: Vec<(i32,)> = sqlx::query_as(
+        "SELECT user_id FROM wallet WHERE currency = $1 AND LOWER(wallet_address) = LOWER($2)
+         LIMIT 2",
+    )
+    .bind(currency.to_string())
+    .bind(address)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(match owners[..] {
+        [(user_id,)] => Some(user_id),
+        _ => None,
+    })
+}
+
+/// Whether a user other than `user_id` registered `address` for `currency`. The
+/// address stays locked until the transaction ends, so two users can't both claim it.
+pub async fn wallet_address_taken(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Option<i32>,
+    currency: Currency,
+    address: &str,
+) -> Result<bool> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(format!("{}:{}", currency, address.to_lowercase()))
+        .execute(&mut **tx)
+        .await?;
+    let (taken,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (
+             SELECT 1 FROM wallet
+             WHERE currency = $1 AND LOWER(wallet_address) = LOWER($2)
+             AND user_id IS DISTINCT FROM $3
+         )",
+    )
+    .bind(currency.to_string())
+    .bind(address)
+    .bind(user_id)
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(taken)
+}
+
+pub async fn get_chain_cursor(pool: &Pool<Postgres>, name: &str) -> Result<Option<ChainCursor>> {
+    sqlx::query_as::<_, ChainCursor>("SELECT * FROM chain_cursors WHERE name = $1")
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::from)
+}
+
+// Moves a chain watcher's cursor, in the transaction that processes the block
+pub async fn set_chain_cursor(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    name: &str,
+    block_number: i64,
+    block_hash: &str,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO chain_cursors (name, block_number, block_hash) VALUES ($1, $2, $3)
+         ON CONFLICT (name) DO UPDATE
+         SET block_number = $2, block_hash = $3, updated_at = NOW()",
+    )
+    .bind(name)
+    .bind(block_number)
+    .bind(block_hash)
+    .execute(&mut **tx)
     .await?;
     Ok(())
 }
 
+/// What the users hold of a currency in total, with their stakes in escrow
+pub async fn get_liabilities(pool: &Pool<Postgres>, currency: Currency) -> Result<Amount> {
+    let (total,): (Amount,) = sqlx::query_as(
+        "SELECT (
+             (SELECT COALESCE(SUM(balance), 0) FROM wallet WHERE currency = $1)
+             + (SELECT COALESCE(SUM(amount), 0) FROM game_escrows
+                WHERE currency = $1 AND status = 'HELD')
+         )::BIGINT",
+    )
+    .bind(currency.to_string())
+    .fetch_one(pool)
+    .await?;
+    Ok(total)
+}
+
+/// Every user's balance of a currency with their stakes in escrow, by user id
+pub async fn get_wallet_balances(
+    pool: &Pool<Postgres>,
+    currency: Currency,
+) -> Result<Vec<(i32, Amount)>> {
+    sqlx::query_as(
+        "SELECT w.user_id, (w.balance + COALESCE(SUM(g.amount), 0))::BIGINT
+         FROM wallet w
+         LEFT JOIN game_escrows g
+             ON g.user_id = w.user_id AND g.currency = w.currency AND g.status = 'HELD'
+         WHERE w.currency = $1
+         GROUP BY w.user_id, w.balance
+         ORDER BY w.user_id",
+    )
+    .bind(currency.to_string())
+    .fetch_all(pool)
+    .await
+    .map_err(Error::from)
+}
+
+/// Stores a signed proof-of-reserves report with its leaves in tree order
+pub async fn insert_reserve_report(
+    pool: &Pool<Postgres>,
+    report: &SignedReport,
+    leaves: &[ReserveLeaf],
+) -> Result<ReserveReport> {
+    let mut tx = pool.begin().await?;
+    let stored = sqlx::query_as::<_, ReserveReport>(
+        "INSERT INTO reserve_reports
+         (currency, root_hash, liabilities, reserves, signer, signature, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING *",
+    )
+    .bind(report.currency.to_string())
+    .bind(&report.root.hash)
+    .bind(report.root.sum)
+    .bind(report.reserves)
+    .bind(&report.signer)
+    .bind(&report.signature)
+    .bind(report.created_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for (index, leaf) in leaves.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO reserve_report_leaves (report_id, leaf_index, user_id, balance, salt)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(stored.id)
+        .bind(i32::try_from(index)?)
+        .bind(leaf.user_id)
+        .bind(leaf.balance)
+        .bind(&leaf.salt)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(stored)
+}
+
+pub async fn get_latest_reserve_report(
+    pool: &Pool<Postgres>,
+    currency: Currency,
+) -> Result<Option<ReserveReport>> {
+    sqlx::query_as::<_, ReserveReport>(
+        "SELECT * FROM reserve_reports WHERE currency = $1 ORDER BY id DESC LIMIT 1",
+    )
+    .bind(currency.to_string())
+    .fetch_optional(pool)
+    .await
+    .map_err(Error::from)
+}
+
+/// The report's leaves in tree order
+pub async fn get_reserve_report_leaves(
+    pool: &Pool<Postgres>,
+    report_id: i32,
+) -> Result<Vec<ReserveLeaf>> {
+    let rows: Vec<(i32, Amount, String)> = sqlx::query_as(
+        "SELECT user_id, balance, salt FROM reserve_report_leaves
+         WHERE report_id = $1 ORDER BY leaf_index",
+    )
+    .bind(report_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(user_id, balance, salt)| ReserveLeaf {
+            user_id,
+            balance,
+            salt,
+        })
+        .collect())
+}
+
 // pub async fn create_user_and_wallet(
 //     pool: &Pool<Postgres>,
 //     user: &User,
@@ -72,60 +594,768 @@ pub async fn update_user_wallet(
 //     Ok(())
 // }
 
-pub async fn update_player_balances(
+#[derive(Debug, PartialEq)]
+pub enum Settlement {
+    Settled,
+    AlreadySettled,
+}
+
+/// Moves `amount` of the user's balance to escrow for a game round. Returns false
+/// when the available balance, i.e. what isn't held by withdrawals, doesn't cover it.
+/// Stakes must be positive, a negative one would credit the user.
+pub async fn reserve_stake(
     pool: &Pool<Postgres>,
-    user_ids: &[i32],
-    loser_idx: usize,
-    single_bet_size: f64,
-    winning_amount: f64,
+    game_id: &str,
+    round: i32,
+    user_id: i32,
+    amount: Amount,
     currency: Currency,
+) -> Result<bool> {
+    info!(
+        "Reserving {} {} for user {} in game {} round {}",
+        amount.to_decimal(currency),
+        currency, user_id, game_id, round
+    );
+    check_stake(game_id, amount)?;
+    let mut tx = pool.begin().await?;
+
+    let inserted = sqlx::query(
+        "INSERT INTO game_escrows (game_id, round, user_id, currency, amount, status)
+         VALUES ($1, $2, $3, $4, $5, 'HELD')
+         ON CONFLICT (game_id, round, user_id) DO NOTHING",
+    )
+    .bind(game_id)
+    .bind(round)
+    .bind(user_id)
+    .bind(currency.to_string())
+    .bind(amount)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        // The stake for this round is already held
+        return Ok(true);
+    }
+
+    let available: Option<(Amount,)> = sqlx::query_as(
+        "SELECT balance - reserved FROM wallet WHERE user_id = $1 AND currency = $2
+         FOR UPDATE",
+    )
+    .bind(user_id)
+    .bind(currency.to_string())
+    .fetch_optional(&mut *tx)
+    .await?;
+    if available.is_none_or(|(available,)| available < amount) {
+        info!(
+            "User {} can't cover a stake of {}",
+            user_id,
+            amount.to_decimal(currency)
+        );
+        return Ok(false);
+    }
+
+    let debit = amount
+        .checked_neg()
+        .ok_or_else(|| anyhow!("Stake of game {} overflows", game_id))?;
+    post_entry(
+        &mut tx,
+        JournalKind::STAKE,
+        &stake_reference(game_id, round, user_id),
+        currency,
+        &[
+            Posting::user(user_id, debit),
+            Posting::to(LedgerAccount::ESCROW, amount),
+        ],
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+fn check_stake(game_id: &str, amount: Amount) -> Result<()> {
+    if amount <= Amount::ZERO {
+        return Err(anyhow!(
+            "Stake of game {} must be positive, got {} units",
+            game_id,
+            amount.units()
+        ));
+    }
+    Ok(())
+}
+
+fn stake_reference(game_id: &str, round: i32, user_id: i32) -> String {
+    format!("{}:{}:{}", game_id, round, user_id)
+}
+
+/// Gives back every stake still held for a game round, e.g. once it was aborted
+pub async fn release_stakes(pool: &Pool<Postgres>, game_id: &str, round: i32) -> Result<()> {
+    info!("Releasing stakes of game {} round {}", game_id, round);
+    release_held_stakes(pool, game_id, round, None).await
+}
+
+/// Gives back one user's stake for a game round if it's still held, e.g. when the
+/// seat it was reserved for is gone by the time it's held
+pub async fn release_stake(
+    pool: &Pool<Postgres>,
+    game_id: &str,
+    round: i32,
+    user_id: i32,
+) -> Result<()> {
+    info!(
+        "Releasing stake of user {} in game {} round {}",
+        user_id, game_id, round
+    );
+    release_held_stakes(pool, game_id, round, Some(user_id)).await
+}
+
+async fn release_held_stakes(
+    pool: &Pool<Postgres>,
+    game_id: &str,
+    round: i32,
+    user_id: Option<i32>,
 ) -> Result<()> {
-    info!("Updating player balances for user_ids: {:?}", user_ids);
     let mut tx = pool.begin().await?;
-    // Default to SOLANA network if none is provided
-    let currency_str = currency.to_string();
+    let held = unhold_stakes(&mut tx, game_id, round, user_id, "RELEASED").await?;
+    for (user_id, currency, amount) in held {
+        check_stake(game_id, amount)?;
+        let credit = amount
+            .checked_neg()
+            .ok_or_else(|| anyhow!("Stake of game {} overflows", game_id))?;
+        post_entry(
+            &mut tx,
+            JournalKind::RELEASE,
+            &stake_reference(game_id, round, user_id),
+            Currency::from_str(&currency)?,
+            &[
+                Posting::to(LedgerAccount::ESCROW, credit),
+                Posting::user(user_id, amount),
+            ],
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
 
-    for (i, user_id) in user_ids.iter().enumerate() {
-        info!("Currency: {:?}, user_id: {:?}", currency_str, user_id);
-        let current_balance: f64 =
-            sqlx::query_scalar("SELECT balance FROM wallet WHERE user_id = $1 AND currency = $2")
-                .bind(user_id)
-                .bind(currency_str.clone())
-                .fetch_one(&mut *tx)
+// Flips the round's held stakes, only `user_id`'s when set, to `status` and returns
+// them. Locking the escrow rows first keeps a stake from being unheld twice.
+async fn unhold_stakes(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    game_id: &str,
+    round: i32,
+    user_id: Option<i32>,
+    status: &str,
+) -> Result<Vec<(i32, String, Amount)>> {
+    sqlx::query_as(
+        "UPDATE game_escrows SET status = $3, updated_at = CURRENT_TIMESTAMP
+         WHERE game_id = $1 AND round = $2 AND status = 'HELD'
+         AND ($4::INTEGER IS NULL OR user_id = $4)
+         RETURNING user_id, currency, amount",
+    )
+    .bind(game_id)
+    .bind(round)
+    .bind(status)
+    .bind(user_id)
+    .fetch_all(&mut **tx)
+    .await
+    .map_err(Error::from)
+}
+
+/// What's owed to the stakes held for a game round
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EscrowAction {
+    /// Pay the round out, see `settle_game`
+    Settle {
+        places: Vec<Vec<i32>>,
+        single_bet_size: Amount,
+        currency: Currency,
+        payout: Payout,
+    },
+    /// Give back the held stakes, only `user_id`'s when set
+    Release { user_id: Option<i32> },
+}
+
+/// Records that `action` is owed to a game round before it's tried, so it's retried
+/// by `resolve_pending_escrow_actions` until it goes through. Returns its id.
+pub async fn queue_escrow_action(
+    pool: &Pool<Postgres>,
+    game_id: &str,
+    round: i32,
+    action: &EscrowAction,
+) -> Result<i32> {
+    let (id,): (i32,) = sqlx::query_as(
+        "INSERT INTO pending_escrow_actions (game_id, round, action)
+         VALUES ($1, $2, $3)
+         RETURNING id",
+    )
+    .bind(game_id)
+    .bind(round)
+    .bind(serde_json::to_string(action)?)
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+/// Carries out a queued action and drops it from the queue. Settling and releasing
+/// only touch stakes that are still held, so running an action twice is harmless.
+pub async fn resolve_escrow_action(
+    pool: &Pool<Postgres>,
+    id: i32,
+    game_id: &str,
+    round: i32,
+    action: &EscrowAction,
+) -> Result<()> {
+    match action {
+        EscrowAction::Settle {
+            places,
+            single_bet_size,
+            currency,
+            payout,
+        } => {
+            settle_game(pool, game_id, round, places, *single_bet_size, *currency, *payout)
                 .await?;
-        info!("Current balance: {:?}", current_balance);
+        }
+        EscrowAction::Release { user_id: None } => release_stakes(pool, game_id, round).await?,
+        EscrowAction::Release {
+            user_id: Some(user_id),
+        } => release_stake(pool, game_id, round, *user_id).await?,
+    }
+
+    sqlx::query("DELETE FROM pending_escrow_actions WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Retries the queued actions older than `min_age`, younger ones are still being
+/// tried by whoever queued them. Returns how many went through.
+pub async fn resolve_pending_escrow_actions(
+    pool: &Pool<Postgres>,
+    min_age: std::time::Duration,
+) -> Result<usize> {
+    let pending: Vec<PendingEscrowAction> = sqlx::query_as(
+        "SELECT * FROM pending_escrow_actions
+         WHERE created_at <= CURRENT_TIMESTAMP - make_interval(secs => $1)
+         ORDER BY id",
+    )
+    .bind(min_age.as_secs_f64())
+    .fetch_all(pool)
+    .await?;
 
-        let (new_balance, profit) = if i == loser_idx {
-            (current_balance - single_bet_size, -single_bet_size)
-        } else {
-            (current_balance + winning_amount, winning_amount)
+    let mut resolved = 0;
+    for pending in pending {
+        let result = match serde_json::from_str::<EscrowAction>(&pending.action) {
+            Ok(action) => {
+                resolve_escrow_action(pool, pending.id, &pending.game_id, pending.round, &action)
+                    .await
+            }
+            Err(e) => Err(e.into()),
         };
+        match result {
+            Ok(()) => resolved += 1,
+            Err(e) => {
+                sqlx::query(
+                    "UPDATE pending_escrow_actions
+                     SET attempts = attempts + 1, last_error = $2
+                     WHERE id = $1",
+                )
+                .bind(pending.id)
+                .bind(e.to_string())
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// Pays out a finished game round exactly once. `places` groups the players' user ids
+/// by finishing place, first place first, and profits follow `payout`: ranked with
+/// `Amount::ranked_split`, where a single loser pays `single_bet_size` split evenly
+/// between the other players, or the whole pot to first place. Every balance change
+/// is kept as a leg, and stakes held in escrow for the round are paid back as part
+/// of the payouts.
+pub async fn settle_game(
+    pool: &Pool<Postgres>,
+    game_id: &str,
+    round: i32,
+    places: &[Vec<i32>],
+    single_bet_size: Amount,
+    currency: Currency,
+    payout: Payout,
+) -> Result<Settlement> {
+    info!("Settling game {} with places: {:?}", game_id, places);
+    check_stake(game_id, single_bet_size)?;
+    let sizes: Vec<usize> = places.iter().map(Vec::len).collect();
+    let profits_by_place = payout
+        .profits(single_bet_size, &sizes)
+        .ok_or_else(|| anyhow!("Cannot rank game {} with places {:?}", game_id, places))?;
+    // A player in last place on their own is the game's loser
+    let loser_id = match places {
+        [_, .., last] if last.len() == 1 => Some(last[0]),
+        _ => None,
+    };
+
+    let currency_str = currency.to_string();
+    let mut tx = pool.begin().await?;
+
+    // Claim the game first, a repeated or concurrent settlement finds it taken
+    let claimed = sqlx::query(
+        "INSERT INTO game_settlements (game_id, round, currency, loser_id, single_bet_size, payout)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (game_id, round) DO NOTHING",
+    )
+    .bind(game_id)
+    .bind(round)
+    .bind(&currency_str)
+    .bind(loser_id)
+    .bind(single_bet_size)
+    .bind(payout.to_string())
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if claimed == 0 {
+        info!("Game {} round {} is already settled", game_id, round);
+        return Ok(Settlement::AlreadySettled);
+    }
+
+    let overflow = || anyhow!("Settling game {} overflows a balance", game_id);
+
+    // Each user is paid their stake back plus what they won or lost
+    let mut payouts: BTreeMap<i32, Amount> = BTreeMap::new();
+    let mut escrowed = Amount::ZERO;
+    for (user_id, stake_currency, amount) in
+        unhold_stakes(&mut tx, game_id, round, None, "SETTLED").await?
+    {
+        if stake_currency != currency_str {
+            return Err(anyhow!("Game {} holds a {} stake", game_id, stake_currency));
+        }
+        let payout = payouts.entry(user_id).or_default();
+        *payout = payout.checked_add(amount).ok_or_else(overflow)?;
+        escrowed = escrowed.checked_add(amount).ok_or_else(overflow)?;
+    }
+
+    let mut profits = Vec::with_capacity(sizes.iter().sum());
+    let mut user_places: BTreeMap<i32, i32> = BTreeMap::new();
+    for (place, (user_ids, place_profits)) in places.iter().zip(profits_by_place).enumerate() {
+        for (user_id, profit) in user_ids.iter().zip(place_profits) {
+            let payout = payouts.entry(*user_id).or_default();
+            *payout = payout.checked_add(profit).ok_or_else(overflow)?;
+            profits.push((*user_id, profit));
+            user_places.insert(*user_id, place as i32 + 1);
+        }
+    }
+
+    // Locked in a stable order so concurrent settlements can't deadlock
+    let balances: Vec<(i32, Amount)> = sqlx::query_as(
+        "SELECT user_id, balance FROM wallet
+         WHERE user_id = ANY($1) AND currency = $2
+         ORDER BY user_id
+         FOR UPDATE",
+    )
+    .bind(payouts.keys().copied().collect::<Vec<_>>())
+    .bind(&currency_str)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut postings: Vec<Posting> = payouts
+        .iter()
+        .map(|(user_id, payout)| Posting::user(*user_id, *payout))
+        .collect();
+    postings.push(Posting::to(
+        LedgerAccount::ESCROW,
+        escrowed.checked_neg().ok_or_else(overflow)?,
+    ));
+    post_entry(
+        &mut tx,
+        JournalKind::SETTLEMENT,
+        &format!("{}:{}", game_id, round),
+        currency,
+        &postings,
+    )
+    .await?;
+
+    for (user_id, payout) in &payouts {
+        let balance = balances
+            .iter()
+            .find(|(id, _)| id == user_id)
+            .map(|(_, balance)| *balance)
+            .ok_or_else(|| anyhow!("User {} has no {} wallet", user_id, currency_str))?;
+        let new_balance = balance.checked_add(*payout).ok_or_else(overflow)?;
 
         sqlx::query(
-            "UPDATE wallet SET balance = $1, updated_at = CURRENT_TIMESTAMP 
-             WHERE user_id = $2 AND currency = $3",
+            "INSERT INTO game_settlement_legs
+             (game_id, round, user_id, currency, amount, balance_before, balance_after, place)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
         )
-        .bind(new_balance)
+        .bind(game_id)
+        .bind(round)
         .bind(user_id)
-        .bind(currency_str.clone())
+        .bind(&currency_str)
+        .bind(payout)
+        .bind(balance)
+        .bind(new_balance)
+        .bind(user_places.get(user_id))
         .execute(&mut *tx)
         .await?;
+    }
 
-        record_game_result_tx(&mut tx, *user_id, &currency_str, profit).await?;
+    for (user_id, profit) in profits {
+        record_game_result_tx(&mut tx, user_id, &currency_str, profit).await?;
     }
 
     tx.commit().await?;
-    Ok(())
+    Ok(Settlement::Settled)
+}
+
+#[derive(Debug)]
+pub enum WithdrawalDecision {
+    // PENDING, or REVIEW when it needs an admin's approval
+    Created(Withdrawal),
+    InsufficientBalance,
+    Rejected(String),
+}
+
+// Withdrawals that still hold the user's funds
+const IN_FLIGHT_WITHDRAWALS: [WithdrawalStatus; 3] = [
+    WithdrawalStatus::REVIEW,
+    WithdrawalStatus::PENDING,
+    WithdrawalStatus::BROADCAST,
+];
+
+/// Opens a withdrawal and reserves its amount, after checking it against `limits`.
+/// Large withdrawals, or ones past the treasury's daily cap, are queued for review.
+pub async fn create_withdrawal(
+    pool: &Pool<Postgres>,
+    user_id: i32,
+    currency: Currency,
+    amount: Amount,
+    withdraw_address: &str,
+    limits: &WithdrawalLimits,
+) -> Result<WithdrawalDecision> {
+    info!(
+        "Creating withdrawal of {} {} for user {}",
+        amount.to_decimal(currency),
+        currency,
+        user_id
+    );
+    let currency_str = currency.to_string();
+    let mut tx = pool.begin().await?;
+
+    // Concurrent withdrawals of a user could otherwise both fit in the daily limit
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(i64::from(user_id))
+        .execute(&mut *tx)
+        .await?;
+
+    let (recent_deposit,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (
+             SELECT 1 FROM transactions
+             WHERE user_id = $1 AND currency = $2 AND tx_type = $3
+             AND created_at > NOW() - make_interval(secs => $4)
+         )",
+    )
+    .bind(user_id)
+    .bind(&currency_str)
+    .bind(TxType::DEPOSIT.to_string())
+    .bind(limits.deposit_cooldown_secs as f64)
+    .fetch_one(&mut *tx)
+    .await?;
+    if recent_deposit {
+        return Ok(WithdrawalDecision::Rejected(
+            "Withdrawals are on hold shortly after a deposit".to_string(),
+        ));
+    }
+
+    if let Some(limit) = limits.user_daily_limit {
+        let withdrawn = withdrawn_last_24h(&mut tx, Some(user_id), &currency_str, true).await?;
+        if withdrawn.checked_add(amount).is_none_or(|total| total > limit) {
+            return Ok(WithdrawalDecision::Rejected(format!(
+                "Daily withdrawal limit of {} {} reached",
+                limit.to_decimal(currency),
+                currency
+            )));
+        }
+    }
+
+    let reserved = sqlx::query(
+        "UPDATE wallet SET reserved = reserved + $1, updated_at = CURRENT_TIMESTAMP
+         WHERE user_id = $2 AND currency = $3 AND balance - reserved >= $1",
+    )
+    .bind(amount)
+    .bind(user_id)
+    .bind(&currency_str)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if reserved == 0 {
+        return Ok(WithdrawalDecision::InsufficientBalance);
+    }
+
+    let mut review_reason = None;
+    if limits.review_threshold.is_some_and(|threshold| amount >= threshold) {
+        review_reason = Some("Amount is above the review threshold");
+    } else if let Some(cap) = limits.treasury_daily_cap {
+        let outflow = withdrawn_last_24h(&mut tx, None, &currency_str, false).await?;
+        if outflow.checked_add(amount).is_none_or(|total| total > cap) {
+            review_reason = Some("Treasury daily outflow cap reached");
+        }
+    }
+    let status = match review_reason {
+        Some(_) => WithdrawalStatus::REVIEW,
+        None => WithdrawalStatus::PENDING,
+    };
+
+    let withdrawal = sqlx::query_as::<_, Withdrawal>(
+        "INSERT INTO withdrawals (user_id, currency, amount, withdraw_address, status, review_reason)
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+    )
+    .bind(user_id)
+    .bind(&currency_str)
+    .bind(amount)
+    .bind(withdraw_address)
+    .bind(status.to_string())
+    .bind(review_reason)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(WithdrawalDecision::Created(withdrawal))
+}
+
+// Confirmed withdrawals of the last 24 hours, from `transactions`, plus the ones still
+// in flight. Only approved withdrawals count towards the treasury's outflow.
+async fn withdrawn_last_24h(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Option<i32>,
+    currency: &str,
+    include_review: bool,
+) -> Result<Amount> {
+    let in_flight: Vec<String> = IN_FLIGHT_WITHDRAWALS
+        .iter()
+        .filter(|status| include_review || **status != WithdrawalStatus::REVIEW)
+        .map(|status| status.to_string())
+        .collect();
+
+    let (confirmed,): (Amount,) = sqlx::query_as(
+        "SELECT COALESCE(SUM(amount), 0)::INT8 FROM transactions
+         WHERE ($1::INT4 IS NULL OR user_id = $1) AND currency = $2 AND tx_type = $3
+         AND created_at > NOW() - INTERVAL '24 hours'",
+    )
+    .bind(user_id)
+    .bind(currency)
+    .bind(TxType::WITHDRAWAL.to_string())
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let (pending,): (Amount,) = sqlx::query_as(
+        "SELECT COALESCE(SUM(amount), 0)::INT8 FROM withdrawals
+         WHERE ($1::INT4 IS NULL OR user_id = $1) AND currency = $2 AND status = ANY($3)",
+    )
+    .bind(user_id)
+    .bind(currency)
+    .bind(&in_flight)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    confirmed
+        .checked_add(pending)
+        .ok_or_else(|| anyhow!("Withdrawn amount overflows"))
+}
+
+pub async fn get_withdrawal(pool: &Pool<Postgres>, id: i32) -> Result<Option<Withdrawal>> {
+    sqlx::query_as::<_, Withdrawal>("SELECT * FROM withdrawals WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(Error::from)
+}
+
+pub async fn get_withdrawals_by_status(
+    pool: &Pool<Postgres>,
+    status: WithdrawalStatus,
+) -> Result<Vec<Withdrawal>> {
+    sqlx::query_as::<_, Withdrawal>("SELECT * FROM withdrawals WHERE status = $1 ORDER BY id")
+        .bind(status.to_string())
+        .fetch_all(pool)
+        .await
+        .map_err(Error::from)
+}
+
+/// Stores the signed transfer of a PENDING withdrawal. It must only be sent once this
+/// returned true, so a crash can't leave a transfer on chain that nobody tracks.
+pub async fn mark_withdrawal_broadcast(
+    pool: &Pool<Postgres>,
+    id: i32,
+    tx_hash: &str,
+    signed_tx: &str,
+    valid_until: i64,
+) -> Result<bool> {
+    let updated = sqlx::query(
+        "UPDATE withdrawals
+         SET status = $2, tx_hash = $3, signed_tx = $4, valid_until = $5,
+             updated_at = CURRENT_TIMESTAMP
+         WHERE id = $1 AND status = $6",
+    )
+    .bind(id)
+    .bind(WithdrawalStatus::BROADCAST.to_string())
+    .bind(tx_hash)
+    .bind(signed_tx)
+    .bind(valid_until)
+    .bind(WithdrawalStatus::PENDING.to_string())
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(updated == 1)
+}
+
+/// Releases a withdrawal waiting for review to the worker. Returns false if it
+/// isn't waiting for review.
+pub async fn approve_withdrawal(pool: &Pool<Postgres>, id: i32) -> Result<bool> {
+    let updated = sqlx::query(
+        "UPDATE withdrawals SET status = $2, updated_at = CURRENT_TIMESTAMP
+         WHERE id = $1 AND status = $3",
+    )
+    .bind(id)
+    .bind(WithdrawalStatus::PENDING.to_string())
+    .bind(WithdrawalStatus::REVIEW.to_string())
+    .execute(pool)
+    .await?
+    .rows_affected();
+    info!("Withdrawal {} approved: {}", id, updated == 1);
+    Ok(updated == 1)
+}
+
+/// Debits a withdrawal whose transfer landed. Returns false if it was already finished.
+pub async fn confirm_withdrawal(pool: &Pool<Postgres>, id: i32) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let Some(withdrawal) = finish_withdrawal(
+        &mut tx,
+        id,
+        &[WithdrawalStatus::BROADCAST],
+        WithdrawalStatus::CONFIRMED,
+        None,
+    )
+    .await?
+    else {
+        return Ok(false);
+    };
+    info!("Withdrawal {} confirmed", id);
+
+    sqlx::query(
+        "UPDATE wallet SET reserved = reserved - $1, updated_at = CURRENT_TIMESTAMP
+         WHERE user_id = $2 AND currency = $3",
+    )
+    .bind(withdrawal.amount)
+    .bind(withdrawal.user_id)
+    .bind(&withdrawal.currency)
+    .execute(&mut *tx)
+    .await?;
+
+    let debit = withdrawal
+        .amount
+        .checked_neg()
+        .ok_or_else(|| anyhow!("Withdrawal {} overflows", id))?;
+    post_entry(
+        &mut tx,
+        JournalKind::WITHDRAWAL,
+        &id.to_string(),
+        Currency::from_str(&withdrawal.currency)?,
+        &[
+            Posting::user(withdrawal.user_id, debit),
+            Posting::to(LedgerAccount::EXTERNAL, withdrawal.amount),
+        ],
+    )
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO transactions (user_id, amount, currency, tx_type, tx_hash)
+         VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(withdrawal.user_id)
+    .bind(withdrawal.amount)
+    .bind(&withdrawal.currency)
+    .bind(TxType::WITHDRAWAL.to_string())
+    .bind(&withdrawal.tx_hash)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Gives the reserved funds of a withdrawal that can no longer land back to the user.
+/// Returns false if it was already finished.
+pub async fn fail_withdrawal(pool: &Pool<Postgres>, id: i32, error: &str) -> Result<bool> {
+    refund_withdrawal(pool, id, &IN_FLIGHT_WITHDRAWALS, error).await
+}
+
+/// Fails a withdrawal waiting for review. Returns false if it isn't waiting for review.
+pub async fn reject_withdrawal(pool: &Pool<Postgres>, id: i32, reason: &str) -> Result<bool> {
+    refund_withdrawal(pool, id, &[WithdrawalStatus::REVIEW], reason).await
+}
+
+async fn refund_withdrawal(
+    pool: &Pool<Postgres>,
+    id: i32,
+    from: &[WithdrawalStatus],
+    error: &str,
+) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    let Some(withdrawal) =
+        finish_withdrawal(&mut tx, id, from, WithdrawalStatus::FAILED, Some(error)).await?
+    else {
+        return Ok(false);
+    };
+    info!("Withdrawal {} failed: {}", id, error);
+
+    sqlx::query(
+        "UPDATE wallet SET reserved = reserved - $1, updated_at = CURRENT_TIMESTAMP
+         WHERE user_id = $2 AND currency = $3",
+    )
+    .bind(withdrawal.amount)
+    .bind(withdrawal.user_id)
+    .bind(&withdrawal.currency)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+// Moves a withdrawal in one of the `from` statuses to its final status, at most once
+async fn finish_withdrawal(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    id: i32,
+    from: &[WithdrawalStatus],
+    status: WithdrawalStatus,
+    error: Option<&str>,
+) -> Result<Option<Withdrawal>> {
+    let from: Vec<String> = from.iter().map(|status| status.to_string()).collect();
+    sqlx::query_as::<_, Withdrawal>(
+        "UPDATE withdrawals SET status = $2, error = $3, updated_at = CURRENT_TIMESTAMP
+         WHERE id = $1 AND status = ANY($4)
+         RETURNING *",
+    )
+    .bind(id)
+    .bind(status.to_string())
+    .bind(error)
+    .bind(&from)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(Error::from)
 }
 
 pub async fn record_game_result_tx(
     tx: &mut sqlx::Transaction<'_, Postgres>,
     user_id: i32,
     currency: &str,
-    profit: f64,
+    profit: Amount,
 ) -> Result<(), Error> {
     info!(
-        "Recording game result for user {} with profit {}",
+        "Recording game result for user {} with profit {:?}",
         user_id, profit
     );
     info!("Currency: {:?}", currency);
@@ -156,11 +1386,11 @@ pub async fn record_game_result_tx(
 
 pub async fn get_leaderboard_24h(
     pool: &Pool<Postgres>,
-    currency: &str,
+    currency: Currency,
     limit: i32,
 ) -> Result<Vec<LeaderboardEntry>, Error> {
-    sqlx::query_as("SELECT * FROM leaderboard_24h WHERE currency = $1 LIMIT $2")
-        .bind(currency)
+    sqlx::query_as("SELECT * FROM leaderboard_24h WHERE currency = $1 ORDER BY rank LIMIT $2")
+        .bind(currency.to_string())
         .bind(limit)
         .fetch_all(pool)
         .await
@@ -169,13 +1399,145 @@ pub async fn get_leaderboard_24h(
 
 pub async fn get_leaderboard_all_time(
     pool: &Pool<Postgres>,
-    currency: &str,
+    currency: Currency,
     limit: i32,
 ) -> Result<Vec<LeaderboardEntry>, Error> {
-    sqlx::query_as("SELECT * FROM leaderboard_all_time WHERE currency = $1 LIMIT $2")
-        .bind(currency)
+    sqlx::query_as("SELECT * FROM leaderboard_all_time WHERE currency = $1 ORDER BY rank LIMIT $2")
+        .bind(currency.to_string())
         .bind(limit)
         .fetch_all(pool)
         .await
         .map_err(Error::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These run against the database at TEST_DATABASE_URL with the migrations applied,
+    // and only with `cargo test -- --ignored`
+    async fn test_pool() -> PgPool {
+        let url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL isn't set");
+        PgPool::connect(&url).await.unwrap()
+    }
+
+    // Nothing done in it is committed
+    async fn test_transaction() -> sqlx::Transaction<'static, Postgres> {
+        test_pool().await.begin().await.unwrap()
+    }
+
+    async fn user_with_wallet(tx: &mut sqlx::Transaction<'_, Postgres>, currency: Currency) -> i32 {
+        let (user_id,): (i32,) = sqlx::query_as(
+            "INSERT INTO users (privy_id, email) VALUES ('mint-test', 'mint@test') RETURNING id",
+        )
+        .fetch_one(&mut **tx)
+        .await
+        .unwrap();
+        get_or_create_wallet(tx, user_id, currency, Some("0xabc"))
+            .await
+            .unwrap();
+        user_id
+    }
+
+    async fn balance(tx: &mut sqlx::Transaction<'_, Postgres>, user_id: i32) -> Amount {
+        let (balance,): (Amount,) = sqlx::query_as("SELECT balance FROM wallet WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&mut **tx)
+            .await
+            .unwrap();
+        balance
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database at TEST_DATABASE_URL"]
+    async fn a_transfer_credited_by_the_watcher_cant_pay_for_a_mint() {
+        let mut tx = test_transaction().await;
+        let user_id = user_with_wallet(&mut tx, Currency::MON).await;
+        let amount = Amount::from_units(5_000);
+
+        assert!(
+            credit_deposit(&mut tx, user_id, Currency::MON, amount, "0xpaid")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !record_mint(&mut tx, user_id, 3, Currency::MON, amount, "0xpaid")
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(balance(&mut tx, user_id).await, amount);
+        let (mints,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM journal_entries WHERE kind = 'MINT'")
+                .fetch_one(&mut *tx)
+                .await
+                .unwrap();
+        assert_eq!(mints, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database at TEST_DATABASE_URL"]
+    async fn a_minted_transfer_isnt_credited_as_a_deposit() {
+        let mut tx = test_transaction().await;
+        let user_id = user_with_wallet(&mut tx, Currency::MON).await;
+        let amount = Amount::from_units(5_000);
+
+        assert!(
+            record_mint(&mut tx, user_id, 3, Currency::MON, amount, "0xpaid")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !credit_deposit(&mut tx, user_id, Currency::MON, amount, "0xpaid")
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(balance(&mut tx, user_id).await, Amount::ZERO);
+        let (gif_ids,): (Vec<i32>,) = sqlx::query_as("SELECT gif_ids FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(gif_ids, vec![3]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database at TEST_DATABASE_URL"]
+    async fn queued_escrow_actions_are_retried_until_they_go_through() {
+        // The queue commits, so this uses its own game id and cleans up after itself
+        let pool = test_pool().await;
+        let game_id = format!("sweep-test-{}", std::process::id());
+        let release = EscrowAction::Release { user_id: None };
+        let released = queue_escrow_action(&pool, &game_id, 0, &release)
+            .await
+            .unwrap();
+        let garbled = queue_escrow_action(&pool, &game_id, 1, &release)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE pending_escrow_actions SET action = 'garbled' WHERE id = $1")
+            .bind(garbled)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        resolve_pending_escrow_actions(&pool, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+
+        let pending: Vec<(i32, i32)> = sqlx::query_as(
+            "SELECT id, attempts FROM pending_escrow_actions WHERE game_id = $1 ORDER BY id",
+        )
+        .bind(&game_id)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        sqlx::query("DELETE FROM pending_escrow_actions WHERE game_id = $1")
+            .bind(&game_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(pending.iter().all(|&(id, _)| id != released));
+        assert_eq!(pending, vec![(garbled, 1)]);
+    }
+}
@@ -1,3 +1,3 @@
 pub mod macros;
 
-agg_mod!(utils models db telegram);
+agg_mod!(utils models db telegram auth reserves);
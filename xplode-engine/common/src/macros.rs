@@ -26,10 +26,10 @@ macro_rules! impl_from_str_for_enum {
 #[macro_export]
 macro_rules! impl_to_string_for_enum {
     ($enum_name:ident, $( $variant:ident ),*) => {
-        impl ToString for $enum_name {
-            fn to_string(&self) -> String {
+        impl std::fmt::Display for $enum_name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 match self {
-                    $( $enum_name::$variant => stringify!($variant).to_string(), )*
+                    $( $enum_name::$variant => f.write_str(stringify!($variant)), )*
                 }
             }
         }
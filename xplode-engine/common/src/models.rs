@@ -2,6 +2,8 @@ use chrono;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::utils::Amount;
+
 #[derive(Deserialize, Serialize, sqlx::FromRow)]
 pub struct User {
     pub id: i32,                                   // Assuming id is INTEGER
@@ -19,7 +21,9 @@ pub struct Wallet {
     pub id: i32,
     pub user_id: i32,
     pub currency: String,
-    pub balance: f64,
+    pub balance: Amount,
+    // Part of the balance held as stakes of games in progress
+    pub reserved: Amount,
     pub wallet_type: String,
     pub wallet_address: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
@@ -31,19 +35,39 @@ pub struct Transaction {
     pub id: i32,
     pub user_id: i32,
     pub wallet_id: i32,
-    pub amount: f64,
+    pub amount: Amount,
     pub currency: String,
     pub tx_type: String,
     pub tx_hash: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct Withdrawal {
+    pub id: i32,
+    pub user_id: i32,
+    pub currency: String,
+    pub amount: Amount,
+    pub withdraw_address: String,
+    pub status: String,
+    // Why it waits for review, if it does
+    pub review_reason: Option<String>,
+    // Set once the transfer is signed, before it's sent
+    pub tx_hash: Option<String>,
+    pub signed_tx: Option<String>,
+    // Solana: last block height the transfer can land in. EVM: its nonce.
+    pub valid_until: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
 #[derive(Deserialize, Serialize, sqlx::FromRow)]
 pub struct GamePnl {
     pub id: i32,
     pub user_id: i32,
     pub currency: String,
-    pub profit: f64,
+    pub profit: Amount,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
@@ -54,7 +78,7 @@ pub struct UserNetworkPnl {
     pub user_id: i32,
     pub currency: String,
     pub total_matches: i32,
-    pub total_profit: f64,
+    pub total_profit: Amount,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
@@ -63,7 +87,52 @@ pub struct UserNetworkPnl {
 pub struct LeaderboardEntry {
     pub name: String,
     pub currency: String,
-    pub total_profit: f64,
+    pub total_profit: Amount,
     pub total_matches: i64,
     pub rank: i64,
 }
+
+// A user's PDA deposit address and how far the deposit watcher has credited it
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct DepositAddress {
+    pub user_pda: String,
+    pub user_id: i32,
+    pub seed: String,
+    pub last_signature: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+// How far a chain watcher has processed, see `db::set_chain_cursor`
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct ChainCursor {
+    pub name: String,
+    pub block_number: i64,
+    pub block_hash: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+// A published proof of reserves, `signature` covers `reserves::report_message`
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct ReserveReport {
+    pub id: i32,
+    pub currency: String,
+    pub root_hash: String,
+    pub liabilities: Amount,
+    pub reserves: Amount,
+    pub signer: String,
+    pub signature: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// A settlement or stake release that hasn't gone through yet, see `db::queue_escrow_action`
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct PendingEscrowAction {
+    pub id: i32,
+    pub game_id: String,
+    pub round: i32,
+    pub action: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
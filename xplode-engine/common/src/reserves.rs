@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::utils::{Amount, Currency};
+
+/// A user's balance as committed to in a proof of reserves. The salt keeps the
+/// leaf hash from revealing who holds what.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReserveLeaf {
+    pub user_id: i32,
+    pub balance: Amount,
+    pub salt: String,
+}
+
+/// A node of the Merkle-sum tree, its sum is the total of the balances below it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SumNode {
+    pub hash: String,
+    pub sum: Amount,
+}
+
+/// The sibling to combine with on the way from a leaf to the root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub sibling: SumNode,
+    pub sibling_on_left: bool,
+}
+
+impl ReserveLeaf {
+    pub fn node(&self) -> SumNode {
+        let mut hasher = Sha256::new();
+        hasher.update(b"leaf");
+        hasher.update(self.user_id.to_be_bytes());
+        hasher.update(self.balance.units().to_be_bytes());
+        hasher.update(self.salt.as_bytes());
+        SumNode {
+            hash: hex::encode(hasher.finalize()),
+            sum: self.balance,
+        }
+    }
+}
+
+// Commits to both children and their sums, so a parent can't claim less than its
+// children hold
+fn parent(left: &SumNode, right: &SumNode) -> Result<SumNode> {
+    let sum = left
+        .sum
+        .checked_add(right.sum)
+        .ok_or_else(|| anyhow!("Liabilities overflow"))?;
+    let mut hasher = Sha256::new();
+    hasher.update(b"node");
+    hasher.update(hex::decode(&left.hash)?);
+    hasher.update(left.sum.units().to_be_bytes());
+    hasher.update(hex::decode(&right.hash)?);
+    hasher.update(right.sum.units().to_be_bytes());
+    Ok(SumNode {
+        hash: hex::encode(hasher.finalize()),
+        sum,
+    })
+}
+
+// Pairs with the last node of a level with an odd number of nodes
+fn padding() -> SumNode {
+    SumNode {
+        hash: hex::encode(Sha256::digest(b"padding")),
+        sum: Amount::ZERO,
+    }
+}
+
+/// Merkle-sum tree over the users' balances of one currency. The root's sum is the
+/// total liabilities, and each user can check their balance is included in it.
+pub struct MerkleSumTree {
+    // Leaves first, the root last
+    levels: Vec<Vec<SumNode>>,
+}
+
+impl MerkleSumTree {
+    pub fn build(leaves: &[ReserveLeaf]) -> Result<Self> {
+        // A negative leaf would let the sum hide liabilities
+        if let Some(leaf) = leaves.iter().find(|leaf| leaf.balance.is_negative()) {
+            return Err(anyhow!("User {} has a negative balance", leaf.user_id));
+        }
+        let mut levels = vec![leaves.iter().map(ReserveLeaf::node).collect::<Vec<_>>()];
+        if levels[0].is_empty() {
+            levels[0].push(padding());
+        }
+        while levels.last().map_or(0, Vec::len) > 1 {
+            let level = levels.last().expect("levels are never empty");
+            let mut next = Vec::with_capacity(level.len().div_ceil(2));
+            for pair in level.chunks(2) {
+                let right = pair.get(1).cloned().unwrap_or_else(padding);
+                next.push(parent(&pair[0], &right)?);
+            }
+            levels.push(next);
+        }
+        Ok(MerkleSumTree { levels })
+    }
+
+    pub fn root(&self) -> &SumNode {
+        &self.levels.last().expect("levels are never empty")[0]
+    }
+
+    /// Siblings from the leaf at `index` up to the root
+    pub fn proof(&self, mut index: usize) -> Option<Vec<ProofStep>> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+        let mut steps = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            steps.push(ProofStep {
+                sibling: level.get(sibling).cloned().unwrap_or_else(padding),
+                sibling_on_left: sibling < index,
+            });
+            index /= 2;
+        }
+        Some(steps)
+    }
+}
+
+/// Checks that `leaf` is included in the tree with `root`
+pub fn verify_proof(leaf: &ReserveLeaf, proof: &[ProofStep], root: &SumNode) -> bool {
+    let mut node = leaf.node();
+    for step in proof {
+        if step.sibling.sum.is_negative() {
+            return false;
+        }
+        let next = if step.sibling_on_left {
+            parent(&step.sibling, &node)
+        } else {
+            parent(&node, &step.sibling)
+        };
+        match next {
+            Ok(next) => node = next,
+            Err(_) => return false,
+        }
+    }
+    &node == root
+}
+
+/// A tree's root signed together with what the treasury held at the time
+pub struct SignedReport {
+    pub currency: Currency,
+    pub root: SumNode,
+    pub reserves: Amount,
+    pub created_at: DateTime<Utc>,
+    // Base58 ed25519 public key and signature of `report_message`
+    pub signer: String,
+    pub signature: String,
+}
+
+/// The statement a report's signature covers
+pub fn report_message(
+    currency: Currency,
+    root: &SumNode,
+    reserves: Amount,
+    created_at: DateTime<Utc>,
+) -> String {
+    format!(
+        "xplode proof of reserves|{}|{}|{}|{}|{}",
+        currency,
+        root.hash,
+        root.sum.units(),
+        reserves.units(),
+        created_at.timestamp()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(user_id: i32, units: i64) -> ReserveLeaf {
+        ReserveLeaf {
+            user_id,
+            balance: Amount::from_units(units),
+            salt: format!("salt-{}", user_id),
+        }
+    }
+
+    #[test]
+    fn every_balance_is_proven_against_the_total() {
+        let leaves: Vec<_> = (1..=5).map(|id| leaf(id, i64::from(id) * 100)).collect();
+        let tree = MerkleSumTree::build(&leaves).unwrap();
+        assert_eq!(tree.root().sum, Amount::from_units(1500));
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof(index).unwrap();
+            assert!(verify_proof(leaf, &proof, tree.root()));
+        }
+        assert!(tree.proof(leaves.len()).is_none());
+    }
+
+    #[test]
+    fn altered_balances_fail_to_verify() {
+        let leaves = vec![leaf(1, 100), leaf(2, 200), leaf(3, 300)];
+        let tree = MerkleSumTree::build(&leaves).unwrap();
+        let proof = tree.proof(1).unwrap();
+
+        assert!(!verify_proof(&leaf(2, 150), &proof, tree.root()));
+
+        let mut understated = proof.clone();
+        understated[0].sibling.sum = Amount::from_units(50);
+        assert!(!verify_proof(&leaves[1], &understated, tree.root()));
+
+        assert!(MerkleSumTree::build(&[leaf(1, -1)]).is_err());
+    }
+}
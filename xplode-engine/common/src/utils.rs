@@ -1,13 +1,221 @@
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use sqlx::encode::IsNull;
+use std::env;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef, Postgres};
 
 use crate::{impl_from_str_for_enum, impl_to_string_for_enum};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Currency {
     INR,
     SOL,
     USDC,
     MON,
+    // Native ETH on Arbitrum, where the game log lives
+    ETH,
+}
+
+impl Currency {
+    // Decimals of the unit balances are kept in. On chain a currency can have more
+    // (wei, or a token's own decimals), its chain adapter knows how many.
+    pub fn decimals(&self) -> u32 {
+        match self {
+            Currency::INR => 2,
+            Currency::SOL => 9,
+            Currency::USDC => 6,
+            // Gwei, wei would overflow a BIGINT past ~9 MON or ETH
+            Currency::MON | Currency::ETH => 9,
+        }
+    }
+}
+
+/// Money as a whole number of the currency's smallest ledger unit, e.g. lamports
+/// for SOL. The currency lives next to the amount, arithmetic never rounds.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Amount(i64);
+
+// Stored as a BIGINT of ledger units
+impl sqlx::Type<Postgres> for Amount {
+    fn type_info() -> PgTypeInfo {
+        <i64 as sqlx::Type<Postgres>>::type_info()
+    }
+}
+
+impl<'q> sqlx::Encode<'q, Postgres> for Amount {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        <i64 as sqlx::Encode<Postgres>>::encode_by_ref(&self.0, buf)
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Postgres> for Amount {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        <i64 as sqlx::Decode<Postgres>>::decode(value).map(Amount)
+    }
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn from_units(units: i64) -> Self {
+        Amount(units)
+    }
+
+    pub fn units(&self) -> i64 {
+        self.0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_neg(self) -> Option<Amount> {
+        self.0.checked_neg().map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: i64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    /// Splits into `parts` amounts that add back up exactly, the first ones
+    /// take the leftover units.
+    pub fn split(self, parts: usize) -> Option<Vec<Amount>> {
+        let count = i64::try_from(parts).ok().filter(|&count| count > 0)?;
+        let share = self.0 / count;
+        let leftover = self.0 % count;
+        Some(
+            (0..count)
+                .map(|i| Amount(share + i64::from(i < leftover.abs()) * leftover.signum()))
+                .collect(),
+        )
+    }
+
+    /// Profits of a game staked at `self` per player, for groups of tied players
+    /// of the given sizes, from first place down. They step evenly from `self` for
+    /// first place to minus `self` for last, tied players share what their places
+    /// add up to, and the leftover units go to the places rounded down the most. With one group
+    /// above a single loser this is the loser's stake split between the others.
+    pub fn ranked_split(self, places: &[usize]) -> Option<Vec<Vec<Amount>>> {
+        let players = places.iter().sum::<usize>() as i128;
+        if players < 2 || places.contains(&0) {
+            return None;
+        }
+
+        // Place q, counted from 0 at the top, earns stake * (players - 1 - 2q) / (players - 1)
+        let mut totals = Vec::with_capacity(places.len());
+        let mut remainders = Vec::with_capacity(places.len());
+        let mut first = 0_i128;
+        for &size in places {
+            let size = size as i128;
+            let share = i128::from(self.0) * size * (players - 2 * first - size);
+            totals.push(share.div_euclid(players - 1));
+            remainders.push(share.rem_euclid(players - 1));
+            first += size;
+        }
+        let leftover = -totals.iter().sum::<i128>();
+        let mut by_remainder: Vec<usize> = (0..places.len()).collect();
+        by_remainder.sort_by_key(|&i| std::cmp::Reverse(remainders[i]));
+        for &i in by_remainder.iter().take(leftover as usize) {
+            totals[i] += 1;
+        }
+
+        totals
+            .into_iter()
+            .zip(places)
+            .map(|(total, &size)| Amount(i64::try_from(total).ok()?).split(size))
+            .collect()
+    }
+
+    /// Profits of a game staked at `self` per player where everyone below first
+    /// place loses their stake, and the players tied for first share the pot.
+    pub fn pot_split(self, places: &[usize]) -> Option<Vec<Vec<Amount>>> {
+        let (&winners, losers) = places.split_first()?;
+        if places.iter().sum::<usize>() < 2 || places.contains(&0) {
+            return None;
+        }
+
+        let pot = self
+            .0
+            .checked_mul(i64::try_from(losers.iter().sum::<usize>()).ok()?)?;
+        let mut profits = vec![Amount(pot).split(winners)?];
+        profits.extend(losers.iter().map(|&size| vec![Amount(-self.0); size]));
+        Some(profits)
+    }
+
+    /// Parses a decimal string such as "1.25" into ledger units
+    pub fn parse(value: &str, currency: Currency) -> Result<Amount> {
+        let decimals = currency.decimals() as usize;
+        let (negative, digits) = match value.trim().strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, value.trim()),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty()
+            || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+        {
+            return Err(anyhow!("Invalid amount: {}", value));
+        }
+        if fraction.len() > decimals {
+            return Err(anyhow!(
+                "{} only has {} decimals, got {}",
+                currency,
+                decimals,
+                value
+            ));
+        }
+
+        let units = format!("{}{:0<decimals$}", whole, fraction)
+            .parse::<i64>()
+            .map_err(|_| anyhow!("Amount out of range: {}", value))?;
+        Ok(Amount(if negative { -units } else { units }))
+    }
+
+    /// Formats the amount as a decimal string, e.g. for notifications
+    pub fn to_decimal(self, currency: Currency) -> String {
+        let scale = 10_u64.pow(currency.decimals());
+        let units = self.0.unsigned_abs();
+        let sign = if self.0 < 0 { "-" } else { "" };
+        if scale == 1 {
+            return format!("{}{}", sign, units);
+        }
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            units / scale,
+            units % scale,
+            width = currency.decimals() as usize
+        )
+    }
+
+    /// Ledger amount of an on-chain value with `chain_decimals`, dust below the
+    /// ledger's precision is dropped
+    pub fn from_chain_units(units: u128, currency: Currency, chain_decimals: u32) -> Option<Amount> {
+        let factor = chain_unit_factor(currency, chain_decimals)?;
+        i64::try_from(units / factor).ok().map(Amount)
+    }
+
+    /// The amount in an on-chain unit with `chain_decimals`, e.g. wei for MON
+    pub fn to_chain_units(self, currency: Currency, chain_decimals: u32) -> Option<u128> {
+        let factor = chain_unit_factor(currency, chain_decimals)?;
+        u128::try_from(self.0).ok()?.checked_mul(factor)
+    }
+}
+
+// None when the chain is less precise than the ledger
+fn chain_unit_factor(currency: Currency, chain_decimals: u32) -> Option<u128> {
+    10_u128.checked_pow(chain_decimals.checked_sub(currency.decimals())?)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -17,10 +225,68 @@ pub enum TxType {
     MINT,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+// PENDING holds the funds, BROADCAST is signed and sent, then CONFIRMED or FAILED.
+// REVIEW holds the funds until an admin approves (PENDING) or rejects (FAILED).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum WithdrawalStatus {
+    REVIEW,
+    PENDING,
+    BROADCAST,
+    CONFIRMED,
+    FAILED,
+}
+
+// Accounts of the double-entry ledger. USER accounts are the users' balances,
+// EXTERNAL is the money outside the ledger, so it goes negative by what came in.
+// ESCROW holds the stakes of games in progress and OPENING the balances that
+// predate the ledger. SALES collects what NFT mints were paid.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum LedgerAccount {
+    USER,
+    EXTERNAL,
+    ESCROW,
+    SALES,
+    OPENING,
+}
+
+// What a journal entry records, each kind and reference is posted at most once
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum JournalKind {
+    OPENING,
+    DEPOSIT,
+    WITHDRAWAL,
+    STAKE,
+    RELEASE,
+    SETTLEMENT,
+    MINT,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Network {
     SOLANA,
     MONAD,
+    ARBITRUM,
+}
+
+/// How a finished game's stakes change hands
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Payout {
+    // Profits step down from first place to last, see `Amount::ranked_split`
+    #[default]
+    PLACEMENT,
+    // Everyone below first place loses their stake to the survivors
+    SURVIVOR,
+}
+
+impl Payout {
+    /// Profits per player of a game staked at `stake`, for groups of tied players
+    /// of the given sizes from first place down
+    pub fn profits(self, stake: Amount, places: &[usize]) -> Option<Vec<Vec<Amount>>> {
+        match self {
+            Payout::PLACEMENT => stake.ranked_split(places),
+            Payout::SURVIVOR => stake.pot_split(places),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -35,6 +301,8 @@ pub struct UserDetailsRequest {
     pub email: String,
     pub privy_id: String,
     pub wallet_address: Option<String>,
+    // The wallet signing `auth::address_ownership_message`, required with an address
+    pub wallet_signature: Option<String>,
     pub currency: Option<Currency>,
 }
 
@@ -43,11 +311,13 @@ pub struct UserDetailsResponse {
     pub id: i32,
     pub name: String,
     pub email: String,
-    pub balance: f64,
+    pub balance: Amount,
     pub privy_id: String,
     pub wallet_address: Option<String>,
     pub currency: Option<Currency>,
     pub gif_ids: Vec<i32>,
+    // Presented to the game server when opening the websocket
+    pub session_token: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -59,34 +329,195 @@ pub struct UpdateUserDetailsRequest {
 #[derive(Deserialize, Debug)]
 pub struct DepositRequest {
     pub user_id: i32,
-    pub amount: f64,
+    pub amount: Amount,
     pub currency: Currency,
     pub tx_type: TxType,
     pub tx_hash: String,
 }
 
+/// Body of /withdraw, the user is the one the session token was issued to
 #[derive(Deserialize, Debug)]
 pub struct WithdrawRequest {
-    pub user_id: i32,
-    pub amount: f64,
+    pub amount: Amount,
     pub currency: Currency,
     pub withdraw_address: String,
 }
 
+/// Withdrawal risk controls of one currency, unset limits don't apply
+#[derive(Debug, Clone, Default)]
+pub struct WithdrawalLimits {
+    // Per user, over the last 24 hours
+    pub user_daily_limit: Option<Amount>,
+    // Over all users, over the last 24 hours. Past it withdrawals wait for review.
+    pub treasury_daily_cap: Option<Amount>,
+    // Withdrawals of at least this much wait for review
+    pub review_threshold: Option<Amount>,
+    // Seconds after a deposit during which the user can't withdraw
+    pub deposit_cooldown_secs: i64,
+}
+
+impl WithdrawalLimits {
+    /// Reads e.g. WITHDRAWAL_USER_DAILY_LIMIT_SOL="10" as decimal amounts of the currency
+    pub fn from_env(currency: Currency) -> Result<Self> {
+        let amount = |name: &str| -> Result<Option<Amount>> {
+            env::var(format!("{}_{}", name, currency))
+                .ok()
+                .map(|value| Amount::parse(&value, currency))
+                .transpose()
+        };
+        Ok(WithdrawalLimits {
+            user_daily_limit: amount("WITHDRAWAL_USER_DAILY_LIMIT")?,
+            treasury_daily_cap: amount("WITHDRAWAL_TREASURY_DAILY_CAP")?,
+            review_threshold: amount("WITHDRAWAL_REVIEW_THRESHOLD")?,
+            deposit_cooldown_secs: env::var("WITHDRAWAL_DEPOSIT_COOLDOWN_SECS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .unwrap_or(0),
+        })
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct MintNftRequest {
-    pub user_id: i32,
     pub gif_id: i32,
-    pub mint_amount: f64,
+    pub mint_amount: Amount,
     pub currency: Currency,
     pub tx_hash: String,
 }
 
-impl_from_str_for_enum!(Currency, INR, SOL, USDC, MON);
-impl_to_string_for_enum!(Currency, INR, SOL, USDC, MON);
+impl_from_str_for_enum!(Currency, INR, SOL, USDC, MON, ETH);
+impl_to_string_for_enum!(Currency, INR, SOL, USDC, MON, ETH);
 impl_from_str_for_enum!(TxType, DEPOSIT, WITHDRAWAL, MINT);
 impl_to_string_for_enum!(TxType, DEPOSIT, WITHDRAWAL, MINT);
-impl_from_str_for_enum!(Network, SOLANA, MONAD);
-impl_to_string_for_enum!(Network, SOLANA, MONAD);
+impl_from_str_for_enum!(LedgerAccount, USER, EXTERNAL, ESCROW, SALES, OPENING);
+impl_to_string_for_enum!(LedgerAccount, USER, EXTERNAL, ESCROW, SALES, OPENING);
+impl_from_str_for_enum!(
+    JournalKind,
+    OPENING,
+    DEPOSIT,
+    WITHDRAWAL,
+    STAKE,
+    RELEASE,
+    SETTLEMENT,
+    MINT
+);
+impl_to_string_for_enum!(
+    JournalKind,
+    OPENING,
+    DEPOSIT,
+    WITHDRAWAL,
+    STAKE,
+    RELEASE,
+    SETTLEMENT,
+    MINT
+);
+impl_from_str_for_enum!(WithdrawalStatus, REVIEW, PENDING, BROADCAST, CONFIRMED, FAILED);
+impl_to_string_for_enum!(WithdrawalStatus, REVIEW, PENDING, BROADCAST, CONFIRMED, FAILED);
+impl_from_str_for_enum!(Network, SOLANA, MONAD, ARBITRUM);
+impl_to_string_for_enum!(Network, SOLANA, MONAD, ARBITRUM);
 impl_from_str_for_enum!(WalletType, PDA, DIRECT);
 impl_to_string_for_enum!(WalletType, PDA, DIRECT);
+impl_from_str_for_enum!(Payout, PLACEMENT, SURVIVOR);
+impl_to_string_for_enum!(Payout, PLACEMENT, SURVIVOR);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn amounts_parse_and_format_without_rounding() {
+        let amount = Amount::parse("0.1", Currency::SOL).unwrap();
+        assert_eq!(amount.units(), 100_000_000);
+        assert_eq!(amount.to_decimal(Currency::SOL), "0.100000000");
+        assert_eq!(
+            Amount::parse("-12.5", Currency::INR).unwrap(),
+            Amount::from_units(-1250)
+        );
+        assert!(Amount::parse("0.0000001", Currency::USDC).is_err());
+        assert!(Amount::parse("1e3", Currency::SOL).is_err());
+        assert!(Amount::parse("10000000000", Currency::SOL).is_err());
+    }
+
+    #[test]
+    fn splits_add_back_up() {
+        let shares = Amount::from_units(10).split(3).unwrap();
+        assert_eq!(shares, vec![4, 3, 3].into_iter().map(Amount::from_units).collect::<Vec<_>>());
+        assert_eq!(Amount::from_units(10).split(0), None);
+        assert_eq!(Amount::from_units(i64::MAX).checked_add(Amount::from_units(1)), None);
+    }
+
+    #[test]
+    fn ranked_splits_step_from_winner_to_loser() {
+        let bet = Amount::from_units(10);
+        let units = |places: &[usize]| -> Vec<Vec<i64>> {
+            bet.ranked_split(places)
+                .unwrap()
+                .iter()
+                .map(|group| group.iter().map(Amount::units).collect())
+                .collect()
+        };
+
+        // A single loser pays everyone else, like the classic split
+        assert_eq!(units(&[1, 1]), vec![vec![10], vec![-10]]);
+        assert_eq!(units(&[2, 1]), vec![vec![5, 5], vec![-10]]);
+        assert_eq!(units(&[3, 1]), vec![vec![4, 3, 3], vec![-10]]);
+        // Ranked all the way down
+        assert_eq!(units(&[1, 1, 1]), vec![vec![10], vec![0], vec![-10]]);
+        assert_eq!(units(&[1, 1, 1, 1]), vec![vec![10], vec![3], vec![-3], vec![-10]]);
+        assert_eq!(units(&[1, 2]), vec![vec![10], vec![-5, -5]]);
+        assert_eq!(units(&[3]), vec![vec![0, 0, 0]]);
+        assert_eq!(bet.ranked_split(&[1]), None);
+    }
+
+    #[test]
+    fn survivors_take_the_whole_pot() {
+        let bet = Amount::from_units(10);
+        let units = |places: &[usize]| -> Vec<Vec<i64>> {
+            Payout::SURVIVOR
+                .profits(bet, places)
+                .unwrap()
+                .iter()
+                .map(|group| group.iter().map(Amount::units).collect())
+                .collect()
+        };
+
+        assert_eq!(units(&[1, 1]), vec![vec![10], vec![-10]]);
+        assert_eq!(units(&[1, 1, 1, 1]), vec![vec![30], vec![-10], vec![-10], vec![-10]]);
+        assert_eq!(units(&[2, 1, 1]), vec![vec![10, 10], vec![-10], vec![-10]]);
+        assert_eq!(units(&[3]), vec![vec![0, 0, 0]]);
+        assert_eq!(bet.pot_split(&[1]), None);
+    }
+
+    #[test]
+    fn chain_units_cover_large_evm_transfers() {
+        let amount = Amount::parse("25", Currency::MON).unwrap();
+        assert_eq!(amount.to_chain_units(Currency::MON, 18), Some(25 * 10_u128.pow(18)));
+        assert_eq!(Amount::from_units(-1).to_chain_units(Currency::SOL, 9), None);
+        assert_eq!(
+            Amount::from_chain_units(25 * 10_u128.pow(18) + 1, Currency::MON, 18),
+            Some(amount)
+        );
+    }
+
+    #[test]
+    fn eth_is_kept_in_gwei() {
+        assert_eq!(Currency::from_str("ETH").unwrap(), Currency::ETH);
+        assert_eq!(Network::from_str("ARBITRUM").unwrap().to_string(), "ARBITRUM");
+        let amount = Amount::parse("0.000000001", Currency::ETH).unwrap();
+        assert_eq!(amount.units(), 1);
+        assert_eq!(amount.to_chain_units(Currency::ETH, 18), Some(10_u128.pow(9)));
+    }
+
+    #[test]
+    fn token_decimals_come_from_the_chain() {
+        let amount = Amount::parse("1.5", Currency::USDC).unwrap();
+        assert_eq!(amount.to_chain_units(Currency::USDC, 6), Some(1_500_000));
+        // e.g. USDC on BNB Chain
+        assert_eq!(
+            amount.to_chain_units(Currency::USDC, 18),
+            Some(15 * 10_u128.pow(17))
+        );
+        assert_eq!(Amount::from_chain_units(1, Currency::USDC, 2), None);
+    }
+}
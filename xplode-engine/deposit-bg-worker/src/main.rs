@@ -1,43 +1,41 @@
-// use std::{str::FromStr, time::Duration};
-
-// use common::{db::establish_connection, models::User};
-// use deposits::sol::DepositService;
-// use dotenv::dotenv;
-// use solana_sdk::pubkey::Pubkey;
-// use tokio::time::sleep;
-// use tracing::info;
-
-// #[tokio::main]
-// async fn main() -> anyhow::Result<()> {
-//     dotenv().ok();
-
-//     info!("Starting the deposit background service");
-//     let program_id = std::env::var("PROGRAM_ID").unwrap();
-
-//     let cwd = std::env::current_dir().unwrap();
-//     let service = DepositService::new(cwd.join("treasury-keypair.json"), program_id.to_string());
-
-//     let pool = establish_connection();
-//     let mut conn = pool.await.acquire().await.expect("DB conn failed");
-
-//     loop {
-//         info!("Hello");
-//         let users: Vec<User> = sqlx::query_as("SELECT * FROM users")
-//             .fetch_all(&mut conn)
-//             .await
-//             .expect("Fqailed to fetch users");
-
-//         let users_pubkeys: Vec<_> = users
-//             .iter()
-//             .filter_map(|user| {
-//                 user.user_pda
-//                     .as_ref()
-//                     .map(|pda| Pubkey::from_str(pda).unwrap())
-//             })
-//             .collect();
-
-//         service.check_deposits(users_pubkeys).await.unwrap();
-
-//         sleep(Duration::from_secs(10)).await;
-//     }
-// }
+use std::{env, time::Duration};
+
+use common::db::establish_connection;
+use deposits::sol::DepositService;
+use dotenv::dotenv;
+use tokio::time::sleep;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    info!("Starting the deposit background service");
+    let pool = establish_connection().await;
+    let service = DepositService::from_env(pool)?;
+
+    // Deposit addresses used to live in Redis only
+    if let Ok(redis_url) = env::var("REDIS_URL") {
+        let imported = service.import_redis_addresses(&redis_url).await?;
+        info!("Imported {} deposit addresses from Redis", imported);
+    }
+
+    let poll_interval = Duration::from_secs(
+        env::var("DEPOSIT_POLL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(10),
+    );
+    loop {
+        if let Err(e) = service.check_deposits().await {
+            error!("Failed to check deposits: {}", e);
+        }
+        sleep(poll_interval).await;
+    }
+}
@@ -1,261 +1,273 @@
-// use redis::Client;
-// use solana_client::rpc_client::RpcClient;
-// use solana_sdk::{
-//     commitment_config::CommitmentConfig, instruction::AccountMeta, pubkey::Pubkey,
-//     signature::Keypair, signer::Signer, system_instruction, system_program,
-//     transaction::Transaction,
-// };
-// use std::{env, path::Path, str::FromStr, sync::Arc};
+use std::{collections::HashMap, env, str::FromStr};
 
-// async fn handle_deposit(
-//     connection: Arc<RpcClient>,
-//     treasury: Arc<Keypair>,
-//     program_id: Pubkey,
-//     redis: Arc<Client>,
-//     deposit_address: Pubkey,
-//     amount: u64,
-// ) -> anyhow::Result<()> {
-//     let mut conn = redis.get_connection()?;
-//     let user_id: String = redis::cmd("HGET")
-//         .arg("deposit_addresses")
-//         .arg(deposit_address.to_string())
-//         .query(&mut conn)?;
+use anyhow::anyhow;
+use common::{
+    db,
+    models::DepositAddress,
+    utils::{Amount, Currency},
+};
+use redis::AsyncCommands;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig, rpc_response::RpcConfirmedTransactionStatusWithSignature,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature},
+    signer::Signer,
+    system_program,
+    transaction::Transaction,
+};
+use solana_transaction_status_client_types::{UiLoadedAddresses, UiTransactionEncoding};
+use sqlx::{Pool, Postgres};
+use tracing::{error, info};
 
-//     let user_pubkey = Pubkey::from_str(&user_id)?;
+// Anchor discriminator of the program's forward_deposit instruction
+const FORWARD_DEPOSIT_DISCRIMINATOR: [u8; 8] = [91, 60, 51, 162, 44, 140, 96, 24];
+// Largest page getSignaturesForAddress returns
+const SIGNATURES_PAGE: usize = 1000;
 
-//     let instruction = anchor_lang::solana_program::instruction::Instruction {
-//         program_id,
-//         accounts: vec![
-//             AccountMeta::new(deposit_address, false), // PDA is not a signer
-//             AccountMeta::new(user_pubkey, false),
-//             AccountMeta::new(treasury.pubkey(), true), // Treasury is signer
-//             AccountMeta::new_readonly(system_program::id(), false),
-//         ],
-//         data: {
-//             let mut data = vec![91, 60, 51, 162, 44, 140, 96, 24]; // discriminator for forward deposit
-//             data.extend_from_slice(&amount.to_le_bytes());
-//             data
-//         },
-//     };
+/// Watches the users' PDA deposit addresses, credits what lands in them and forwards
+/// it to the treasury through the program
+pub struct DepositService {
+    pool: Pool<Postgres>,
+    connection: RpcClient,
+    treasury: Keypair,
+    program_id: Pubkey,
+}
 
-//     let recent_blockhash = connection.get_latest_blockhash()?;
-//     let transaction = Transaction::new_signed_with_payer(
-//         &[instruction],
-//         Some(&treasury.pubkey()),
-//         &[treasury.as_ref()], // Only treasury signs
-//         recent_blockhash,
-//     );
+impl DepositService {
+    /// Reads SOLANA_RPC_URL, PROGRAM_ID and the treasury keypair, from
+    /// SOLANA_TREASURY_KEYPAIR or treasury-keypair.json in the working directory
+    pub fn from_env(pool: Pool<Postgres>) -> anyhow::Result<Self> {
+        let program_id = Pubkey::from_str(&env::var("PROGRAM_ID")?)?;
+        let keypair_path = match env::var("SOLANA_TREASURY_KEYPAIR") {
+            Ok(path) => path.into(),
+            Err(_) => env::current_dir()?.join("treasury-keypair.json"),
+        };
+        let treasury = read_keypair_file(&keypair_path)
+            .map_err(|e| anyhow!("Failed to load {}: {}", keypair_path.display(), e))?;
 
-//     let signature = connection.send_and_confirm_transaction(&transaction)?;
+        // Only finalized transactions are credited, they can't be rolled back
+        let connection = RpcClient::new_with_commitment(
+            env::var("SOLANA_RPC_URL")?,
+            CommitmentConfig::finalized(),
+        );
 
-//     println!("Confirmation sent: {:?}", signature);
-//     Ok(())
-// }
+        Ok(Self {
+            pool,
+            connection,
+            treasury,
+            program_id,
+        })
+    }
 
-// #[derive(Clone)]
-// pub struct DepositService {
-//     redis: Arc<Client>,
-//     connection: Arc<RpcClient>,
-//     treasury: Arc<Keypair>,
-//     program_id: Pubkey,
-// }
+    fn deposit_address(&self, seed: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"deposit", seed.as_ref()], &self.program_id).0
+    }
 
-// impl DepositService {
-//     pub fn new<P: AsRef<Path>>(treasury_keypair_path: P, program_id: String) -> Self {
-//         println!("Creating DepositService");
-//         let program_id = Pubkey::from_str(&program_id).unwrap();
-//         let connection = RpcClient::new_with_commitment(
-//             std::env::var("SOLANA_RPC_URL").unwrap(),
-//             CommitmentConfig::confirmed(),
-//         );
+    /// Derives a fresh deposit address for the user and registers it
+    pub async fn generate_deposit_address(&self, user_id: i32) -> anyhow::Result<Pubkey> {
+        let seed = Keypair::new().pubkey();
+        let pda = self.deposit_address(&seed);
+        db::insert_deposit_address(&self.pool, user_id, &pda.to_string(), &seed.to_string())
+            .await?;
+        info!("Registered deposit address {} for user {}", pda, user_id);
+        Ok(pda)
+    }
 
-//         let treasury_data = std::fs::read_to_string(treasury_keypair_path).unwrap();
-//         let treasury_bytes: Vec<u8> = serde_json::from_str(&treasury_data).unwrap();
-//         let treasury = Keypair::from_bytes(&treasury_bytes).unwrap();
-//         let redis_url = env::var("REDIS_URL").unwrap();
-//         let client = Client::open(redis_url.clone()).expect("Failed to create Redis client");
+    /// Copies the deposit addresses of the Redis `deposit_addresses` hash (PDA to seed)
+    /// that aren't in Postgres yet, returns how many were added
+    pub async fn import_redis_addresses(&self, redis_url: &str) -> anyhow::Result<usize> {
+        let mut conn = redis::Client::open(redis_url)?
+            .get_multiplexed_async_connection()
+            .await?;
+        let addresses: HashMap<String, String> = conn.hgetall("deposit_addresses").await?;
 
-//         Self {
-//             redis: Arc::new(client),
-//             connection: Arc::new(connection),
-//             treasury: Arc::new(treasury),
-//             //program_id: Pubkey::from_str("FFT8CyM7DnNoWG2AukQqCEyNtZRLJvxN9WK6S7mC5kLP").unwrap(),
-//             program_id,
-//         }
-//     }
-//     pub fn generate_deposit_address(&self) -> anyhow::Result<Pubkey> {
-//         // let program_id = Pubkey::from_str(&program_id)?;
-//         let new_keypair = Keypair::new();
-//         let user_pubkey = new_keypair.pubkey();
-//         let (pda, _) =
-//             Pubkey::find_program_address(&[b"deposit", user_pubkey.as_ref()], &self.program_id);
+        let mut imported = 0;
+        for (pda, seed) in addresses {
+            let user_id: Option<(i32,)> =
+                sqlx::query_as("SELECT id FROM users WHERE user_pda = $1")
+                    .bind(&pda)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            let Some((user_id,)) = user_id else {
+                error!("Deposit address {} belongs to no user", pda);
+                continue;
+            };
+            if db::insert_deposit_address(&self.pool, user_id, &pda, &seed).await? {
+                imported += 1;
+            }
+        }
+        Ok(imported)
+    }
 
-//         println!("PDA: {:?}", pda);
-//         let mut conn = self.redis.get_connection()?;
-//         let result = redis::cmd("HSET")
-//             .arg("deposit_addresses")
-//             .arg(pda.to_string())
-//             .arg(user_pubkey.to_string())
-//             .exec(&mut conn);
+    /// Credits new deposits of every address, then forwards their balances
+    pub async fn check_deposits(&self) -> anyhow::Result<()> {
+        for address in db::get_deposit_addresses(&self.pool).await? {
+            if let Err(e) = self.credit_new_deposits(&address).await {
+                error!("Failed to credit deposits of {}: {}", address.user_pda, e);
+                continue;
+            }
+            if let Err(e) = self.forward_balance(&address).await {
+                error!("Failed to forward deposits of {}: {}", address.user_pda, e);
+            }
+        }
+        Ok(())
+    }
 
-//         if let Err(err) = result {
-//             eprintln!("Error executing HSET: {:?}", err);
-//         }
-//         Ok(pda)
-//     }
+    // Credits every signature after the cursor, oldest first. Each one is credited
+    // in the transaction that moves the cursor past it.
+    async fn credit_new_deposits(&self, address: &DepositAddress) -> anyhow::Result<()> {
+        let pda = Pubkey::from_str(&address.user_pda)?;
+        let until = address
+            .last_signature
+            .as_deref()
+            .map(Signature::from_str)
+            .transpose()?;
 
-//     pub async fn check_deposits(&self, pubkeys: Vec<Pubkey>) -> anyhow::Result<()> {
-//         if let Ok(accounts) = self.connection.get_multiple_accounts(&pubkeys) {
-//             for (i, account) in accounts.iter().enumerate() {
-//                 // check if account lamport is > 0, initiate fund transfer to the treasury
-//                 if let Some(account) = account {
-//                     if account.lamports > 0 {
-//                         // handle deposit
-//                         println!("Account: {:?}", account);
-//                         let conn = self.connection.clone();
-//                         let treasury = self.treasury.clone();
-//                         let redis = self.redis.clone();
-//                         let program_id = self.program_id.clone();
-//                         let pubkey = pubkeys[i].clone();
-//                         let amount = account.lamports.clone();
-//                         tokio::spawn(async move {
-//                             if let Err(err) =
-//                                 handle_deposit(conn, treasury, program_id, redis, pubkey, amount)
-//                                     .await
-//                             {
-//                                 eprintln!("Error: {:?}", err);
-//                             }
-//                         });
-//                     }
-//                 }
-//             }
-//         }
+        for signature in self.signatures_since(&pda, until).await?.into_iter().rev() {
+            let lamports = match &signature.err {
+                Some(_) => 0,
+                None => self.received_lamports(&pda, &signature.signature).await?,
+            };
 
-//         Ok(())
-//     }
+            let mut tx = self.pool.begin().await?;
+            if lamports > 0 {
+                let amount = Amount::from_chain_units(u128::from(lamports), Currency::SOL, 9)
+                    .ok_or_else(|| anyhow!("Deposit {} is too large", signature.signature))?;
+                if db::credit_deposit(
+                    &mut tx,
+                    address.user_id,
+                    Currency::SOL,
+                    amount,
+                    &signature.signature,
+                )
+                .await?
+                {
+                    info!(
+                        "Credited {} SOL to user {} for {}",
+                        amount.to_decimal(Currency::SOL),
+                        address.user_id,
+                        signature.signature
+                    );
+                }
+            }
+            db::set_deposit_cursor(&mut tx, &address.user_pda, &signature.signature).await?;
+            tx.commit().await?;
+        }
+        Ok(())
+    }
 
-//     pub async fn withdraw_to_user_from_treasury(
-//         &self,
-//         withdrawal_address: String,
-//         amount: u64,
-//     ) -> anyhow::Result<String> {
-//         let to_pubkey = Pubkey::from_str(&withdrawal_address)?;
+    // Finalized signatures of `pda` newer than `until`, newest first
+    async fn signatures_since(
+        &self,
+        pda: &Pubkey,
+        until: Option<Signature>,
+    ) -> anyhow::Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self
+                .connection
+                .get_signatures_for_address_with_config(
+                    pda,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: Some(SIGNATURES_PAGE),
+                        commitment: Some(CommitmentConfig::finalized()),
+                    },
+                )
+                .await?;
+            let full_page = page.len() == SIGNATURES_PAGE;
+            if let Some(last) = page.last() {
+                before = Some(Signature::from_str(&last.signature)?);
+            }
+            signatures.extend(page);
+            if !full_page {
+                return Ok(signatures);
+            }
+        }
+    }
 
-//         let treasury_pubkey = self.treasury.pubkey();
-//         let treasury_keypair = self.treasury.clone();
-//         let rpc_client = self.connection.clone();
+    // How much the transaction raised the PDA's balance. Forwards lower it, so they
+    // credit nothing.
+    async fn received_lamports(&self, pda: &Pubkey, signature: &str) -> anyhow::Result<u64> {
+        let transaction = self
+            .connection
+            .get_transaction_with_config(
+                &Signature::from_str(signature)?,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::finalized()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?
+            .transaction;
+        let meta = transaction
+            .meta
+            .ok_or_else(|| anyhow!("Transaction {} has no status", signature))?;
+        let decoded = transaction
+            .transaction
+            .decode()
+            .ok_or_else(|| anyhow!("Could not decode transaction {}", signature))?;
 
-//         let signature = tokio::task::spawn_blocking(move || {
-//             let instruction = system_instruction::transfer(&treasury_pubkey, &to_pubkey, amount);
-//             let recent_blockhash = rpc_client.get_latest_blockhash()?; // Blocking
-//             let transaction = Transaction::new_signed_with_payer(
-//                 &[instruction],
-//                 Some(&treasury_pubkey),
-//                 &[treasury_keypair.as_ref()],
-//                 recent_blockhash,
-//             );
+        // Balances follow the static keys, then the writable keys of lookup tables
+        let loaded: Option<UiLoadedAddresses> = meta.loaded_addresses.into();
+        let mut keys = decoded.message.static_account_keys().to_vec();
+        for key in loaded.map(|loaded| loaded.writable).unwrap_or_default() {
+            keys.push(Pubkey::from_str(&key)?);
+        }
+        let Some(index) = keys.iter().position(|key| key == pda) else {
+            return Ok(0);
+        };
 
-//             let signature = rpc_client.send_and_confirm_transaction(&transaction)?; // Blocking
-//             Ok::<_, anyhow::Error>(signature.to_string())
-//         })
-//         .await??;
+        let pre = meta.pre_balances.get(index).copied().unwrap_or_default();
+        let post = meta.post_balances.get(index).copied().unwrap_or_default();
+        Ok(post.saturating_sub(pre))
+    }
 
-//         println!("Signature: {:?}", signature);
-//         Ok(signature)
-//     }
-// }
+    // Moves everything on the PDA to the treasury, it's already credited
+    async fn forward_balance(&self, address: &DepositAddress) -> anyhow::Result<()> {
+        let pda = Pubkey::from_str(&address.user_pda)?;
+        let seed = Pubkey::from_str(&address.seed)?;
+        let lamports = self.connection.get_balance(&pda).await?;
+        if lamports == 0 {
+            return Ok(());
+        }
 
-// // // pub async fn read_account_updates(&self, account_pubkey: Pubkey) -> anyhow::Result<()> {
-// // //     let url = "wss://api.devnet.solana.com/";
+        let mut data = FORWARD_DEPOSIT_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&lamports.to_le_bytes());
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(pda, false), // PDA is not a signer
+                AccountMeta::new(seed, false),
+                AccountMeta::new(self.treasury.pubkey(), true), // Treasury is signer
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data,
+        };
 
-// // //     let connection = self.connection.clone();
-// // //     let treasury = self.treasury.clone();
-// // //     let program_id = self.program_id;
-// // //     let redis = self.redis.clone();
-
-// // //     // let ws_url = std::env::var("SOLANA_WS_URL").unwrap_or_else(|_| {
-// // //     //     std::env::var("SOLANA_RPC_URL")
-// // //     //         .unwrap()
-// // //     //         .replace("http", "ws")
-// // //     // });
-
-// // //     tokio::spawn(async move {
-// // //         loop {
-// // //             info!("Reconnecting ...");
-// // //             let (subscription, mut account_subscription_receiver) =
-// // //                 PubsubClient::account_subscribe(
-// // //                     url,
-// // //                     &account_pubkey,
-// // //                     Some(RpcAccountInfoConfig {
-// // //                         encoding: None,
-// // //                         data_slice: None,
-// // //                         commitment: Some(CommitmentConfig::confirmed()),
-// // //                         min_context_slot: None,
-// // //                     }),
-// // //                 )
-// // //                 .unwrap();
-// // //             let _sub = subscription;
-// // //             loop {
-// // //                 match account_subscription_receiver.recv() {
-// // //                     Ok(response) => {
-// // //                         // info!("account subscription response: {:?}", response);
-// // //                         if response.value.lamports > 0 {
-// // //                             if let Err(e) = handle_deposit(
-// // //                                 &connection,
-// // //                                 &treasury,
-// // //                                 program_id,
-// // //                                 &redis,
-// // //                                 account_pubkey,
-// // //                                 response.value.lamports,
-// // //                             )
-// // //                             .await
-// // //                             {
-// // //                                 einfo!("Error handling deposit: {}", e);
-// // //                             }
-// // //                         }
-// // //                     }
-// // //                     Err(e) => {
-// // //                         info!("account subscription error: {:?}", e);
-// // //                         break;
-// // //                     }
-// // //                 }
-// // //             }
-// // //         }
-// // //     });
-
-// // //     Ok(())
-// // // }
-
-// // pub async fn read_account_updates(&self, account_pubkey: Pubkey) -> anyhow::Result<()> {
-// //     let url = "wss://api.devnet.solana.com/";
-// //     loop {
-// //         let (subscription, account_subscription_receiver) = PubsubClient::account_subscribe(
-// //             url,
-// //             &account_pubkey,
-// //             Some(RpcAccountInfoConfig {
-// //                 encoding: None,
-// //                 data_slice: None,
-// //                 commitment: Some(CommitmentConfig::confirmed()),
-// //                 min_context_slot: None,
-// //             }),
-// //         )?;
-// //         let _sub = subscription;
-
-// //         while let Ok(response) = account_subscription_receiver.recv() {
-// //             // Use async recv
-// //             if response.value.lamports > 0 {
-// //                 handle_deposit(
-// //                     &self.connection,
-// //                     &self.treasury,
-// //                     self.program_id,
-// //                     &self.redis,
-// //                     account_pubkey,
-// //                     response.value.lamports,
-// //                 )
-// //                 .await?;
-// //             }
-// //         }
-// //         // Reconnect if the WebSocket drops
-// //         tokio::time::sleep(Duration::from_secs(5)).await;
-// //     }
-// // }
+        let recent_blockhash = self.connection.get_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.treasury.pubkey()),
+            &[&self.treasury], // Only treasury signs
+            recent_blockhash,
+        );
+        let signature = self
+            .connection
+            .send_and_confirm_transaction(&transaction)
+            .await?;
+        info!(
+            "Forwarded {} lamports from {}: {}",
+            lamports, pda, signature
+        );
+        Ok(())
+    }
+}
@@ -1,44 +1,467 @@
-use alloy_network::TransactionBuilder;
-use alloy_primitives::{Address, U256};
-use alloy_provider::{Provider, ProviderBuilder};
-use alloy_rpc_types::TransactionRequest;
-use alloy_signer_local::PrivateKeySigner;
-use std::{env, str::FromStr};
+use alloy_consensus::Transaction;
+use alloy_network::{ReceiptResponse, TransactionBuilder, TransactionResponse};
+use alloy_primitives::{Address, PrimitiveSignature, TxHash, U256};
+use alloy_provider::{Provider, RootProvider};
+use alloy_rpc_types::{BlockNumberOrTag, Filter, TransactionRequest};
+use alloy_sol_types::{sol, SolCall, SolEvent};
+use anyhow::anyhow;
+use std::{collections::BTreeMap, env, str::FromStr};
+use tokio::sync::Mutex;
 
-pub async fn transfer_funds(to_address: &str, amount_in_eth: f64) -> anyhow::Result<String> {
-    let private_key = env::var("MONAD_ACCOUNT_PRIVATE_KEY").unwrap();
-    let wallet = PrivateKeySigner::from_str(&private_key)?;
-    let from_address = wallet.address();
-    let rpc_url = env::var("MONAD_RPC_URL").unwrap();
-    // Connect to an Ethereum node via RPC
-    let provider = ProviderBuilder::new()
-        .wallet(wallet)
-        .on_http(rpc_url.parse().unwrap());
+mod signer;
 
-    // Define the recipient address
-    let to_address = Address::from_str(to_address)?; // Replace with recipient address
+pub use signer::EvmSigner;
 
-    // Build a transaction to send 100 wei from Alice to Bob
-    let tx = TransactionRequest::default()
-        .with_from(from_address)
-        .with_to(to_address)
-        .with_value(U256::from((amount_in_eth * 10_u64.pow(18) as f64) as u64));
+sol! {
+    function transfer(address to, uint256 amount) returns (bool);
+    function decimals() returns (uint8);
+    function balanceOf(address owner) returns (uint256);
+    event Transfer(address indexed from, address indexed to, uint256 value);
+}
+
+/// What a transfer moves: the chain's native coin or an ERC-20 token
+#[derive(Clone, Copy)]
+pub enum Asset {
+    Native,
+    Erc20(Address),
+}
+
+impl Asset {
+    pub fn erc20(token_address: &str) -> anyhow::Result<Asset> {
+        Ok(Asset::Erc20(Address::from_str(token_address)?))
+    }
+}
+
+/// A signed treasury transfer that hasn't necessarily been sent yet
+pub struct SignedTransfer {
+    pub tx_hash: String,
+    // EIP-2718 encoded, hex
+    pub raw: String,
+    pub nonce: u64,
+}
 
-    // Send the transaction and listen for the transaction to be included.
-    let tx_hash = provider.send_transaction(tx).await?.watch().await?;
+/// A transfer into the treasury found by `scan_block`
+pub struct TreasuryDeposit {
+    pub tx_hash: String,
+    pub sender: String,
+    pub amount: u128,
+}
+
+/// A block and the treasury deposits in it, `parent_hash` links it to the block before
+pub struct ScannedBlock {
+    pub number: u64,
+    pub hash: String,
+    pub parent_hash: String,
+    pub deposits: Vec<TreasuryDeposit>,
+}
+
+pub struct TransferReceipt {
+    pub succeeded: bool,
+    pub confirmations: u64,
+}
+
+pub fn is_valid_address(address: &str) -> bool {
+    Address::from_str(address).is_ok()
+}
 
-    println!("Sent transaction: {tx_hash}");
+/// Whether `signature`, hex, is `address` signing `message` with personal_sign (EIP-191)
+pub fn is_signed_by(address: &str, message: &str, signature: &str) -> bool {
+    let (Ok(address), Ok(signature)) = (
+        Address::from_str(address),
+        PrimitiveSignature::from_str(signature),
+    ) else {
+        return false;
+    };
+    signature
+        .recover_address_from_msg(message)
+        .is_ok_and(|signer| signer == address)
+}
 
-    Ok(tx_hash.to_string())
+/// An EVM chain and the treasury account that pays out of it. Keep one per chain,
+/// it hands out the treasury's nonces.
+pub struct EvmChain {
+    provider: RootProvider,
+    treasury: EvmSigner,
+    // Next nonce to sign with, None until the first transfer
+    next_nonce: Mutex<Option<u64>>,
+}
+
+impl EvmChain {
+    pub fn new(rpc_url: &str, treasury: EvmSigner) -> anyhow::Result<Self> {
+        Ok(EvmChain {
+            provider: RootProvider::new_http(rpc_url.parse()?),
+            treasury,
+            next_nonce: Mutex::new(None),
+        })
+    }
+
+    /// Reads `<PREFIX>_RPC_URL` and the treasury signer (see `EvmSigner::from_env`),
+    /// e.g. MONAD_RPC_URL
+    pub fn from_env(prefix: &str) -> anyhow::Result<Self> {
+        let rpc_url_var = format!("{}_RPC_URL", prefix);
+        let rpc_url = env::var(&rpc_url_var).map_err(|_| anyhow!("{} is not set", rpc_url_var))?;
+        EvmChain::new(&rpc_url, EvmSigner::from_env(prefix)?)
+    }
+
+    pub fn treasury_address(&self) -> Address {
+        self.treasury.address()
+    }
+
+    fn provider(&self) -> &RootProvider {
+        &self.provider
+    }
+
+    // The nonce after the treasury's last signed transfer. Concurrent transfers each
+    // get their own, where asking the node for the pending nonce would hand out the
+    // same one until the first is broadcast.
+    async fn next_transfer_nonce(&self, next_nonce: Option<u64>) -> anyhow::Result<u64> {
+        let provider = self.provider();
+        let treasury = self.treasury_address();
+        let pending = provider.get_transaction_count(treasury).pending().await?;
+
+        Ok(match next_nonce {
+            Some(next) if next > pending => {
+                // Nothing is waiting in the mempool, so the transfers signed with the
+                // nonces in between were never sent or were dropped. Their nonces are
+                // reused, or no later transfer could ever be mined.
+                let mined = provider.get_transaction_count(treasury).latest().await?;
+                if pending == mined {
+                    pending
+                } else {
+                    next
+                }
+            }
+            _ => pending,
+        })
+    }
+
+    /// Decimals of the asset's smallest unit, 18 (wei) for the native coin
+    pub async fn decimals(&self, asset: Asset) -> anyhow::Result<u32> {
+        let Asset::Erc20(token) = asset else {
+            return Ok(18);
+        };
+        let output = self
+            .provider()
+            .call(
+                TransactionRequest::default()
+                    .with_to(token)
+                    .with_input(decimalsCall {}.abi_encode()),
+            )
+            .await?;
+        Ok(decimalsCall::abi_decode_returns(&output, true)?._0.into())
+    }
+
+    /// What the treasury holds of the asset, in its smallest unit
+    pub async fn treasury_balance(&self, asset: Asset) -> anyhow::Result<u128> {
+        let treasury = self.treasury_address();
+        let balance = match asset {
+            Asset::Native => self.provider().get_balance(treasury).await?,
+            Asset::Erc20(token) => {
+                let output = self
+                    .provider()
+                    .call(
+                        TransactionRequest::default()
+                            .with_to(token)
+                            .with_input(balanceOfCall { owner: treasury }.abi_encode()),
+                    )
+                    .await?;
+                balanceOfCall::abi_decode_returns(&output, true)?._0
+            }
+        };
+        Ok(u128::try_from(balance)?)
+    }
+
+    /// Signs a transfer of `amount` of the asset's smallest unit from the treasury
+    /// without sending it. Sending the same signed transfer again can't pay twice,
+    /// its nonce can only be used once.
+    pub async fn sign_transfer(
+        &self,
+        asset: Asset,
+        to_address: &str,
+        amount: u128,
+    ) -> anyhow::Result<SignedTransfer> {
+        let provider = self.provider();
+        let from_address = self.treasury_address();
+        let to_address = Address::from_str(to_address)?;
+
+        let tx = match asset {
+            Asset::Native => TransactionRequest::default()
+                .with_to(to_address)
+                .with_value(U256::from(amount))
+                .with_gas_limit(21_000),
+            Asset::Erc20(token) => {
+                let tx = TransactionRequest::default()
+                    .with_from(from_address)
+                    .with_to(token)
+                    .with_input(
+                        transferCall {
+                            to: to_address,
+                            amount: U256::from(amount),
+                        }
+                        .abi_encode(),
+                    );
+                let gas = provider.estimate_gas(tx.clone()).await?;
+                tx.with_gas_limit(gas)
+            }
+        };
+
+        // Held until signed, so nonces are handed out in signing order
+        let mut next_nonce = self.next_nonce.lock().await;
+        let nonce = self.next_transfer_nonce(*next_nonce).await?;
+        let fees = provider.estimate_eip1559_fees().await?;
+        let tx = tx
+            .with_from(from_address)
+            .with_nonce(nonce)
+            .with_chain_id(provider.get_chain_id().await?)
+            .with_max_fee_per_gas(fees.max_fee_per_gas)
+            .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+        let (tx_hash, raw) = self.treasury.sign(tx).await?;
+        *next_nonce = Some(nonce + 1);
+
+        Ok(SignedTransfer {
+            tx_hash: tx_hash.to_string(),
+            raw: hex::encode(raw),
+            nonce,
+        })
+    }
+
+    pub async fn broadcast_transfer(&self, raw: &str) -> anyhow::Result<()> {
+        // Inclusion is followed through `transfer_receipt`
+        let _pending = self
+            .provider()
+            .send_raw_transaction(&hex::decode(raw)?)
+            .await?;
+        Ok(())
+    }
+
+    /// Number of treasury transfers that are mined, i.e. the next unused nonce
+    pub async fn treasury_nonce(&self) -> anyhow::Result<u64> {
+        Ok(self
+            .provider()
+            .get_transaction_count(self.treasury_address())
+            .latest()
+            .await?)
+    }
+
+    /// Receipt of a mined transfer, None while it isn't mined
+    pub async fn transfer_receipt(&self, tx_hash: &str) -> anyhow::Result<Option<TransferReceipt>> {
+        let provider = self.provider();
+        let Some(receipt) = provider
+            .get_transaction_receipt(TxHash::from_str(tx_hash)?)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let Some(mined_at) = receipt.block_number() else {
+            return Ok(None);
+        };
+
+        Ok(Some(TransferReceipt {
+            succeeded: receipt.status(),
+            confirmations: provider.get_block_number().await?.saturating_sub(mined_at) + 1,
+        }))
+    }
+
+    pub async fn latest_block_number(&self) -> anyhow::Result<u64> {
+        Ok(self.provider().get_block_number().await?)
+    }
+
+    /// Finds the successful transfers of the asset into the treasury in block `number`,
+    /// summed per transaction and sender
+    pub async fn scan_block(&self, asset: Asset, number: u64) -> anyhow::Result<ScannedBlock> {
+        let provider = self.provider();
+        let treasury = self.treasury_address();
+        let mut request = provider.get_block_by_number(BlockNumberOrTag::Number(number));
+        if let Asset::Native = asset {
+            request = request.full();
+        }
+        let block = request
+            .await?
+            .ok_or_else(|| anyhow!("Block {} not found", number))?;
+        let block_hash = block.header.hash;
+
+        let mut deposits: BTreeMap<(TxHash, Address), U256> = BTreeMap::new();
+        match asset {
+            Asset::Native => {
+                for tx in block.transactions.txns() {
+                    if tx.to() != Some(treasury) || tx.value().is_zero() {
+                        continue;
+                    }
+                    // Reverted transfers move nothing
+                    let tx_hash = tx.tx_hash();
+                    let receipt = provider
+                        .get_transaction_receipt(tx_hash)
+                        .await?
+                        .ok_or_else(|| anyhow!("Transaction {} has no receipt", tx_hash))?;
+                    if receipt.status() && receipt.block_hash() == Some(block_hash) {
+                        *deposits.entry((tx_hash, tx.from())).or_default() += tx.value();
+                    }
+                }
+            }
+            Asset::Erc20(token) => {
+                // By hash, so the logs are from this very block even if it was replaced
+                let filter = Filter::new()
+                    .address(token)
+                    .event_signature(Transfer::SIGNATURE_HASH)
+                    .topic2(treasury.into_word())
+                    .at_block_hash(block_hash);
+                for log in provider.get_logs(&filter).await? {
+                    let (Some(tx_hash), Ok(transfer)) =
+                        (log.transaction_hash, log.log_decode::<Transfer>())
+                    else {
+                        continue;
+                    };
+                    let transfer = transfer.inner.data;
+                    *deposits.entry((tx_hash, transfer.from)).or_default() += transfer.value;
+                }
+            }
+        }
+
+        Ok(ScannedBlock {
+            number,
+            hash: block_hash.to_string(),
+            parent_hash: block.header.parent_hash.to_string(),
+            deposits: deposits
+                .into_iter()
+                .filter_map(|((tx_hash, sender), amount)| {
+                    Some(TreasuryDeposit {
+                        tx_hash: tx_hash.to_string(),
+                        sender: sender.to_string(),
+                        amount: u128::try_from(amount).ok()?,
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    /// Checks that `tx_hash` is a successful transfer of the asset with at least
+    /// `min_confirmations` from `sender` to the treasury, and returns how much of the
+    /// asset's smallest unit it moved.
+    pub async fn verify_deposit(
+        &self,
+        asset: Asset,
+        tx_hash: &str,
+        sender: &str,
+        min_confirmations: u64,
+    ) -> anyhow::Result<u128> {
+        let treasury = self.treasury_address().to_string();
+        self.verify_transfer(asset, tx_hash, sender, &treasury, min_confirmations)
+            .await
+    }
+
+    /// Like `verify_deposit`, for a transfer to `recipient` instead of the treasury
+    pub async fn verify_transfer(
+        &self,
+        asset: Asset,
+        tx_hash: &str,
+        sender: &str,
+        recipient: &str,
+        min_confirmations: u64,
+    ) -> anyhow::Result<u128> {
+        let sender = Address::from_str(sender)?;
+        let recipient = Address::from_str(recipient)?;
+        let receipt = self
+            .transfer_receipt(tx_hash)
+            .await?
+            .ok_or_else(|| anyhow!("Transaction {} is not mined yet", tx_hash))?;
+        if !receipt.succeeded {
+            return Err(anyhow!("Transaction {} reverted", tx_hash));
+        }
+        if receipt.confirmations < min_confirmations {
+            return Err(anyhow!(
+                "Transaction {} has {} of {} confirmations",
+                tx_hash,
+                receipt.confirmations,
+                min_confirmations
+            ));
+        }
+
+        let value = match asset {
+            Asset::Native => self.native_deposit(tx_hash, sender, recipient).await?,
+            Asset::Erc20(token) => {
+                self.token_deposit(token, tx_hash, sender, recipient)
+                    .await?
+            }
+        };
+        if value.is_zero() {
+            return Err(anyhow!(
+                "Transaction {} is not a transfer from {} to {}",
+                tx_hash,
+                sender,
+                recipient
+            ));
+        }
+        u128::try_from(value).map_err(|_| anyhow!("Transaction {} value overflows", tx_hash))
+    }
+
+    async fn native_deposit(
+        &self,
+        tx_hash: &str,
+        sender: Address,
+        recipient: Address,
+    ) -> anyhow::Result<U256> {
+        let tx = self
+            .provider()
+            .get_transaction_by_hash(TxHash::from_str(tx_hash)?)
+            .await?
+            .ok_or_else(|| anyhow!("Transaction {} not found", tx_hash))?;
+        if tx.from() != sender || tx.to() != Some(recipient) {
+            return Ok(U256::ZERO);
+        }
+        Ok(tx.value())
+    }
+
+    // Sums the token's Transfer events from the sender to the recipient
+    async fn token_deposit(
+        &self,
+        token: Address,
+        tx_hash: &str,
+        sender: Address,
+        recipient: Address,
+    ) -> anyhow::Result<U256> {
+        let receipt = self
+            .provider()
+            .get_transaction_receipt(TxHash::from_str(tx_hash)?)
+            .await?
+            .ok_or_else(|| anyhow!("Transaction {} is not mined yet", tx_hash))?;
+
+        let mut value = U256::ZERO;
+        for log in receipt.inner.logs() {
+            if log.address() != token {
+                continue;
+            }
+            let Ok(transfer) = log.log_decode::<Transfer>() else {
+                continue;
+            };
+            let transfer = transfer.inner.data;
+            if transfer.from == sender && transfer.to == recipient {
+                value = value
+                    .checked_add(transfer.value)
+                    .ok_or_else(|| anyhow!("Transaction {} value overflows", tx_hash))?;
+            }
+        }
+        Ok(value)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloy_signer::SignerSync;
+    use alloy_signer_local::PrivateKeySigner;
 
-    #[tokio::test]
-    async fn test_transfer_funds() -> anyhow::Result<()> {
-        transfer_funds("0x0BF493537Fa5b08836d7AE8750CFEA682a0f190C", 0.01).await?;
-        Ok(())
+    #[test]
+    fn personal_signatures_recover_their_signer() {
+        let signer = PrivateKeySigner::random();
+        let address = signer.address().to_string();
+        let signature = signer.sign_message_sync(b"link").unwrap();
+        let signature = format!("0x{}", hex::encode(signature.as_bytes()));
+
+        assert!(is_signed_by(&address, "link", &signature));
+        assert!(is_signed_by(&address.to_lowercase(), "link", &signature));
+        assert!(!is_signed_by(&address, "other", &signature));
+        let other = PrivateKeySigner::random().address().to_string();
+        assert!(!is_signed_by(&other, "link", &signature));
+        assert!(!is_signed_by(&address, "link", "0x00"));
     }
 }
@@ -0,0 +1,71 @@
+use alloy_network::{eip2718::Encodable2718, EthereumWallet, TransactionBuilder};
+use alloy_primitives::{keccak256, Address, Bytes, TxHash};
+use alloy_provider::{Provider, RootProvider};
+use alloy_rpc_types::TransactionRequest;
+use alloy_signer_local::PrivateKeySigner;
+use anyhow::anyhow;
+use std::{env, str::FromStr};
+
+/// Signs the treasury's transactions. The key is loaded once, when it's built.
+pub enum EvmSigner {
+    /// A key held in memory, given directly or decrypted from a keystore file
+    Local(PrivateKeySigner),
+    /// An external signer answering `eth_signTransaction` for `address`, e.g. Web3Signer
+    /// or Clef, so the key never enters this process
+    Remote {
+        provider: RootProvider,
+        address: Address,
+    },
+}
+
+impl EvmSigner {
+    /// Reads the first configured of, e.g. for MONAD:
+    /// - MONAD_ACCOUNT_PRIVATE_KEY
+    /// - MONAD_KEYSTORE_PATH and MONAD_KEYSTORE_PASSWORD
+    /// - MONAD_REMOTE_SIGNER_URL and MONAD_TREASURY_ADDRESS
+    pub fn from_env(prefix: &str) -> anyhow::Result<Self> {
+        let var = |name: &str| env::var(format!("{}_{}", prefix, name)).ok();
+        let missing = |name: &str| anyhow!("{}_{} is not set", prefix, name);
+
+        if let Some(private_key) = var("ACCOUNT_PRIVATE_KEY") {
+            return Ok(EvmSigner::Local(PrivateKeySigner::from_str(&private_key)?));
+        }
+        if let Some(path) = var("KEYSTORE_PATH") {
+            let password = var("KEYSTORE_PASSWORD").ok_or_else(|| missing("KEYSTORE_PASSWORD"))?;
+            let signer = PrivateKeySigner::decrypt_keystore(&path, password)
+                .map_err(|e| anyhow!("Failed to decrypt {}: {}", path, e))?;
+            return Ok(EvmSigner::Local(signer));
+        }
+        if let Some(url) = var("REMOTE_SIGNER_URL") {
+            let address = var("TREASURY_ADDRESS").ok_or_else(|| missing("TREASURY_ADDRESS"))?;
+            return Ok(EvmSigner::Remote {
+                provider: RootProvider::new_http(url.parse()?),
+                address: Address::from_str(&address)?,
+            });
+        }
+        Err(missing("ACCOUNT_PRIVATE_KEY"))
+    }
+
+    pub fn address(&self) -> Address {
+        match self {
+            EvmSigner::Local(signer) => signer.address(),
+            EvmSigner::Remote { address, .. } => *address,
+        }
+    }
+
+    /// Signs a complete transaction, returns its hash and EIP-2718 encoding
+    pub async fn sign(&self, tx: TransactionRequest) -> anyhow::Result<(TxHash, Vec<u8>)> {
+        match self {
+            EvmSigner::Local(signer) => {
+                let envelope = tx.build(&EthereumWallet::from(signer.clone())).await?;
+                Ok((*envelope.tx_hash(), envelope.encoded_2718()))
+            }
+            EvmSigner::Remote { provider, .. } => {
+                let raw: Bytes = provider
+                    .raw_request("eth_signTransaction".into(), (tx,))
+                    .await?;
+                Ok((keccak256(&raw), raw.to_vec()))
+            }
+        }
+    }
+}
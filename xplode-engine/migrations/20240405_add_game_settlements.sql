@@ -0,0 +1,22 @@
+-- One row per settled game, the primary key keeps settlement idempotent
+CREATE TABLE game_settlements (
+    game_id TEXT PRIMARY KEY,
+    currency TEXT NOT NULL,
+    loser_id INTEGER NOT NULL,
+    single_bet_size DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Every balance change a settlement made
+CREATE TABLE game_settlement_legs (
+    id SERIAL PRIMARY KEY,
+    game_id TEXT NOT NULL REFERENCES game_settlements (game_id),
+    user_id INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    balance_before DOUBLE PRECISION NOT NULL,
+    balance_after DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_game_settlement_legs_user ON game_settlement_legs (user_id, currency);
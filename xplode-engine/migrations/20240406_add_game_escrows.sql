@@ -0,0 +1,27 @@
+-- Part of the balance held as stakes of games in progress
+ALTER TABLE wallet
+ADD COLUMN reserved DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+CREATE TABLE game_escrows (
+    game_id TEXT NOT NULL,
+    round INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    -- HELD, then RELEASED or SETTLED
+    status TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (game_id, round, user_id)
+);
+
+-- Rematches reuse the game id, so settlements are kept per round
+ALTER TABLE game_settlement_legs DROP CONSTRAINT game_settlement_legs_game_id_fkey;
+ALTER TABLE game_settlements DROP CONSTRAINT game_settlements_pkey;
+
+ALTER TABLE game_settlements ADD COLUMN round INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE game_settlements ADD PRIMARY KEY (game_id, round);
+
+ALTER TABLE game_settlement_legs ADD COLUMN round INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE game_settlement_legs
+ADD FOREIGN KEY (game_id, round) REFERENCES game_settlements (game_id, round);
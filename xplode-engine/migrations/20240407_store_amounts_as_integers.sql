@@ -0,0 +1,59 @@
+-- Money is stored as a BIGINT of the currency's smallest ledger unit:
+-- INR 2 decimals, SOL 9 (lamports), USDC 6, MON 9 (gwei)
+CREATE FUNCTION pg_temp.to_units(amount DOUBLE PRECISION, currency TEXT) RETURNS BIGINT AS $$
+    SELECT ROUND(amount::NUMERIC * 10 ^ CASE currency
+        WHEN 'INR' THEN 2
+        WHEN 'USDC' THEN 6
+        ELSE 9
+    END)::BIGINT
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- The leaderboard views depend on the profit columns
+DROP VIEW IF EXISTS leaderboard_24h;
+DROP VIEW IF EXISTS leaderboard_all_time;
+
+ALTER TABLE wallet
+    ALTER COLUMN balance TYPE BIGINT USING pg_temp.to_units(balance, currency),
+    ALTER COLUMN reserved TYPE BIGINT USING pg_temp.to_units(reserved, currency);
+
+ALTER TABLE transactions
+    ALTER COLUMN amount TYPE BIGINT USING pg_temp.to_units(amount, currency);
+
+ALTER TABLE game_pnl
+    ALTER COLUMN profit TYPE BIGINT USING pg_temp.to_units(profit, currency);
+
+ALTER TABLE user_network_pnl
+    ALTER COLUMN total_profit TYPE BIGINT USING pg_temp.to_units(total_profit, currency);
+
+ALTER TABLE game_escrows
+    ALTER COLUMN amount TYPE BIGINT USING pg_temp.to_units(amount, currency);
+
+ALTER TABLE game_settlements
+    ALTER COLUMN single_bet_size TYPE BIGINT USING pg_temp.to_units(single_bet_size, currency);
+
+ALTER TABLE game_settlement_legs
+    ALTER COLUMN amount TYPE BIGINT USING pg_temp.to_units(amount, currency),
+    ALTER COLUMN balance_before TYPE BIGINT USING pg_temp.to_units(balance_before, currency),
+    ALTER COLUMN balance_after TYPE BIGINT USING pg_temp.to_units(balance_after, currency);
+
+CREATE VIEW leaderboard_24h AS
+SELECT 
+    u.name,
+    g.currency,
+    COUNT(*)::INT8 as total_matches,
+    SUM(g.profit)::INT8 as total_profit,
+    RANK() OVER (PARTITION BY g.currency ORDER BY SUM(g.profit) DESC)::INT8 as rank
+FROM game_pnl g
+JOIN users u ON g.user_id = u.id
+WHERE g.created_at >= NOW() - INTERVAL '24 hours'
+GROUP BY u.name, g.currency;
+
+CREATE VIEW leaderboard_all_time AS
+SELECT 
+    u.name,
+    p.currency,
+    p.total_profit::INT8,
+    p.total_matches::INT8,
+    RANK() OVER (PARTITION BY p.currency ORDER BY p.total_profit DESC)::INT8 as rank
+FROM user_network_pnl p
+JOIN users u ON p.user_id = u.id;
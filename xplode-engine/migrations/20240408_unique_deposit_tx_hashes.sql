@@ -0,0 +1,3 @@
+-- An on-chain transfer can only be credited once
+CREATE UNIQUE INDEX transactions_deposit_tx_hash ON transactions (tx_hash)
+WHERE tx_type = 'DEPOSIT';
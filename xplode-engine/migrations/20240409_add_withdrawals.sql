@@ -0,0 +1,18 @@
+-- PENDING reserves the amount, BROADCAST stores the signed transfer before it's
+-- sent, CONFIRMED debits the balance and FAILED gives the reservation back
+CREATE TABLE withdrawals (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    withdraw_address TEXT NOT NULL,
+    status TEXT NOT NULL,
+    tx_hash TEXT,
+    signed_tx TEXT,
+    valid_until BIGINT,
+    error TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX withdrawals_status ON withdrawals (status);
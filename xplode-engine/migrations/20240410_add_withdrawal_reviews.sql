@@ -0,0 +1,6 @@
+-- Withdrawals above the review threshold or past the treasury's daily outflow cap
+-- wait in REVIEW until an admin approves or rejects them
+ALTER TABLE withdrawals ADD COLUMN review_reason TEXT;
+
+CREATE INDEX transactions_user_currency_type_created_at
+ON transactions (user_id, currency, tx_type, created_at);
@@ -0,0 +1,3 @@
+-- One wallet per user and currency, so a wallet for a newly added currency
+-- (e.g. ETH on Arbitrum) can be created on demand without duplicates
+CREATE UNIQUE INDEX wallet_user_currency ON wallet (user_id, currency);
@@ -0,0 +1,10 @@
+-- PDA deposit addresses, formerly the Redis deposit_addresses hash. `seed` is the key
+-- the PDA is derived from, `last_signature` the newest one the watcher has credited.
+CREATE TABLE deposit_addresses (
+    user_pda TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    seed TEXT NOT NULL,
+    last_signature TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,11 @@
+-- Last block a chain watcher has processed, e.g. 'MONAD:MON'. The hash detects
+-- when that block was replaced by a reorg.
+CREATE TABLE chain_cursors (
+    name TEXT PRIMARY KEY,
+    block_number BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Deposits are matched to users by the address they were sent from
+CREATE INDEX idx_wallet_address ON wallet (currency, LOWER(wallet_address));
@@ -0,0 +1,26 @@
+-- Signed proof-of-reserves reports: a Merkle-sum root over the users' balances of a
+-- currency, next to what the treasury held when it was taken
+CREATE TABLE reserve_reports (
+    id SERIAL PRIMARY KEY,
+    currency VARCHAR(10) NOT NULL,
+    root_hash TEXT NOT NULL,
+    liabilities BIGINT NOT NULL,
+    reserves BIGINT NOT NULL,
+    signer TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_reserve_reports_currency ON reserve_reports (currency, id DESC);
+
+-- The report's leaves, in tree order, so users can fetch their inclusion proof
+CREATE TABLE reserve_report_leaves (
+    report_id INTEGER NOT NULL REFERENCES reserve_reports(id) ON DELETE CASCADE,
+    leaf_index INTEGER NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    balance BIGINT NOT NULL,
+    salt TEXT NOT NULL,
+    PRIMARY KEY (report_id, leaf_index)
+);
+
+CREATE INDEX idx_reserve_report_leaves_user ON reserve_report_leaves (report_id, user_id);
@@ -0,0 +1,60 @@
+-- Double-entry ledger. The postings of an entry sum to zero, and a user's balance
+-- is the sum of the postings to their USER account. wallet.balance caches it.
+CREATE TABLE journal_entries (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    -- What the entry is for, e.g. the deposit's tx hash or 'game:round'
+    reference TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (kind, reference)
+);
+
+CREATE TABLE postings (
+    id BIGSERIAL PRIMARY KEY,
+    entry_id BIGINT NOT NULL REFERENCES journal_entries (id),
+    account TEXT NOT NULL,
+    user_id INTEGER REFERENCES users (id),
+    amount BIGINT NOT NULL,
+    CHECK ((account = 'USER') = (user_id IS NOT NULL))
+);
+
+CREATE INDEX idx_postings_entry ON postings (entry_id);
+CREATE INDEX idx_postings_user ON postings (user_id) WHERE user_id IS NOT NULL;
+
+-- Stakes of games in progress were kept in the balance and reserved, they now move
+-- out of the balance to the ESCROW account
+UPDATE wallet w
+SET balance = w.balance - held.amount, reserved = w.reserved - held.amount
+FROM (
+    SELECT user_id, currency, SUM(amount) AS amount FROM game_escrows
+    WHERE status = 'HELD'
+    GROUP BY user_id, currency
+) held
+WHERE w.user_id = held.user_id AND w.currency = held.currency;
+
+-- Today's balances open the ledger, one entry per currency
+INSERT INTO journal_entries (kind, reference, currency)
+SELECT 'OPENING', currency, currency FROM wallet
+UNION
+SELECT 'OPENING', currency, currency FROM game_escrows WHERE status = 'HELD';
+
+INSERT INTO postings (entry_id, account, user_id, amount)
+SELECT e.id, 'USER', w.user_id, w.balance
+FROM wallet w
+JOIN journal_entries e ON e.kind = 'OPENING' AND e.currency = w.currency
+WHERE w.balance <> 0;
+
+INSERT INTO postings (entry_id, account, amount)
+SELECT e.id, 'ESCROW', SUM(g.amount)
+FROM game_escrows g
+JOIN journal_entries e ON e.kind = 'OPENING' AND e.currency = g.currency
+WHERE g.status = 'HELD'
+GROUP BY e.id;
+
+INSERT INTO postings (entry_id, account, amount)
+SELECT e.id, 'OPENING', -SUM(p.amount)
+FROM journal_entries e
+JOIN postings p ON p.entry_id = e.id
+WHERE e.kind = 'OPENING'
+GROUP BY e.id;
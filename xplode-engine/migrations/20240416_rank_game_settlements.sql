@@ -0,0 +1,6 @@
+-- Games with lives are ranked rather than lost by a single player. loser_id keeps
+-- the player in last place, when a single one is.
+ALTER TABLE game_settlements ALTER COLUMN loser_id DROP NOT NULL;
+
+-- Finishing place of the leg's player, 1 for first. Tied players share a place.
+ALTER TABLE game_settlement_legs ADD COLUMN place INTEGER;
@@ -0,0 +1,3 @@
+-- How the settlement paid out, PLACEMENT steps profits down the ranking and
+-- SURVIVOR hands every stake below first place to the winners
+ALTER TABLE game_settlements ADD COLUMN payout TEXT NOT NULL DEFAULT 'PLACEMENT';
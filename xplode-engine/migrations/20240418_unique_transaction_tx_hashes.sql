@@ -0,0 +1,6 @@
+-- An on-chain transfer pays for one thing only, whatever it was recorded as. A mint
+-- paid into the treasury could also be credited as a deposit, such pairs have to be
+-- settled by hand before this applies:
+--   SELECT tx_hash FROM transactions GROUP BY tx_hash HAVING COUNT(*) > 1;
+DROP INDEX transactions_deposit_tx_hash;
+CREATE UNIQUE INDEX transactions_tx_hash ON transactions (tx_hash);
@@ -0,0 +1,13 @@
+-- Settlements and stake releases the game servers still owe. Each is queued before
+-- it's tried and deleted once it went through, the servers retry whatever is left,
+-- so a failure can't leave stakes HELD in game_escrows.
+CREATE TABLE pending_escrow_actions (
+    id SERIAL PRIMARY KEY,
+    game_id TEXT NOT NULL,
+    round INTEGER NOT NULL,
+    -- JSON of common::db::EscrowAction
+    action TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
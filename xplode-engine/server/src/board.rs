@@ -2,47 +2,348 @@ use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use crate::seed_gen::get_bomb_coords;
+use crate::seed_gen::{get_bomb_coords, ServerSeed};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CellState {
     Mined,
     Hidden,
     Bomb,
+    // A safe cell of a classic board, with how many of its neighbors are bombs
+    Revealed(u8),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How much mining a safe cell reveals, chosen by the game's creator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum BoardMode {
+    // Just the cell, without a hint
+    #[default]
+    Standard,
+    // The cell's count of adjacent bombs, and the whole region around it when the
+    // count is zero, like classic minesweeper
+    Classic,
+}
+
+impl std::fmt::Display for BoardMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::str::FromStr for BoardMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Standard" => Ok(BoardMode::Standard),
+            "Classic" => Ok(BoardMode::Classic),
+            _ => Err(anyhow::anyhow!("Invalid board mode: {}", s)),
+        }
+    }
+}
+
+/// How the bombs are spread over the board, drawn from the game's seeds like the
+/// bombs themselves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BombLayout {
+    // Every cell is equally likely
+    #[default]
+    Uniform,
+    // Blobs of adjacent bombs
+    Clustered,
+    // Short horizontal and vertical runs
+    Lines,
+    // Sparse along one edge, getting denser towards the opposite one
+    Gradient,
+}
+
+impl std::fmt::Display for BombLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::str::FromStr for BombLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Uniform" => Ok(BombLayout::Uniform),
+            "Clustered" => Ok(BombLayout::Clustered),
+            "Lines" => Ok(BombLayout::Lines),
+            "Gradient" => Ok(BombLayout::Gradient),
+            _ => Err(anyhow::anyhow!("Invalid bomb layout: {}", s)),
+        }
+    }
+}
+
+/// How cells connect to their neighbors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Topology {
+    // Up to 8 neighbors, edges are walls
+    #[default]
+    Square,
+    // Odd rows are shifted half a cell right, each cell has up to 6 neighbors
+    Hex,
+    // Like Square, but the edges wrap around to the opposite side
+    Torus,
+}
+
+impl std::fmt::Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::str::FromStr for Topology {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Square" => Ok(Topology::Square),
+            "Hex" => Ok(Topology::Hex),
+            "Torus" => Ok(Topology::Torus),
+            _ => Err(anyhow::anyhow!("Invalid topology: {}", s)),
+        }
+    }
+}
+
+/// Shape of a board: `height` rows of `width` cells. Cell (x, y) is column y of
+/// row x, and its bomb coordinate is `x * width + y`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub width: usize,
+    pub height: usize,
+    #[serde(default)]
+    pub topology: Topology,
+}
+
+impl Geometry {
+    pub fn square(n: usize) -> Geometry {
+        Geometry {
+            width: n,
+            height: n,
+            topology: Topology::Square,
+        }
+    }
+
+    pub fn cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn position(&self, x: usize, y: usize) -> u64 {
+        (x * self.width + y) as u64
+    }
+
+    pub fn coordinates(&self, position: u64) -> (usize, usize) {
+        let width = self.width as u64;
+        ((position / width) as usize, (position % width) as usize)
+    }
+
+    // The cells adjacent to (x, y), each listed once
+    pub fn neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let (x, y) = (x as i64, y as i64);
+        let offsets: &[(i64, i64)] = match self.topology {
+            Topology::Square | Topology::Torus => &[
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ],
+            // Even rows touch the cell above-left, odd rows the one above-right
+            Topology::Hex if x % 2 == 0 => &[(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)],
+            Topology::Hex => &[(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)],
+        };
+        let (height, width) = (self.height as i64, self.width as i64);
+
+        let mut neighbors = Vec::with_capacity(offsets.len());
+        for &(dx, dy) in offsets {
+            let (mut nx, mut ny) = (x + dx, y + dy);
+            if self.topology == Topology::Torus {
+                nx = nx.rem_euclid(height);
+                ny = ny.rem_euclid(width);
+            }
+            let cell = (nx as usize, ny as usize);
+            let on_board = (0..height).contains(&nx) && (0..width).contains(&ny);
+            // Small tori wrap onto the same cell from several sides
+            if on_board && cell != (x as usize, y as usize) && !neighbors.contains(&cell) {
+                neighbors.push(cell);
+            }
+        }
+        neighbors
+    }
+}
+
+// Compact form used in matchmaking keys, e.g. 8x6:Hex
+impl std::fmt::Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}:{}", self.width, self.height, self.topology)
+    }
+}
+
+impl std::str::FromStr for Geometry {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("Invalid geometry: {}", s);
+        let (size, topology) = s.split_once(':').ok_or_else(invalid)?;
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        Ok(Geometry {
+            width: width.parse()?,
+            height: height.parse()?,
+            topology: topology.parse()?,
+        })
+    }
+}
+
+/// What a single mine did to the board
+#[derive(Debug, Clone, PartialEq)]
+pub struct MineOutcome {
+    pub bomb: bool,
+    // Every cell this move uncovered, the mined cell first
+    pub revealed: Vec<(usize, usize)>,
+}
+
+// Not Serialize on purpose, clients only ever receive a BoardView
+#[derive(Debug, Clone, Deserialize)]
 pub struct Board {
-    pub n: usize, // it would be nXn
+    pub geometry: Geometry,
+    #[serde(default)]
+    pub mode: BoardMode,
+    #[serde(default)]
+    pub layout: BombLayout,
     grid: Vec<Vec<CellState>>,
-    //TODO: It should be either continuous or scattered
+    pub bomb_coordinates: Vec<u64>,
+}
+
+/// What a player is allowed to see of a board
+#[derive(Debug, Clone, Serialize)]
+pub struct BoardView {
+    pub geometry: Geometry,
+    pub mode: BoardMode,
+    pub layout: BombLayout,
+    pub grid: Vec<Vec<CellState>>,
+    // Empty until the game is over
     pub bomb_coordinates: Vec<u64>,
 }
 
 impl Board {
-    pub fn new(n: usize, bombs: usize) -> Board {
-        let bomb_coords = get_bomb_coords(bombs, n as u64);
+    pub fn new(
+        geometry: Geometry,
+        bombs: usize,
+        mode: BoardMode,
+        layout: BombLayout,
+        server_seed: &ServerSeed,
+        client_seeds: &[String],
+    ) -> Board {
+        let bomb_coords = get_bomb_coords(bombs, &geometry, layout, server_seed, client_seeds);
 
         Board {
-            n,
-            grid: vec![vec![CellState::Hidden; n]; n],
+            geometry,
+            mode,
+            layout,
+            grid: vec![vec![CellState::Hidden; geometry.width]; geometry.height],
             bomb_coordinates: bomb_coords,
         }
     }
 
-    pub fn mine(&mut self, x: usize, y: usize) -> bool {
-        let position = x * self.n + y;
-        if self.bomb_coordinates.contains(&(position as u64)) {
+    // Placeholder shown while players are still joining, bombs are placed on start
+    pub fn hidden(geometry: Geometry, mode: BoardMode, layout: BombLayout) -> Board {
+        Board {
+            geometry,
+            mode,
+            layout,
+            grid: vec![vec![CellState::Hidden; geometry.width]; geometry.height],
+            bomb_coordinates: vec![],
+        }
+    }
+
+    pub fn view(&self, reveal_bombs: bool) -> BoardView {
+        BoardView {
+            geometry: self.geometry,
+            mode: self.mode,
+            layout: self.layout,
+            grid: self.grid.clone(),
+            bomb_coordinates: if reveal_bombs {
+                self.bomb_coordinates.clone()
+            } else {
+                vec![]
+            },
+        }
+    }
+
+    // None when (x, y) is off the board
+    pub fn cell(&self, x: usize, y: usize) -> Option<&CellState> {
+        self.grid.get(x)?.get(y)
+    }
+
+    pub fn hidden_cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+        for (x, row) in self.grid.iter().enumerate() {
+            for (y, cell) in row.iter().enumerate() {
+                if matches!(cell, CellState::Hidden) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    fn is_bomb(&self, x: usize, y: usize) -> bool {
+        self.bomb_coordinates
+            .contains(&self.geometry.position(x, y))
+    }
+
+    fn adjacent_bombs(&self, x: usize, y: usize) -> u8 {
+        self.geometry
+            .neighbors(x, y)
+            .into_iter()
+            .filter(|&(nx, ny)| self.is_bomb(nx, ny))
+            .count() as u8
+    }
+
+    pub fn mine(&mut self, x: usize, y: usize) -> MineOutcome {
+        if self.is_bomb(x, y) {
             self.grid[x][y] = CellState::Bomb;
-            true // true means bomb
-        } else {
+            return MineOutcome {
+                bomb: true,
+                revealed: vec![(x, y)],
+            };
+        }
+        if self.mode == BoardMode::Standard {
             self.grid[x][y] = CellState::Mined;
-            false
+            return MineOutcome {
+                bomb: false,
+                revealed: vec![(x, y)],
+            };
+        }
+
+        // Flood fill, a cell without adjacent bombs also reveals its neighbors
+        let mut revealed = Vec::new();
+        let mut pending = vec![(x, y)];
+        while let Some((cx, cy)) = pending.pop() {
+            if !matches!(self.grid[cx][cy], CellState::Hidden) {
+                continue;
+            }
+            let count = self.adjacent_bombs(cx, cy);
+            self.grid[cx][cy] = CellState::Revealed(count);
+            revealed.push((cx, cy));
+            if count == 0 {
+                pending.extend(self.geometry.neighbors(cx, cy));
+            }
+        }
+        MineOutcome {
+            bomb: false,
+            revealed,
         }
     }
 
     pub fn display(&self) {
-        info!("╔{}╗", "═".repeat(self.n * 5));
+        info!("╔{}╗", "═".repeat(self.geometry.width * 5));
         for (row_idx, row) in self.grid.iter().enumerate() {
             // Start of row
             print!("║ ");
@@ -62,11 +363,14 @@ impl Board {
 
                         print!("{:<3} ", "💣".yellow());
                     }
+                    CellState::Revealed(count) => {
+                        print!("{:<3} ", count.to_string().green());
+                    }
                 }
             }
 
             // Row number on the right side
-            if row_idx == self.n - 1 {
+            if row_idx == self.geometry.height - 1 {
                 info!("║ {}", row_idx)
             } else {
                 info!("║ {}\n\n", row_idx);
@@ -74,11 +378,113 @@ impl Board {
         }
 
         // Bottom border with column indices
-        print!("╚{}╝\n  ", "═".repeat(self.n * 5));
+        print!("╚{}╝\n  ", "═".repeat(self.geometry.width * 5));
 
         // Column indices
-        for col in 0..self.n {
+        for col in 0..self.geometry.width {
             print!("{:<3} ", col);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bombs at (0, 0) and (0, 3) of a 4x4 board
+    fn classic_board() -> Board {
+        Board {
+            bomb_coordinates: vec![0, 3],
+            ..Board::hidden(Geometry::square(4), BoardMode::Classic, BombLayout::Uniform)
+        }
+    }
+
+    #[test]
+    fn classic_cells_count_adjacent_bombs() {
+        let mut board = classic_board();
+        let outcome = board.mine(1, 1);
+
+        assert!(!outcome.bomb);
+        assert_eq!(outcome.revealed, vec![(1, 1)]);
+        assert_eq!(board.cell(1, 1), Some(&CellState::Revealed(1)));
+        assert!(board.mine(0, 0).bomb);
+    }
+
+    #[test]
+    fn classic_zero_regions_flood_fill() {
+        let mut board = classic_board();
+        let outcome = board.mine(3, 0);
+
+        // Everything but the top row is reached, it borders the bombs
+        assert_eq!(outcome.revealed.len(), 12);
+        assert_eq!(board.cell(3, 3), Some(&CellState::Revealed(0)));
+        assert_eq!(board.cell(1, 0), Some(&CellState::Revealed(1)));
+        assert_eq!(board.cell(0, 1), Some(&CellState::Hidden));
+        assert_eq!(board.hidden_cells(), vec![(0, 0), (0, 1), (0, 2), (0, 3)]);
+    }
+
+    #[test]
+    fn standard_boards_reveal_only_the_mined_cell() {
+        let mut board = Board {
+            mode: BoardMode::Standard,
+            ..classic_board()
+        };
+        let outcome = board.mine(3, 0);
+
+        assert_eq!(outcome.revealed, vec![(3, 0)]);
+        assert_eq!(board.cell(3, 0), Some(&CellState::Mined));
+        assert_eq!(board.hidden_cells().len(), 15);
+    }
+
+    #[test]
+    fn rectangular_boards_index_rows_by_width() {
+        let geometry = Geometry {
+            width: 5,
+            height: 2,
+            topology: Topology::Square,
+        };
+        assert_eq!(geometry.position(1, 3), 8);
+        assert_eq!(geometry.coordinates(8), (1, 3));
+        assert_eq!("5x2:Square".parse::<Geometry>().unwrap(), geometry);
+
+        let mut board = Board {
+            bomb_coordinates: vec![8],
+            ..Board::hidden(geometry, BoardMode::Classic, BombLayout::Uniform)
+        };
+        assert_eq!(board.cell(1, 4), Some(&CellState::Hidden));
+        assert_eq!(board.cell(2, 0), None);
+        assert!(board.mine(1, 3).bomb);
+        assert_eq!(board.mine(0, 0).revealed.len(), 6);
+    }
+
+    #[test]
+    fn topologies_choose_the_neighbors() {
+        let hex = Geometry {
+            topology: Topology::Hex,
+            ..Geometry::square(4)
+        };
+        assert_eq!(
+            hex.neighbors(1, 1),
+            vec![(0, 1), (0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]
+        );
+        assert_eq!(hex.neighbors(0, 0), vec![(0, 1), (1, 0)]);
+
+        let torus = Geometry {
+            topology: Topology::Torus,
+            ..Geometry::square(4)
+        };
+        let corner = torus.neighbors(0, 0);
+        assert_eq!(corner.len(), 8);
+        assert!(corner.contains(&(3, 3)) && corner.contains(&(0, 3)));
+        // Each side of a 2x2 torus is the same cell
+        assert_eq!(
+            Geometry {
+                topology: Topology::Torus,
+                ..Geometry::square(2)
+            }
+            .neighbors(0, 0)
+            .len(),
+            3
+        );
+    }
+}
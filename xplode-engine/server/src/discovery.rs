@@ -1,17 +1,46 @@
 use anyhow::Result;
+use common::utils::{Amount, Currency};
 use redis::{AsyncCommands, Client};
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Instant};
+use std::{str::FromStr, sync::Arc, time::Instant};
 use tracing::{info, warn};
 
+use crate::{
+    board::{BoardMode, BombLayout, Geometry},
+    game::Elimination,
+};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameSession {
     pub game_id: String,
     pub server_id: String, // This will be machine_id if available, otherwise UUID
-    pub single_bet_size: f64,
+    pub currency: Currency,
+    pub single_bet_size: Amount,
     pub min_players: u32,
     pub current_players: u32,
-    pub grid_size: u32,
+    pub geometry: Geometry,
+    pub board_mode: BoardMode,
+    pub bomb_layout: BombLayout,
+    // Lives per player, None when the first bomb ends the game
+    pub lives: Option<u32>,
+    // Last-one-standing rules, None for a game without them
+    pub elimination: Option<Elimination>,
+}
+
+// Empty when the setting is off
+fn optional_field<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn parse_optional<T>(field: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field.parse().map(Some).map_err(Into::into)
 }
 
 #[derive(Clone)]
@@ -43,17 +72,32 @@ impl DiscoveryService {
             &key,
             &[
                 ("server_id", session.server_id.clone()),
-                ("single_bet_size", session.single_bet_size.to_string()),
+                ("currency", session.currency.to_string()),
+                (
+                    "single_bet_size",
+                    session.single_bet_size.units().to_string(),
+                ),
                 ("min_players", session.min_players.to_string()),
                 ("current_players", session.current_players.to_string()),
-                ("grid_size", session.grid_size.to_string()),
+                ("geometry", session.geometry.to_string()),
+                ("board_mode", session.board_mode.to_string()),
+                ("bomb_layout", session.bomb_layout.to_string()),
+                ("lives", optional_field(session.lives)),
+                ("elimination", optional_field(session.elimination)),
             ],
         );
 
         // Add to matchmaking set
         let matchmaking_key = format!(
-            "matchmaking:{}:{}:{}",
-            session.single_bet_size, session.min_players, session.grid_size
+            "matchmaking:{}:{}:{}:{}:{}:{}:{}:{}",
+            session.currency,
+            session.single_bet_size.units(),
+            session.min_players,
+            session.geometry,
+            session.board_mode,
+            session.bomb_layout,
+            optional_field(session.lives),
+            optional_field(session.elimination)
         );
         pipe.sadd(matchmaking_key.clone(), session.game_id);
 
@@ -86,18 +130,23 @@ impl DiscoveryService {
                 &key,
                 &[
                     "server_id",
+                    "currency",
                     "single_bet_size",
                     "min_players",
                     "current_players",
-                    "grid_size",
+                    "geometry",
+                    "board_mode",
+                    "bomb_layout",
+                    "lives",
+                    "elimination",
                 ],
             )
             .await?;
 
         info!("Here 1");
-        // Return None if values is None or doesn't have exactly 5 elements
+        // Return None if values is None or doesn't have exactly 10 elements
         let values = match values {
-            Some(v) if v.len() == 5 => v,
+            Some(v) if v.len() == 10 => v,
             _ => return Ok(None),
         };
 
@@ -105,10 +154,15 @@ impl DiscoveryService {
         let session = GameSession {
             game_id: game_id.to_string(),
             server_id: values[0].clone(),
-            single_bet_size: values[1].parse()?,
-            min_players: values[2].parse()?,
-            current_players: values[3].parse()?,
-            grid_size: values[4].parse()?,
+            currency: values[1].parse()?,
+            single_bet_size: Amount::from_units(values[2].parse()?),
+            min_players: values[3].parse()?,
+            current_players: values[4].parse()?,
+            geometry: values[5].parse()?,
+            board_mode: values[6].parse()?,
+            bomb_layout: values[7].parse()?,
+            lives: parse_optional(&values[8])?,
+            elimination: parse_optional(&values[9])?,
         };
 
         info!("Here 2");
@@ -121,11 +175,17 @@ impl DiscoveryService {
     }
 
     // Find best matching game session based on bet size and player count
+    #[allow(clippy::too_many_arguments)]
     pub async fn find_game_session(
         &self,
-        single_bet_size: f64,
+        currency: Currency,
+        single_bet_size: Amount,
         min_players: u32,
-        grid_size: u32,
+        geometry: Geometry,
+        board_mode: BoardMode,
+        bomb_layout: BombLayout,
+        lives: Option<u32>,
+        elimination: Option<Elimination>,
     ) -> Result<Option<GameSession>> {
         info!("Finding game session");
         let start = Instant::now();
@@ -134,8 +194,15 @@ impl DiscoveryService {
 
         // Get a random game ID from the matchmaking set
         let matchmaking_key = format!(
-            "matchmaking:{}:{}:{}",
-            single_bet_size, min_players, grid_size
+            "matchmaking:{}:{}:{}:{}:{}:{}:{}:{}",
+            currency,
+            single_bet_size.units(),
+            min_players,
+            geometry,
+            board_mode,
+            bomb_layout,
+            optional_field(lives),
+            optional_field(elimination)
         );
 
         let game_id: Option<String> = conn.srandmember(&matchmaking_key).await?;
@@ -151,23 +218,33 @@ impl DiscoveryService {
                     &key,
                     &[
                         "server_id",
+                        "currency",
                         "single_bet_size",
                         "min_players",
                         "current_players",
-                        "grid_size",
+                        "geometry",
+                        "board_mode",
+                        "bomb_layout",
+                        "lives",
+                        "elimination",
                     ],
                 )
                 .await?;
 
             if let Some(values) = values {
-                if values.len() == 5 {
+                if values.len() == 10 {
                     let session = GameSession {
                         game_id: game_id.to_string(),
                         server_id: values[0].clone(),
-                        single_bet_size: values[1].parse()?,
-                        min_players: values[2].parse()?,
-                        current_players: values[3].parse()?,
-                        grid_size: values[4].parse()?,
+                        currency: values[1].parse()?,
+                        single_bet_size: Amount::from_units(values[2].parse()?),
+                        min_players: values[3].parse()?,
+                        current_players: values[4].parse()?,
+                        geometry: values[5].parse()?,
+                        board_mode: values[6].parse()?,
+                        bomb_layout: values[7].parse()?,
+                        lives: parse_optional(&values[8])?,
+                        elimination: parse_optional(&values[9])?,
                     };
                     if session.current_players < min_players {
                         Some(session)
@@ -189,9 +266,14 @@ impl DiscoveryService {
         // Log timing information
         info!(
             found_game = %game_id.is_some(),
-            bet_size = %single_bet_size,
+            currency = %currency,
+            bet_size = %single_bet_size.to_decimal(currency),
             min_players = %min_players,
-            grid_size = %grid_size,
+            geometry = %geometry,
+            board_mode = %board_mode,
+            bomb_layout = %bomb_layout,
+            lives = ?lives,
+            elimination = ?elimination,
             conn_latency_ms = %conn_time.as_millis(),
             pipeline_latency_ms = %pipeline_time.as_millis(),
             session_fetch_latency_ms = %session_fetch_time.as_millis(),
@@ -232,19 +314,33 @@ impl DiscoveryService {
                 &key,
                 &[
                     "server_id",
+                    "currency",
                     "single_bet_size",
                     "min_players",
                     "current_players",
-                    "grid_size",
+                    "geometry",
+                    "board_mode",
+                    "bomb_layout",
+                    "lives",
+                    "elimination",
                 ],
             )
             .await?;
 
         if let Some(values) = values {
-            if values.len() == 5 {
+            if values.len() == 10 {
                 // Remove from matchmaking set
-                let matchmaking_key =
-                    format!("matchmaking:{}:{}:{}", values[1], values[2], values[4]);
+                let matchmaking_key = format!(
+                    "matchmaking:{}:{}:{}:{}:{}:{}:{}:{}",
+                    values[1],
+                    values[2],
+                    values[3],
+                    values[5],
+                    values[6],
+                    values[7],
+                    values[8],
+                    values[9]
+                );
                 pipe.srem(matchmaking_key, game_id);
             }
         }
@@ -1,8 +1,9 @@
 use anyhow::Result;
 use common::{
-    db::{self, establish_connection},
+    auth::verify_session_token,
+    db::{self, establish_connection, EscrowAction},
     telegram::send_telegram_message,
-    utils::Currency,
+    utils::{Amount, Currency, Payout},
 };
 use futures_util::{
     lock::Mutex,
@@ -11,9 +12,16 @@ use futures_util::{
 };
 
 use http::HeaderValue;
+use rand::seq::SliceRandom;
 use redis::Client;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, sync::Arc};
+use sqlx::{Pool, Postgres};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    env,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::{
     io::AsyncWriteExt,
     net::{TcpListener, TcpStream},
@@ -28,43 +36,110 @@ use tracing::{error, info};
 use uuid::Uuid;
 
 use crate::{
-    board::Board,
+    board::{Board, BoardMode, BoardView, BombLayout, CellState, Geometry, Topology},
     discovery::{DiscoveryService, GameSession},
     player::Player,
+    seed_gen::{generate_client_seed, ServerSeed},
     xplode_moves::XplodeMovesClient,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Internal state, holds the full board. Clients only get a GameStateView
+#[derive(Debug, Clone, Deserialize)]
 pub enum GameState {
     WAITING {
         game_id: String,
         creator: Player,
         board: Board,
-        single_bet_size: f64,
+        single_bet_size: Amount,
+        currency: Currency,
         min_players: u32,
         players: Vec<Player>,
+        // Players whose stake is being reserved, their seats are kept for them
+        #[serde(skip)]
+        seating: Vec<String>,
+        bombs: u32,
+        // Bombs each player can hit before they're out, None for a game that ends on
+        // the first bomb
+        #[serde(default)]
+        lives: Option<u32>,
+        #[serde(default)]
+        elimination: Option<Elimination>,
+        turn_timeout_mode: TurnTimeoutMode,
+        // Commitment to the server seed, published before players contribute
+        server_seed_hash: String,
+        #[serde(skip)]
+        server_seed: ServerSeed,
     },
     RUNNING {
         game_id: String,
+        // Bumped on every rematch, the first game is round 0
+        round: u32,
         players: Vec<Player>,
         board: Board,
         turn_idx: usize,
-        single_bet_size: f64,
+        single_bet_size: Amount,
+        currency: Currency,
         locks: Option<Vec<(usize, usize)>>,
+        // Unix millis after which the turn holder is timed out
+        turn_deadline: i64,
+        turn_timeout_mode: TurnTimeoutMode,
+        // Whether the turn holder already mined a cell this turn
+        #[serde(skip)]
+        turn_moved: bool,
+        // Players whose socket dropped, the game is paused while any are missing
+        disconnected: Vec<Disconnection>,
+        #[serde(default)]
+        lives: Option<u32>,
+        #[serde(default)]
+        elimination: Option<Elimination>,
+        // Lives each player has left, in seat order. Players without any are out
+        #[serde(default)]
+        lives_left: Vec<u32>,
+        // Seats in the order their players went out
+        #[serde(default)]
+        eliminated: Vec<usize>,
+        server_seed_hash: String,
+        #[serde(skip)]
+        server_seed: ServerSeed,
     },
     FINISHED {
         game_id: String,
+        round: u32,
+        // A player in last place
         loser_idx: usize,
+        // Seats grouped by finishing place, first place first. Tied players share one
+        #[serde(default)]
+        ranking: Vec<Vec<usize>>,
+        #[serde(default)]
+        lives: Option<u32>,
+        #[serde(default)]
+        elimination: Option<Elimination>,
         board: Board,
         players: Vec<Player>,
-        single_bet_size: f64,
+        single_bet_size: Amount,
+        currency: Currency,
+        // Kept so a rematch plays under the same rules
+        turn_timeout_mode: TurnTimeoutMode,
+        // Revealed so players can re-derive the bomb coordinates
+        server_seed: String,
     },
     REMATCH {
         game_id: String,
+        round: u32,
         players: Vec<Player>,
         board: Board,
-        single_bet_size: f64,
+        single_bet_size: Amount,
+        currency: Currency,
         accepted: Vec<usize>,
+        bombs: u32,
+        #[serde(default)]
+        lives: Option<u32>,
+        #[serde(default)]
+        elimination: Option<Elimination>,
+        turn_timeout_mode: TurnTimeoutMode,
+        server_seed_hash: String,
+        #[serde(skip)]
+        server_seed: ServerSeed,
     },
     // During the start, user doesn't make a move for some predefined time
     ABORTED {
@@ -75,28 +150,337 @@ pub enum GameState {
     },
 }
 
+/// What happens when the turn holder lets the turn deadline pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TurnTimeoutMode {
+    // The idle player loses the game
+    #[default]
+    Forfeit,
+    // A random hidden cell is mined on the idle player's behalf
+    AutoMove,
+}
+
+/// Last-one-standing rules for games of more than two players. A bomb only knocks
+/// out the player who hit it and the survivors play on until one is left
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Elimination {
+    // Deal the survivors a fresh board every time a player goes out
+    #[serde(default)]
+    pub reset_board: bool,
+    #[serde(default)]
+    pub payout: Payout,
+}
+
+impl std::fmt::Display for Elimination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.payout)?;
+        if self.reset_board {
+            f.write_str(":reset")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Elimination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (payout, reset_board) = match s.strip_suffix(":reset") {
+            Some(payout) => (payout, true),
+            None => (s, false),
+        };
+        Ok(Elimination {
+            reset_board,
+            payout: payout.parse()?,
+        })
+    }
+}
+
+/// Seat held for a player whose socket dropped mid-game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Disconnection {
+    pub player_id: String,
+    // Unix millis after which the player forfeits
+    pub reconnect_deadline: i64,
+}
+
+/// Client-facing projection of GameState. Bomb positions stay hidden until FINISHED
+#[derive(Debug, Clone, Serialize)]
+pub enum GameStateView {
+    WAITING {
+        game_id: String,
+        creator: Player,
+        board: BoardView,
+        single_bet_size: Amount,
+        currency: Currency,
+        min_players: u32,
+        players: Vec<Player>,
+        bombs: u32,
+        lives: Option<u32>,
+        elimination: Option<Elimination>,
+        turn_timeout_mode: TurnTimeoutMode,
+        server_seed_hash: String,
+    },
+    RUNNING {
+        game_id: String,
+        round: u32,
+        players: Vec<Player>,
+        board: BoardView,
+        turn_idx: usize,
+        single_bet_size: Amount,
+        currency: Currency,
+        locks: Option<Vec<(usize, usize)>>,
+        turn_deadline: i64,
+        turn_timeout_mode: TurnTimeoutMode,
+        disconnected: Vec<Disconnection>,
+        lives: Option<u32>,
+        elimination: Option<Elimination>,
+        lives_left: Vec<u32>,
+        eliminated: Vec<usize>,
+        server_seed_hash: String,
+    },
+    FINISHED {
+        game_id: String,
+        round: u32,
+        loser_idx: usize,
+        ranking: Vec<Vec<usize>>,
+        lives: Option<u32>,
+        elimination: Option<Elimination>,
+        board: BoardView,
+        players: Vec<Player>,
+        single_bet_size: Amount,
+        currency: Currency,
+        turn_timeout_mode: TurnTimeoutMode,
+        server_seed: String,
+    },
+    REMATCH {
+        game_id: String,
+        round: u32,
+        players: Vec<Player>,
+        board: BoardView,
+        single_bet_size: Amount,
+        currency: Currency,
+        accepted: Vec<usize>,
+        bombs: u32,
+        lives: Option<u32>,
+        elimination: Option<Elimination>,
+        turn_timeout_mode: TurnTimeoutMode,
+        server_seed_hash: String,
+    },
+    ABORTED {
+        game_id: String,
+    },
+    RematchRejected {
+        game_id: String,
+    },
+}
+
+impl From<&GameState> for GameStateView {
+    fn from(state: &GameState) -> Self {
+        match state.clone() {
+            GameState::WAITING {
+                game_id,
+                creator,
+                board,
+                single_bet_size,
+                currency,
+                min_players,
+                players,
+                bombs,
+                lives,
+                elimination,
+                turn_timeout_mode,
+                server_seed_hash,
+                ..
+            } => GameStateView::WAITING {
+                game_id,
+                creator,
+                board: board.view(false),
+                single_bet_size,
+                currency,
+                min_players,
+                players,
+                bombs,
+                lives,
+                elimination,
+                turn_timeout_mode,
+                server_seed_hash,
+            },
+            GameState::RUNNING {
+                game_id,
+                round,
+                players,
+                board,
+                turn_idx,
+                single_bet_size,
+                currency,
+                locks,
+                turn_deadline,
+                turn_timeout_mode,
+                disconnected,
+                lives,
+                elimination,
+                lives_left,
+                eliminated,
+                server_seed_hash,
+                ..
+            } => GameStateView::RUNNING {
+                game_id,
+                round,
+                players,
+                board: board.view(false),
+                turn_idx,
+                single_bet_size,
+                currency,
+                locks,
+                turn_deadline,
+                turn_timeout_mode,
+                disconnected,
+                lives,
+                elimination,
+                lives_left,
+                eliminated,
+                server_seed_hash,
+            },
+            GameState::FINISHED {
+                game_id,
+                round,
+                loser_idx,
+                ranking,
+                lives,
+                elimination,
+                board,
+                players,
+                single_bet_size,
+                currency,
+                turn_timeout_mode,
+                server_seed,
+            } => GameStateView::FINISHED {
+                game_id,
+                round,
+                loser_idx,
+                ranking,
+                lives,
+                elimination,
+                board: board.view(true),
+                players,
+                single_bet_size,
+                currency,
+                turn_timeout_mode,
+                server_seed,
+            },
+            GameState::REMATCH {
+                game_id,
+                round,
+                players,
+                board,
+                single_bet_size,
+                currency,
+                accepted,
+                bombs,
+                lives,
+                elimination,
+                turn_timeout_mode,
+                server_seed_hash,
+                ..
+            } => GameStateView::REMATCH {
+                game_id,
+                round,
+                players,
+                board: board.view(false),
+                single_bet_size,
+                currency,
+                accepted,
+                bombs,
+                lives,
+                elimination,
+                turn_timeout_mode,
+                server_seed_hash,
+            },
+            GameState::ABORTED { game_id } => GameStateView::ABORTED { game_id },
+            GameState::RematchRejected { game_id } => GameStateView::RematchRejected { game_id },
+        }
+    }
+}
+
+// Clients predating multi-currency games only ever played for SOL
+fn legacy_currency() -> Currency {
+    Currency::SOL
+}
+
+// Largest board a game can be played on, every cell is sent with each update
+const MAX_BOARD_SIDE: usize = 32;
+const MAX_BOARD_CELLS: usize = 512;
+
+// Older clients only send `grid`, the side of a square board
+fn play_geometry(
+    grid: u32,
+    width: Option<u32>,
+    height: Option<u32>,
+    topology: Topology,
+) -> Geometry {
+    Geometry {
+        width: width.unwrap_or(grid) as usize,
+        height: height.unwrap_or(grid) as usize,
+        topology,
+    }
+}
+
+// Every GameUpdate that leaves the server goes through the client projection
+fn serialize_game_state<S: Serializer>(
+    state: &GameState,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    GameStateView::from(state).serialize(serializer)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BlockchainUpdateType {
     GameInitialized,
     MoveRecorded,
     GameCommitted,
+    GameRevealed,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum GameMessage {
     Play {
         player_id: String,
         name: String,
-        single_bet_size: f64,
+        single_bet_size: Amount,
         min_players: u32,
         bombs: u32,
+        #[serde(default)]
         grid: u32,
+        #[serde(default)]
+        width: Option<u32>,
+        #[serde(default)]
+        height: Option<u32>,
+        #[serde(default)]
+        topology: Topology,
         is_creating_room: bool,
+        #[serde(default)]
+        client_seed: Option<String>,
+        #[serde(default)]
+        turn_timeout_mode: TurnTimeoutMode,
+        #[serde(default = "legacy_currency")]
+        currency: Currency,
+        #[serde(default)]
+        board_mode: BoardMode,
+        #[serde(default)]
+        bomb_layout: BombLayout,
+        #[serde(default)]
+        lives: Option<u32>,
+        #[serde(default)]
+        elimination: Option<Elimination>,
     },
     Join {
         game_id: String,
         player_id: String,
         name: String,
+        #[serde(default)]
+        client_seed: Option<String>,
     },
     MakeMove {
         game_id: String,
@@ -119,7 +503,12 @@ pub enum GameMessage {
         game_id: Option<String>,
         player_id: Option<String>,
     },
-    GameUpdate(GameState),
+    // Reattaches a new socket to a seat kept after a disconnect
+    Resume {
+        game_id: String,
+        player_id: String,
+    },
+    GameUpdate(#[serde(serialize_with = "serialize_game_state")] GameState),
     Error(String),
     RedirectToServer {
         game_id: String,
@@ -132,11 +521,15 @@ pub enum GameMessage {
     RematchRequest {
         game_id: String,
         requester_id: String,
+        #[serde(default)]
+        client_seed: Option<String>,
     },
     RematchResponse {
         game_id: String,
         player_id: String,
         want_rematch: bool,
+        #[serde(default)]
+        client_seed: Option<String>,
     },
     BlockchainUpdate {
         game_id: String,
@@ -148,6 +541,357 @@ pub enum GameMessage {
         player_id: String,
         gif_id: usize,
     },
+    Rejected {
+        game_id: Option<String>,
+        error: GameError,
+    },
+}
+
+impl GameMessage {
+    // Player id the sender claims to act as
+    fn claimed_player_id(&self) -> Option<&str> {
+        match self {
+            GameMessage::Play { player_id, .. }
+            | GameMessage::Join { player_id, .. }
+            | GameMessage::Resume { player_id, .. }
+            | GameMessage::Rematch { player_id, .. }
+            | GameMessage::RematchResponse { player_id, .. }
+            | GameMessage::Gif { player_id, .. } => Some(player_id),
+            GameMessage::Ping { player_id, .. } => player_id.as_deref(),
+            GameMessage::RematchRequest { requester_id, .. } => Some(requester_id),
+            _ => None,
+        }
+    }
+
+    // Messages only the server may produce, never accepted from a socket
+    fn is_server_only(&self) -> bool {
+        matches!(
+            self,
+            GameMessage::GameUpdate(_)
+                | GameMessage::Error(_)
+                | GameMessage::RedirectToServer { .. }
+                | GameMessage::BlockchainUpdate { .. }
+                | GameMessage::Rejected { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GameError {
+    GameNotFound,
+    GameNotRunning,
+    NotInGame,
+    AlreadySeated,
+    NotYourTurn,
+    GamePaused,
+    AlreadyMoved,
+    MoveRequired,
+    InsufficientBalance,
+    StakeUnavailable,
+    IdentityMismatch,
+    OutOfBounds { x: usize, y: usize },
+    AlreadyMined { x: usize, y: usize },
+}
+
+impl std::fmt::Display for GameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameError::GameNotFound => write!(f, "Game not found"),
+            GameError::GameNotRunning => write!(f, "Game is not running"),
+            GameError::NotInGame => write!(f, "You are not a player in this game"),
+            GameError::AlreadySeated => write!(f, "You already have a seat in this game"),
+            GameError::NotYourTurn => write!(f, "It is not your turn"),
+            GameError::GamePaused => write!(f, "Game is paused until everyone reconnects"),
+            GameError::AlreadyMoved => write!(f, "You already mined a cell this turn"),
+            GameError::MoveRequired => write!(f, "You have to mine a cell first"),
+            GameError::InsufficientBalance => write!(f, "Your balance doesn't cover the stake"),
+            GameError::StakeUnavailable => write!(f, "Couldn't reserve your stake, try again"),
+            GameError::IdentityMismatch => {
+                write!(f, "This connection is bound to another player")
+            }
+            GameError::OutOfBounds { x, y } => write!(f, "Cell ({}, {}) is off the board", x, y),
+            GameError::AlreadyMined { x, y } => write!(f, "Cell ({}, {}) is already mined", x, y),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl GameState {
+    // Seated in a game that's waiting, running or up for a rematch
+    fn has_player(&self, player_id: &str) -> bool {
+        match self {
+            GameState::WAITING { players, .. }
+            | GameState::RUNNING { players, .. }
+            | GameState::REMATCH { players, .. } => players.iter().any(|p| p.id == player_id),
+            _ => false,
+        }
+    }
+
+    // Only the player holding the turn may act on a running game
+    fn check_turn(&self, player_id: &str) -> Result<(), GameError> {
+        match self {
+            GameState::RUNNING {
+                players,
+                turn_idx,
+                disconnected,
+                ..
+            } => {
+                if !players.iter().any(|p| p.id == player_id) {
+                    Err(GameError::NotInGame)
+                } else if !disconnected.is_empty() {
+                    Err(GameError::GamePaused)
+                } else if players[*turn_idx].id == player_id {
+                    Ok(())
+                } else {
+                    Err(GameError::NotYourTurn)
+                }
+            }
+            _ => Err(GameError::GameNotRunning),
+        }
+    }
+
+    // Aborting a lobby is open to anyone seated in it, otherwise only the turn holder may
+    // stop. A running game can't be aborted, stopping it concedes.
+    fn check_stop(&self, player_id: &str, abort: bool) -> Result<(), GameError> {
+        match self {
+            GameState::WAITING { players, .. } if abort => {
+                if players.iter().any(|p| p.id == player_id) {
+                    Ok(())
+                } else {
+                    Err(GameError::NotInGame)
+                }
+            }
+            _ => self.check_turn(player_id),
+        }
+    }
+
+    // Turn holder who hasn't mined yet, on a cell that is still hidden
+    fn check_move(&self, player_id: &str, x: usize, y: usize) -> Result<(), GameError> {
+        self.check_turn(player_id)?;
+        match self {
+            GameState::RUNNING {
+                board, turn_moved, ..
+            } => {
+                if *turn_moved {
+                    return Err(GameError::AlreadyMoved);
+                }
+                check_cell(board, x, y)
+            }
+            _ => Err(GameError::GameNotRunning),
+        }
+    }
+
+    // Locks are placed by the turn holder after mining, on hidden cells
+    fn check_lock(&self, player_id: &str, x: usize, y: usize) -> Result<(), GameError> {
+        self.check_lock_complete(player_id)?;
+        match self {
+            GameState::RUNNING { board, .. } => check_cell(board, x, y),
+            _ => Err(GameError::GameNotRunning),
+        }
+    }
+
+    fn check_lock_complete(&self, player_id: &str) -> Result<(), GameError> {
+        self.check_turn(player_id)?;
+        match self {
+            GameState::RUNNING { turn_moved, .. } if !*turn_moved => Err(GameError::MoveRequired),
+            _ => Ok(()),
+        }
+    }
+
+    // Hands the turn to the next player still in the game
+    fn advance_turn(&mut self, deadline: i64) {
+        if let GameState::RUNNING {
+            players,
+            turn_idx,
+            turn_deadline,
+            turn_moved,
+            lives_left,
+            ..
+        } = self
+        {
+            for _ in 0..players.len() {
+                *turn_idx = (*turn_idx + 1) % players.len();
+                if lives_left.get(*turn_idx) != Some(&0) {
+                    break;
+                }
+            }
+            *turn_deadline = deadline;
+            *turn_moved = false;
+        }
+    }
+
+    // Costs the player a life for hitting a bomb, or every life they have left
+    // when they forfeit
+    fn take_lives(&mut self, player_idx: usize, forfeit: bool) -> LifeLost {
+        let GameState::RUNNING {
+            players,
+            lives_left,
+            eliminated,
+            disconnected,
+            ..
+        } = self
+        else {
+            return LifeLost::GameOver;
+        };
+        let lives = &mut lives_left[player_idx];
+        *lives = if forfeit { 0 } else { lives.saturating_sub(1) };
+        if *lives == 0 && !eliminated.contains(&player_idx) {
+            eliminated.push(player_idx);
+            // Nobody waits for a player who's out
+            disconnected.retain(|d| d.player_id != players[player_idx].id);
+        }
+        let out = *lives == 0;
+
+        if self.is_over() {
+            LifeLost::GameOver
+        } else if out {
+            self.reset_board();
+            LifeLost::Eliminated
+        } else {
+            LifeLost::Survived
+        }
+    }
+
+    // Deals a fresh board when the elimination rules ask for one. The bombs are drawn
+    // from the game's seeds plus the number of players out so far, so every board
+    // can be re-derived once the server seed is revealed.
+    fn reset_board(&mut self) {
+        let GameState::RUNNING {
+            players,
+            board,
+            locks,
+            elimination: Some(Elimination {
+                reset_board: true, ..
+            }),
+            eliminated,
+            server_seed,
+            ..
+        } = self
+        else {
+            return;
+        };
+
+        let mut seeds: Vec<String> = players.iter().map(|p| p.client_seed.clone()).collect();
+        seeds.push(format!("elimination:{}", eliminated.len()));
+        *board = Board::new(
+            board.geometry,
+            board.bomb_coordinates.len(),
+            board.mode,
+            board.layout,
+            server_seed,
+            &seeds,
+        );
+        *locks = None;
+    }
+
+    // Over once at most one player is left, or there's nothing left to mine. Without
+    // lives the first player out ends the game.
+    fn is_over(&self) -> bool {
+        match self {
+            GameState::RUNNING {
+                board,
+                lives,
+                lives_left,
+                eliminated,
+                ..
+            } => {
+                (lives.is_none() && !eliminated.is_empty())
+                    || lives_left.iter().filter(|&&lives| lives > 0).count() <= 1
+                    || board.hidden_cells().is_empty()
+            }
+            _ => true,
+        }
+    }
+
+    // Seats grouped by finishing place, first place first. Players still in rank by
+    // the lives they have left, the others by how long they lasted.
+    fn ranking(&self) -> Vec<Vec<usize>> {
+        let GameState::RUNNING {
+            lives_left,
+            eliminated,
+            ..
+        } = self
+        else {
+            return Vec::new();
+        };
+
+        let mut standing: Vec<u32> = lives_left.clone();
+        standing.sort_unstable_by(|a, b| b.cmp(a));
+        standing.dedup();
+        let mut ranking: Vec<Vec<usize>> = standing
+            .into_iter()
+            .filter(|&lives| lives > 0)
+            .map(|lives| {
+                (0..lives_left.len())
+                    .filter(|&idx| lives_left[idx] == lives)
+                    .collect()
+            })
+            .collect();
+        ranking.extend(eliminated.iter().rev().map(|&idx| vec![idx]));
+        ranking
+    }
+
+    // The finished game a running one ends as
+    fn finished(&self) -> Option<GameState> {
+        let GameState::RUNNING {
+            game_id,
+            round,
+            players,
+            board,
+            single_bet_size,
+            currency,
+            turn_timeout_mode,
+            lives,
+            elimination,
+            server_seed,
+            ..
+        } = self
+        else {
+            return None;
+        };
+
+        let ranking = self.ranking();
+        Some(GameState::FINISHED {
+            game_id: game_id.clone(),
+            round: *round,
+            loser_idx: ranking.last().map_or(0, |last| last[0]),
+            ranking,
+            lives: *lives,
+            elimination: *elimination,
+            board: board.clone(),
+            players: players.clone(),
+            single_bet_size: *single_bet_size,
+            currency: *currency,
+            turn_timeout_mode: *turn_timeout_mode,
+            server_seed: server_seed.reveal(),
+        })
+    }
+}
+
+/// What losing lives did to a running game
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LifeLost {
+    // The player still has lives, and keeps the turn if they held it
+    Survived,
+    // The player is out, the others play on
+    Eliminated,
+    GameOver,
+}
+
+fn check_cell(board: &Board, x: usize, y: usize) -> Result<(), GameError> {
+    match board.cell(x, y) {
+        None => Err(GameError::OutOfBounds { x, y }),
+        Some(CellState::Hidden) => Ok(()),
+        Some(_) => Err(GameError::AlreadyMined { x, y }),
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -157,6 +901,7 @@ pub struct GameMessageWrapper {
 }
 #[derive(Clone)]
 pub struct GameRegistry {
+    // Whoever needs both takes games before active_players
     games: Arc<RwLock<HashMap<String, GameState>>>,
     active_players: Arc<RwLock<HashMap<String, String>>>,
     game_channels: Arc<RwLock<HashMap<String, Arc<mpsc::Sender<GameMessage>>>>>,
@@ -164,16 +909,40 @@ pub struct GameRegistry {
     discovery: DiscoveryService,
     server_id: String,
     xplode_moves: XplodeMovesClient,
+    pool: Pool<Postgres>,
+    turn_timeout: Duration,
+    reconnect_grace: Duration,
+    rematch_timeout: Duration,
+    // How often settlements and releases that didn't go through are retried
+    escrow_retry: Duration,
+    // Latest socket of every connected player, older sockets don't own the seat
+    connections: Arc<RwLock<HashMap<String, String>>>,
 }
 
 type WebSocketSink = SplitSink<WebSocketStream<TcpStream>, Message>;
 
 impl GameRegistry {
-    pub fn new(redis: redis::Client, server_id: String) -> Self {
+    pub fn new(redis: redis::Client, server_id: String, pool: Pool<Postgres>) -> Self {
         let api_base = env::var("XPLODE_MOVES_API")
             // .unwrap_or_else(|_| "https://xplode-moves.fly.dev/api/game".to_string());
-        // let api_base = env::var("XPLODE_MOVES_API")
-                .unwrap_or_else(|_| "http://localhost:3004/api/game".to_string());
+            // let api_base = env::var("XPLODE_MOVES_API")
+            .unwrap_or_else(|_| "http://localhost:3004/api/game".to_string());
+        let turn_timeout = env::var("TURN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(30);
+        let reconnect_grace = env::var("RECONNECT_GRACE_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(30);
+        let rematch_timeout = env::var("REMATCH_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(60);
+        let escrow_retry = env::var("ESCROW_RETRY_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(30);
         Self {
             games: Arc::new(RwLock::new(HashMap::new())),
             active_players: Arc::new(RwLock::new(HashMap::new())),
@@ -182,6 +951,12 @@ impl GameRegistry {
             discovery: DiscoveryService::new(redis),
             server_id,
             xplode_moves: XplodeMovesClient::new(api_base),
+            pool,
+            turn_timeout: Duration::from_secs(turn_timeout),
+            reconnect_grace: Duration::from_secs(reconnect_grace),
+            rematch_timeout: Duration::from_secs(rematch_timeout),
+            escrow_retry: Duration::from_secs(escrow_retry),
+            connections: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -243,72 +1018,946 @@ impl GameRegistry {
                     break; // Exit the loop if client disconnects
                 }
             }
-        });
+        });
+
+        Ok(())
+    }
+
+    // Simplified publish method for local broadcasting only
+    pub async fn publish_message(
+        &self,
+        channel: String,
+        game_message_wrapper: GameMessageWrapper,
+        _from_redis: bool, // Not needed anymore since we're local only
+    ) -> Result<()> {
+        info!("--------------------------------");
+        info!("Publishing message to channel: {:?}", channel);
+        info!("--------------------------------");
+        if let Some(broadcast_tx) = self.broadcast_channels.read().await.get(&channel) {
+            info!("--------------------------------");
+            info!("Sending message to channel: {:?}", channel);
+            info!("--------------------------------");
+            let _ = broadcast_tx.send(game_message_wrapper.game_message);
+        }
+        Ok(())
+    }
+
+    // Add new cleanup method
+    pub async fn cleanup_player(&self, player_id: &str) {
+        // Locked in the same order as everywhere else, games first
+        let mut games_write = self.games.write().await;
+        let mut active_players_write = self.active_players.write().await;
+
+        // Remove from active players
+        active_players_write.remove(player_id);
+
+        // Check if player is in any WAITING games and clean those up
+        let mut games_to_abort = Vec::new();
+        let mut games_left = Vec::new();
+        let mut rematches_to_reject = Vec::new();
+
+        for (game_id, state) in games_write.iter_mut() {
+            match state {
+                GameState::WAITING { creator, .. } if creator.id == player_id => {
+                    games_to_abort.push(game_id.clone());
+                }
+                // Anyone else gives up their seat, and a stake still being reserved
+                // for it is released by seat_player
+                GameState::WAITING {
+                    players, seating, ..
+                } if players.iter().any(|p| p.id == player_id)
+                    || seating.iter().any(|id| id == player_id) =>
+                {
+                    seating.retain(|id| id != player_id);
+                    if players.iter().any(|p| p.id == player_id) {
+                        players.retain(|p| p.id != player_id);
+                        self.release_player_stake(game_id, 0, player_id);
+                        games_left.push((game_id.clone(), state.clone()));
+                    }
+                }
+                // The rematch can't start without everyone
+                GameState::REMATCH { players, .. } if players.iter().any(|p| p.id == player_id) => {
+                    rematches_to_reject.push(game_id.clone());
+                }
+                _ => {}
+            }
+        }
+
+        // Abort any WAITING games where this player was the creator
+        for game_id in games_to_abort {
+            let aborted_state = GameState::ABORTED {
+                game_id: game_id.clone(),
+            };
+            games_write.insert(game_id.clone(), aborted_state);
+            self.release_stakes(&game_id, 0);
+
+            // Only remove from discovery service, no need to save state
+            let _ = self.discovery.remove_game_session(&game_id).await;
+        }
+
+        for game_id in &rematches_to_reject {
+            if let Some(game_state) = games_write.get_mut(game_id) {
+                let ids = self.reject_rematch(game_state);
+                active_players_write.retain(|p, _| !ids.contains(p));
+            }
+        }
+        drop(active_players_write);
+        drop(games_write);
+
+        for game_id in rematches_to_reject {
+            info!("Player {} left the rematch of game {}", player_id, game_id);
+            self.publish_rematch_rejected(&game_id).await;
+        }
+
+        for (game_id, state) in games_left {
+            info!("Player {} left game {}", player_id, game_id);
+            if let Err(e) = self.announce_seat(&state).await {
+                error!("Failed to update discovery for game {}: {}", game_id, e);
+            }
+            let wrapper = GameMessageWrapper {
+                server_id: self.server_id.clone(),
+                game_message: GameMessage::GameUpdate(state),
+            };
+            let _ = self.publish_message(game_id, wrapper, false).await;
+        }
+    }
+
+    // Spawns the on-chain game initialization once the bombs are placed. Only the
+    // seed commitment is posted, the bombs stay off chain until the game is over.
+    fn initialize_game_on_chain(
+        &self,
+        game_id: &str,
+        geometry: Geometry,
+        server_seed_hash: &str,
+    ) {
+        let registry_clone = self.clone();
+        let game_id_clone = game_id.to_string();
+        let server_seed_hash = server_seed_hash.to_string();
+
+        tokio::spawn(async move {
+            if let Ok(tx_hash) = registry_clone
+                .xplode_moves
+                .initialize_game(&game_id_clone, geometry, &server_seed_hash)
+                .await
+            {
+                let update = GameMessage::BlockchainUpdate {
+                    game_id: game_id_clone.clone(),
+                    update_type: BlockchainUpdateType::GameInitialized,
+                    transaction_hash: tx_hash,
+                };
+                let wrapper = GameMessageWrapper {
+                    server_id: registry_clone.server_id.clone(),
+                    game_message: update,
+                };
+                let _ = registry_clone
+                    .publish_message(game_id_clone.clone(), wrapper, false)
+                    .await;
+            }
+        });
+    }
+
+    // Spawns the on-chain reveal of a finished game's seed and bombs
+    fn reveal_game_on_chain(&self, finished: &GameState) {
+        let GameState::FINISHED {
+            game_id,
+            board,
+            server_seed,
+            ..
+        } = finished
+        else {
+            return;
+        };
+        let registry_clone = self.clone();
+        let game_id_clone = game_id.clone();
+        let server_seed = server_seed.clone();
+        let geometry = board.geometry;
+        let bomb_positions: Vec<(usize, usize)> = board
+            .bomb_coordinates
+            .iter()
+            .map(|&pos| geometry.coordinates(pos))
+            .collect();
+
+        tokio::spawn(async move {
+            if let Ok(tx_hash) = registry_clone
+                .xplode_moves
+                .reveal_game(&game_id_clone, &server_seed, bomb_positions)
+                .await
+            {
+                let update = GameMessage::BlockchainUpdate {
+                    game_id: game_id_clone.clone(),
+                    update_type: BlockchainUpdateType::GameRevealed,
+                    transaction_hash: tx_hash,
+                };
+                let wrapper = GameMessageWrapper {
+                    server_id: registry_clone.server_id.clone(),
+                    game_message: update,
+                };
+                let _ = registry_clone
+                    .publish_message(game_id_clone, wrapper, false)
+                    .await;
+            }
+        });
+    }
+
+    // Spawns the on-chain record of a mined cell
+    fn record_move_on_chain(&self, game_id: &str, player_name: String, x: usize, y: usize) {
+        let registry_clone = self.clone();
+        let game_id_clone = game_id.to_string();
+        tokio::spawn(async move {
+            if let Ok(tx_hash) = registry_clone
+                .xplode_moves
+                .record_move(&game_id_clone, &player_name, x, y)
+                .await
+            {
+                let update = GameMessage::BlockchainUpdate {
+                    game_id: game_id_clone.clone(),
+                    update_type: BlockchainUpdateType::MoveRecorded,
+                    transaction_hash: tx_hash,
+                };
+                let wrapper = GameMessageWrapper {
+                    server_id: registry_clone.server_id.clone(),
+                    game_message: update,
+                };
+                let _ = registry_clone
+                    .publish_message(game_id_clone, wrapper, false)
+                    .await;
+            }
+        });
+    }
+
+    fn next_turn_deadline(&self) -> i64 {
+        now_millis() + self.turn_timeout.as_millis() as i64
+    }
+
+    // Wakes up at the deadline, by then the turn may long be over
+    fn schedule_turn_timeout(&self, game_id: String, deadline: i64) {
+        let registry_clone = self.clone();
+        tokio::spawn(async move {
+            let remaining = (deadline - now_millis()).max(0) as u64;
+            tokio::time::sleep(Duration::from_millis(remaining)).await;
+            if let Err(e) = registry_clone.handle_turn_timeout(&game_id, deadline).await {
+                error!("Failed to handle turn timeout for {}: {}", game_id, e);
+            }
+        });
+    }
+
+    async fn handle_turn_timeout(&self, game_id: &str, deadline: i64) -> Result<()> {
+        let mut games_write = self.games.write().await;
+        let Some(game_state) = games_write.get_mut(game_id) else {
+            return Ok(());
+        };
+
+        // Only act if the turn that scheduled us is still the current one
+        let (turn_idx, turn_timeout_mode, turn_moved) = match game_state {
+            GameState::RUNNING {
+                turn_idx,
+                turn_deadline,
+                turn_timeout_mode,
+                turn_moved,
+                disconnected,
+                ..
+            } if *turn_deadline == deadline && disconnected.is_empty() => {
+                (*turn_idx, *turn_timeout_mode, *turn_moved)
+            }
+            _ => return Ok(()),
+        };
+        info!(
+            "Turn of player {} timed out in game {} ({:?})",
+            turn_idx, game_id, turn_timeout_mode
+        );
+
+        // When only the lock phase was left, the turn is just handed over
+        if !turn_moved {
+            match turn_timeout_mode {
+                TurnTimeoutMode::Forfeit => {
+                    game_state.take_lives(turn_idx, true);
+                }
+                TurnTimeoutMode::AutoMove => {
+                    let GameState::RUNNING {
+                        players,
+                        board,
+                        locks,
+                        ..
+                    } = game_state
+                    else {
+                        return Ok(());
+                    };
+                    let cells = board.hidden_cells();
+                    let Some(&(x, y)) = cells.choose(&mut rand::thread_rng()) else {
+                        return Ok(());
+                    };
+                    *locks = None;
+                    self.record_move_on_chain(game_id, players[turn_idx].name.clone(), x, y);
+                    if board.mine(x, y).bomb {
+                        game_state.take_lives(turn_idx, false);
+                    }
+                }
+            }
+        }
+
+        if game_state.is_over() {
+            self.finish_game(game_state).await;
+        } else {
+            let deadline = self.next_turn_deadline();
+            game_state.advance_turn(deadline);
+            self.schedule_turn_timeout(game_id.to_string(), deadline);
+        }
+
+        let wrapper = GameMessageWrapper {
+            server_id: self.server_id.clone(),
+            game_message: GameMessage::GameUpdate(game_state.clone()),
+        };
+        drop(games_write);
+        self.publish_message(game_id.to_string(), wrapper, false)
+            .await
+    }
+
+    // Returns false if a newer socket took over the player in the meantime
+    async fn release_connection(&self, player_id: &str, connection_id: &str) -> bool {
+        let mut connections_write = self.connections.write().await;
+        if connections_write.get(player_id).map(String::as_str) == Some(connection_id) {
+            connections_write.remove(player_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Pauses a running game instead of forfeiting the dropped player right away.
+    // Returns false if the player has no seat to keep.
+    async fn hold_seat(&self, game_id: &str, player_id: &str) -> bool {
+        let mut games_write = self.games.write().await;
+        let Some(game_state) = games_write.get_mut(game_id) else {
+            return false;
+        };
+        let GameState::RUNNING {
+            players,
+            disconnected,
+            lives_left,
+            ..
+        } = game_state
+        else {
+            return false;
+        };
+        // A player who's out has nothing to come back for
+        match players.iter().position(|p| p.id == player_id) {
+            Some(idx) if lives_left.get(idx) != Some(&0) => {}
+            _ => return false,
+        }
+
+        let reconnect_deadline = now_millis() + self.reconnect_grace.as_millis() as i64;
+        disconnected.retain(|d| d.player_id != player_id);
+        disconnected.push(Disconnection {
+            player_id: player_id.to_string(),
+            reconnect_deadline,
+        });
+        info!(
+            "Holding seat of player {} in game {} until {}",
+            player_id, game_id, reconnect_deadline
+        );
+
+        let wrapper = GameMessageWrapper {
+            server_id: self.server_id.clone(),
+            game_message: GameMessage::GameUpdate(game_state.clone()),
+        };
+        drop(games_write);
+
+        self.schedule_reconnect_timeout(
+            game_id.to_string(),
+            player_id.to_string(),
+            reconnect_deadline,
+        );
+        let _ = self
+            .publish_message(game_id.to_string(), wrapper, false)
+            .await;
+        true
+    }
+
+    // Gives the seat back, the game resumes once nobody is missing
+    async fn resume_player(&self, game_id: &str, player_id: &str) -> Result<GameState, GameError> {
+        let mut games_write = self.games.write().await;
+        let game_state = games_write
+            .get_mut(game_id)
+            .ok_or(GameError::GameNotFound)?;
+
+        match game_state {
+            GameState::RUNNING {
+                players,
+                turn_deadline,
+                disconnected,
+                ..
+            } => {
+                if !players.iter().any(|p| p.id == player_id) {
+                    return Err(GameError::NotInGame);
+                }
+                let was_paused = !disconnected.is_empty();
+                disconnected.retain(|d| d.player_id != player_id);
+                if was_paused && disconnected.is_empty() {
+                    // The turn holder gets a full turn after the pause
+                    *turn_deadline = self.next_turn_deadline();
+                    self.schedule_turn_timeout(game_id.to_string(), *turn_deadline);
+                }
+            }
+            GameState::WAITING { players, .. }
+            | GameState::FINISHED { players, .. }
+            | GameState::REMATCH { players, .. } => {
+                if !players.iter().any(|p| p.id == player_id) {
+                    return Err(GameError::NotInGame);
+                }
+            }
+            GameState::ABORTED { .. } | GameState::RematchRejected { .. } => {}
+        }
+
+        Ok(game_state.clone())
+    }
+
+    fn schedule_reconnect_timeout(&self, game_id: String, player_id: String, deadline: i64) {
+        let registry_clone = self.clone();
+        tokio::spawn(async move {
+            let remaining = (deadline - now_millis()).max(0) as u64;
+            tokio::time::sleep(Duration::from_millis(remaining)).await;
+            if let Err(e) = registry_clone
+                .handle_reconnect_timeout(&game_id, &player_id, deadline)
+                .await
+            {
+                error!("Failed to handle reconnect timeout for {}: {}", game_id, e);
+            }
+        });
+    }
+
+    async fn handle_reconnect_timeout(
+        &self,
+        game_id: &str,
+        player_id: &str,
+        deadline: i64,
+    ) -> Result<()> {
+        let mut games_write = self.games.write().await;
+        let Some(game_state) = games_write.get_mut(game_id) else {
+            return Ok(());
+        };
+
+        // Still missing under the same disconnect that scheduled us
+        let loser_idx = match game_state {
+            GameState::RUNNING {
+                players,
+                disconnected,
+                ..
+            } if disconnected
+                .iter()
+                .any(|d| d.player_id == player_id && d.reconnect_deadline == deadline) =>
+            {
+                players.iter().position(|p| p.id == player_id)
+            }
+            _ => None,
+        };
+        let Some(loser_idx) = loser_idx else {
+            return Ok(());
+        };
+        info!("Player {} did not reconnect to game {}", player_id, game_id);
+
+        let lost = game_state.take_lives(loser_idx, true);
+        if lost == LifeLost::GameOver {
+            self.finish_game(game_state).await;
+        } else if let GameState::RUNNING {
+            turn_idx,
+            turn_deadline,
+            disconnected,
+            ..
+        } = game_state
+        {
+            // The others play on, with a full turn once nobody else is missing
+            let deadline = self.next_turn_deadline();
+            *turn_deadline = deadline;
+            let (held_turn, resumed) = (*turn_idx == loser_idx, disconnected.is_empty());
+            if held_turn {
+                game_state.advance_turn(deadline);
+            }
+            if resumed {
+                self.schedule_turn_timeout(game_id.to_string(), deadline);
+            }
+        }
+        let wrapper = GameMessageWrapper {
+            server_id: self.server_id.clone(),
+            game_message: GameMessage::GameUpdate(game_state.clone()),
+        };
+        drop(games_write);
+
+        self.publish_message(game_id.to_string(), wrapper, false)
+            .await?;
+        if lost == LifeLost::GameOver {
+            // Clean up broadcast channel since player has left
+            self.cleanup_broadcast_channel(game_id).await;
+        }
+        Ok(())
+    }
+
+    // Rejects the rematch if it's still waiting for answers when the deadline passes
+    fn schedule_rematch_timeout(&self, game_id: String, round: u32) {
+        let registry_clone = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(registry_clone.rematch_timeout).await;
+            registry_clone.handle_rematch_timeout(&game_id, round).await;
+        });
+    }
+
+    async fn handle_rematch_timeout(&self, game_id: &str, round: u32) {
+        let mut games_write = self.games.write().await;
+        let Some(game_state) = games_write.get_mut(game_id) else {
+            return;
+        };
+        // A round is only ever offered as a rematch once
+        if !matches!(game_state, GameState::REMATCH { round: offered, .. } if *offered == round) {
+            return;
+        }
+        info!("Rematch of game {} timed out", game_id);
+
+        let ids = self.reject_rematch(game_state);
+        drop(games_write);
+        self.active_players
+            .write()
+            .await
+            .retain(|p, _| !ids.contains(p));
+        self.publish_rematch_rejected(game_id).await;
+    }
+
+    // Counts the player in for the rematch, their stake is already held, and deals the
+    // board once everyone is in. Returns the game when it started.
+    fn accept_rematch(
+        &self,
+        game_state: &mut GameState,
+        player_id: &str,
+        client_seed: Option<String>,
+    ) -> Option<GameState> {
+        let GameState::REMATCH {
+            game_id,
+            round,
+            players,
+            board,
+            single_bet_size,
+            currency,
+            accepted,
+            bombs,
+            lives,
+            elimination,
+            turn_timeout_mode,
+            server_seed_hash,
+            server_seed,
+        } = game_state
+        else {
+            return None;
+        };
+        let index = players.iter().position(|p| p.id == player_id)?;
+        accepted[index] = 1;
+        players[index].client_seed = client_seed.unwrap_or_else(generate_client_seed);
+        if !accepted.iter().all(|&x| x == 1) {
+            return None;
+        }
+
+        let client_seeds: Vec<String> = players.iter().map(|p| p.client_seed.clone()).collect();
+        let turn_deadline = self.next_turn_deadline();
+        self.schedule_turn_timeout(game_id.clone(), turn_deadline);
+        let started = GameState::RUNNING {
+            game_id: game_id.clone(),
+            round: *round,
+            players: players.clone(),
+            board: Board::new(
+                board.geometry,
+                *bombs as usize,
+                board.mode,
+                board.layout,
+                server_seed,
+                &client_seeds,
+            ),
+            turn_idx: 0,
+            single_bet_size: *single_bet_size,
+            currency: *currency,
+            locks: None,
+            turn_deadline,
+            turn_timeout_mode: *turn_timeout_mode,
+            turn_moved: false,
+            disconnected: Vec::new(),
+            lives: *lives,
+            elimination: *elimination,
+            lives_left: vec![lives.unwrap_or(1); players.len()],
+            eliminated: Vec::new(),
+            server_seed_hash: server_seed_hash.clone(),
+            server_seed: server_seed.clone(),
+        };
+        *game_state = started.clone();
+        Some(started)
+    }
+
+    // Ends a rematch that can no longer start and releases the stakes reserved for it.
+    // Returns the players who were seated in it.
+    fn reject_rematch(&self, game_state: &mut GameState) -> Vec<String> {
+        let GameState::REMATCH {
+            game_id,
+            round,
+            players,
+            ..
+        } = game_state
+        else {
+            return Vec::new();
+        };
+        self.release_stakes(game_id, *round);
+        let ids = players.iter().map(|p| p.id.clone()).collect();
+        *game_state = GameState::RematchRejected {
+            game_id: game_id.clone(),
+        };
+        ids
+    }
+
+    async fn publish_rematch_rejected(&self, game_id: &str) {
+        let wrapper = GameMessageWrapper {
+            server_id: self.server_id.clone(),
+            game_message: GameMessage::GameUpdate(GameState::RematchRejected {
+                game_id: game_id.to_string(),
+            }),
+        };
+        let _ = self
+            .publish_message(game_id.to_string(), wrapper, false)
+            .await;
+        // Clean up broadcast channel since the rematch is over
+        self.cleanup_broadcast_channel(game_id).await;
+    }
+
+    // Ends a running game, ranked as it stands, and settles the bets
+    async fn finish_game(&self, game_state: &mut GameState) {
+        let Some(finished) = game_state.finished() else {
+            return;
+        };
+        *game_state = finished.clone();
+
+        if let GameState::FINISHED {
+            game_id, players, ..
+        } = &finished
+        {
+            let ids: Vec<String> = players.iter().map(|p| p.id.clone()).collect();
+            self.active_players
+                .write()
+                .await
+                .retain(|id, _| !ids.contains(id));
+            self.settle_game(&finished);
+            self.reveal_game_on_chain(&finished);
+            self.save_game_state(game_id.clone(), finished.clone()).await;
+        }
+    }
+
+    // Holds the player's stake before they take a seat
+    async fn reserve_stake(
+        &self,
+        game_id: &str,
+        round: u32,
+        player_id: &str,
+        amount: Amount,
+        currency: Currency,
+    ) -> Result<(), GameError> {
+        let user_id = player_id.parse::<i32>().map_err(|_| GameError::NotInGame)?;
+        match db::reserve_stake(
+            &self.pool,
+            game_id,
+            round as i32,
+            user_id,
+            amount,
+            currency,
+        )
+        .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(GameError::InsufficientBalance),
+            Err(e) => {
+                error!("Failed to reserve stake for {}: {}", player_id, e);
+                Err(GameError::StakeUnavailable)
+            }
+        }
+    }
+
+    // Returns the stakes of a round that will never be settled
+    fn release_stakes(&self, game_id: &str, round: u32) {
+        self.resolve_escrow(game_id, round, EscrowAction::Release { user_id: None });
+    }
+
+    // Returns a stake reserved for a seat that's gone by the time it was held
+    fn release_player_stake(&self, game_id: &str, round: u32, player_id: &str) {
+        let Ok(user_id) = player_id.parse::<i32>() else {
+            return;
+        };
+        self.resolve_escrow(
+            game_id,
+            round,
+            EscrowAction::Release {
+                user_id: Some(user_id),
+            },
+        );
+    }
+
+    // Seats a player in a waiting game and starts it once it's full. The seat is
+    // claimed as pending before the stake is reserved, outside the games lock, so
+    // concurrent joins can't overfill the game. None when the game isn't waiting for
+    // players.
+    async fn seat_player(
+        &self,
+        game_id: &str,
+        player: Player,
+    ) -> Result<Option<GameState>, GameError> {
+        let (single_bet_size, currency) = {
+            let mut games_write = self.games.write().await;
+            let Some(GameState::WAITING {
+                single_bet_size,
+                currency,
+                min_players,
+                players,
+                seating,
+                ..
+            }) = games_write.get_mut(game_id)
+            else {
+                return Ok(None);
+            };
+            if players.iter().any(|p| p.id == player.id) || seating.contains(&player.id) {
+                return Err(GameError::AlreadySeated);
+            }
+            // The remaining seats are kept for players whose stakes are being reserved
+            if players.len() + seating.len() >= *min_players as usize {
+                return Ok(None);
+            }
+            seating.push(player.id.clone());
+            (*single_bet_size, *currency)
+        };
+
+        let reserved = self
+            .reserve_stake(game_id, 0, &player.id, single_bet_size, currency)
+            .await;
+
+        let mut games_write = self.games.write().await;
+        let waiting = match games_write.get_mut(game_id) {
+            // Unless the player left in the meantime
+            Some(GameState::WAITING { seating, .. }) if seating.contains(&player.id) => {
+                seating.retain(|id| *id != player.id);
+                true
+            }
+            _ => false,
+        };
+        reserved?;
+        if !waiting {
+            // The game was aborted or the player left in the meantime, the stake has
+            // no seat anymore
+            drop(games_write);
+            self.release_player_stake(game_id, 0, &player.id);
+            return Ok(None);
+        }
+
+        let Some(GameState::WAITING {
+            game_id,
+            creator,
+            board,
+            single_bet_size,
+            currency,
+            min_players,
+            players,
+            seating,
+            bombs,
+            lives,
+            elimination,
+            turn_timeout_mode,
+            server_seed_hash,
+            server_seed,
+        }) = games_write.get(game_id).cloned()
+        else {
+            return Ok(None);
+        };
+
+        let mut players = players;
+        players.push(player);
+
+        let new_state = if players.len() < min_players as usize {
+            GameState::WAITING {
+                game_id: game_id.clone(),
+                creator,
+                board,
+                single_bet_size,
+                currency,
+                min_players,
+                players,
+                seating,
+                bombs,
+                lives,
+                elimination,
+                turn_timeout_mode,
+                server_seed_hash,
+                server_seed,
+            }
+        } else {
+            // Every contribution is in, place the bombs
+            let client_seeds: Vec<String> =
+                players.iter().map(|p| p.client_seed.clone()).collect();
+            let board = Board::new(
+                board.geometry,
+                bombs as usize,
+                board.mode,
+                board.layout,
+                &server_seed,
+                &client_seeds,
+            );
+            self.initialize_game_on_chain(&game_id, board.geometry, &server_seed_hash);
 
-        Ok(())
+            let turn_deadline = self.next_turn_deadline();
+            self.schedule_turn_timeout(game_id.clone(), turn_deadline);
+
+            let lives_left = vec![lives.unwrap_or(1); players.len()];
+            GameState::RUNNING {
+                game_id: game_id.clone(),
+                round: 0,
+                players,
+                board,
+                turn_idx: 0,
+                single_bet_size,
+                currency,
+                locks: None,
+                turn_deadline,
+                turn_timeout_mode,
+                turn_moved: false,
+                disconnected: Vec::new(),
+                lives,
+                elimination,
+                lives_left,
+                eliminated: Vec::new(),
+                server_seed_hash,
+                server_seed,
+            }
+        };
+        games_write.insert(game_id, new_state.clone());
+        Ok(Some(new_state))
     }
 
-    // Simplified publish method for local broadcasting only
-    pub async fn publish_message(
-        &self,
-        channel: String,
-        game_message_wrapper: GameMessageWrapper,
-        _from_redis: bool, // Not needed anymore since we're local only
-    ) -> Result<()> {
-        info!("--------------------------------");
-        info!("Publishing message to channel: {:?}", channel);
-        info!("--------------------------------");
-        if let Some(broadcast_tx) = self.broadcast_channels.read().await.get(&channel) {
-            info!("--------------------------------");
-            info!("Sending message to channel: {:?}", channel);
-            info!("--------------------------------");
-            let _ = broadcast_tx.send(game_message_wrapper.game_message);
+    // Tells discovery about a new seat, a game that started stops accepting players
+    async fn announce_seat(&self, state: &GameState) -> Result<()> {
+        match state {
+            GameState::WAITING {
+                game_id, players, ..
+            } => {
+                self.discovery
+                    .update_player_count(game_id, players.len() as u32)
+                    .await
+            }
+            GameState::RUNNING { game_id, .. } => {
+                self.discovery.remove_game_session(game_id).await
+            }
+            _ => Ok(()),
         }
-        Ok(())
     }
 
-    // Add new cleanup method
-    pub async fn cleanup_player(&self, player_id: &str) {
-        // Remove from active players
-        let mut active_players_write = self.active_players.write().await;
-        active_players_write.remove(player_id);
-
-        // Check if player is in any WAITING games and clean those up
-        let mut games_write = self.games.write().await;
-        let mut games_to_abort = Vec::new();
+    // Every path that finishes a game ends up here, settling twice is a no-op
+    fn settle_game(&self, finished: &GameState) {
+        let GameState::FINISHED {
+            game_id,
+            round,
+            ranking,
+            players,
+            single_bet_size,
+            currency,
+            elimination,
+            ..
+        } = finished
+        else {
+            return;
+        };
 
-        for (game_id, state) in games_write.iter() {
-            if let GameState::WAITING { creator, .. } = state {
-                if creator.id == player_id {
-                    games_to_abort.push(game_id.clone());
-                }
+        let places: Result<Vec<Vec<i32>>, _> = ranking
+            .iter()
+            .map(|place| {
+                place
+                    .iter()
+                    .map(|&idx| players[idx].id.parse::<i32>())
+                    .collect()
+            })
+            .collect();
+        let action = match places {
+            Ok(places) => EscrowAction::Settle {
+                places,
+                single_bet_size: *single_bet_size,
+                currency: *currency,
+                payout: elimination.map_or(Payout::default(), |elimination| elimination.payout),
+            },
+            Err(e) => {
+                // Nobody can be paid, everyone gets their stake back instead
+                error!("Failed to settle game {}, a player id isn't a user id: {}", game_id, e);
+                EscrowAction::Release { user_id: None }
             }
-        }
+        };
+        self.resolve_escrow(game_id, *round, action);
+    }
 
-        // Abort any WAITING games where this player was the creator
-        for game_id in games_to_abort {
-            let aborted_state = GameState::ABORTED {
-                game_id: game_id.clone(),
+    // Queues what's owed to a round's stakes and tries it right away. Whatever doesn't
+    // go through is left queued for the escrow sweeper.
+    fn resolve_escrow(&self, game_id: &str, round: u32, action: EscrowAction) {
+        let pool = self.pool.clone();
+        let game_id = game_id.to_string();
+        tokio::spawn(async move {
+            let mut backoff = Duration::from_secs(1);
+            let id = loop {
+                match db::queue_escrow_action(&pool, &game_id, round as i32, &action).await {
+                    Ok(id) => break id,
+                    Err(e) => {
+                        error!("Failed to queue {:?} for game {}: {}", action, game_id, e);
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(Duration::from_secs(60));
+                    }
+                }
             };
-            games_write.insert(game_id.clone(), aborted_state);
+            if let Err(e) =
+                db::resolve_escrow_action(&pool, id, &game_id, round as i32, &action).await
+            {
+                error!("Failed {:?} for game {}, will retry: {}", action, game_id, e);
+            }
+        });
+    }
 
-            // Only remove from discovery service, no need to save state
-            let _ = self.discovery.remove_game_session(&game_id).await;
-        }
+    // Retries the settlements and releases that didn't go through, for as long as the
+    // server runs. Every server sweeps, resolving an action twice is a no-op.
+    fn spawn_escrow_sweeper(&self) {
+        let pool = self.pool.clone();
+        let every = self.escrow_retry;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                match db::resolve_pending_escrow_actions(&pool, every).await {
+                    Ok(0) => {}
+                    Ok(resolved) => info!("Resolved {} pending escrow actions", resolved),
+                    Err(e) => error!("Failed to sweep pending escrow actions: {}", e),
+                }
+            }
+        });
     }
 
     // Modify the matchmaking logic in handle_play_message
+    #[allow(clippy::too_many_arguments)]
     async fn handle_play_message(
         &self,
         player_id: String,
         name: String,
-        single_bet_size: f64,
+        single_bet_size: Amount,
         min_players: u32,
         bombs: u32,
-        grid: u32,
+        geometry: Geometry,
         is_creating_room: bool,
+        client_seed: String,
+        turn_timeout_mode: TurnTimeoutMode,
+        currency: Currency,
+        board_mode: BoardMode,
+        bomb_layout: BombLayout,
+        lives: Option<u32>,
+        elimination: Option<Elimination>,
     ) -> Result<Option<GameState>> {
         info!("Handling play message");
+        // A negative stake would credit whoever loses it
+        if single_bet_size <= Amount::ZERO {
+            return Err(anyhow::anyhow!(
+                "Bet size must be positive, got {}",
+                single_bet_size.to_decimal(currency)
+            ));
+        }
+
         // First check if player is already in a game
         let active_players_read = self.active_players.read().await;
         if active_players_read.contains_key(&player_id) {
@@ -316,66 +1965,57 @@ impl GameRegistry {
         }
         drop(active_players_read);
 
+        if bombs == 0 || bombs as usize >= geometry.cells() {
+            return Err(anyhow::anyhow!(
+                "Invalid board: {} bombs on a {} grid",
+                bombs,
+                geometry
+            ));
+        }
+        if geometry.width > MAX_BOARD_SIDE
+            || geometry.height > MAX_BOARD_SIDE
+            || geometry.cells() > MAX_BOARD_CELLS
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid board: a {} grid is larger than {} cells or {} a side",
+                geometry,
+                MAX_BOARD_CELLS,
+                MAX_BOARD_SIDE
+            ));
+        }
+        if lives == Some(0) {
+            return Err(anyhow::anyhow!("Players need at least one life"));
+        }
+        if elimination.is_some() && min_players <= 2 {
+            return Err(anyhow::anyhow!(
+                "Elimination games need more than two players, got {}",
+                min_players
+            ));
+        }
+        // Bombs only knock out whoever hit them, once they're out of lives
+        let lives = elimination.map_or(lives, |_| Some(lives.unwrap_or(1)));
+
         // Try to find an existing game session through discovery service
         // let current_region = env::var("FLY_REGION").unwrap_or_else(|_| "unknown".to_string());
         if let Some(session) = self
             .discovery
-            .find_game_session(single_bet_size, min_players, grid)
+            .find_game_session(
+                currency,
+                single_bet_size,
+                min_players,
+                geometry,
+                board_mode,
+                bomb_layout,
+                lives,
+                elimination,
+            )
             .await?
         {
             // If the session is on this server, get it from local state
             if session.server_id == self.server_id {
-                let state = {
-                    let games_read = self.games.read().await;
-                    if let Some(state) = games_read.get(&session.game_id) {
-                        Some(state.clone())
-                    } else {
-                        None
-                    }
-                };
-
-                if let Some(GameState::WAITING {
-                    game_id,
-                    creator,
-                    board,
-                    single_bet_size,
-                    min_players,
-                    mut players,
-                }) = state
-                {
-                    let player = Player::new(player_id.clone(), name.clone());
-                    players.push(player);
-
-                    // Update player count in Redis
-                    self.discovery
-                        .update_player_count(&game_id, players.len() as u32)
-                        .await?;
-
-                    let new_state = if players.len() < min_players as usize {
-                        GameState::WAITING {
-                            game_id: game_id.clone(),
-                            creator,
-                            board,
-                            single_bet_size,
-                            min_players,
-                            players,
-                        }
-                    } else {
-                        // Game is transitioning to RUNNING state
-                        // Remove from discovery since it's no longer accepting players
-                        self.discovery.remove_game_session(&game_id).await?;
-                        GameState::RUNNING {
-                            game_id: game_id.clone(),
-                            players,
-                            board,
-                            turn_idx: 0,
-                            single_bet_size,
-                            locks: None,
-                        }
-                    };
-
-                    let mut games_write = self.games.write().await;
-                    games_write.insert(game_id.clone(), new_state.clone());
+                let player = Player::new(player_id.clone(), name.clone(), client_seed);
+                if let Some(new_state) = self.seat_player(&session.game_id, player).await? {
+                    self.announce_seat(&new_state).await?;
                     return Ok(Some(new_state));
                 }
             }
@@ -385,51 +2025,27 @@ impl GameRegistry {
 
         // Create new game if no suitable session found
         let game_id = Uuid::new_v4().to_string();
-        let board = Board::new(grid as usize, bombs as usize);
-        let player = Player::new(player_id.clone(), name.clone());
+        self.reserve_stake(&game_id, 0, &player_id, single_bet_size, currency)
+            .await?;
+        let server_seed = ServerSeed::generate();
+        let player = Player::new(player_id.clone(), name.clone(), client_seed);
 
         let game_state = GameState::WAITING {
             game_id: game_id.clone(),
             creator: player.clone(),
-            board: board.clone(),
+            board: Board::hidden(geometry, board_mode, bomb_layout),
             single_bet_size,
+            currency,
             min_players,
             players: vec![player.clone()],
+            seating: Vec::new(),
+            bombs,
+            lives,
+            elimination,
+            turn_timeout_mode,
+            server_seed_hash: server_seed.hash(),
+            server_seed,
         };
-        // Initialize game on blockchain
-        let registry_clone = self.clone();
-        let game_id_clone = game_id.clone();
-        let grid_size = board.n as u32;
-        let bomb_positions: Vec<(usize, usize)> = board
-            .bomb_coordinates
-            .iter()
-            .map(|&pos| {
-                let x = (pos / board.n as u64) as usize;
-                let y = (pos % board.n as u64) as usize;
-                (x, y)
-            })
-            .collect();
-
-        tokio::spawn(async move {
-            if let Ok(tx_hash) = registry_clone
-                .xplode_moves
-                .initialize_game(&game_id_clone, grid_size, bomb_positions)
-                .await
-            {
-                let update = GameMessage::BlockchainUpdate {
-                    game_id: game_id_clone.clone(),
-                    update_type: BlockchainUpdateType::GameInitialized,
-                    transaction_hash: tx_hash,
-                };
-                let wrapper = GameMessageWrapper {
-                    server_id: registry_clone.server_id.clone(),
-                    game_message: update,
-                };
-                let _ = registry_clone
-                    .publish_message(game_id_clone.clone(), wrapper, false)
-                    .await;
-            }
-        });
 
         info!("--------------------------------");
         info!("Ahoy");
@@ -442,8 +2058,8 @@ impl GameRegistry {
             // Send Telegram notification.
             let game_url = format!("https://playxplode.xyz/multiplayer/{}", game_id);
             let notification_message = format!(
-            "🎮 New game created!\n\nGame URL: {}\nCreator: {}\nBet Size: {}\nMin Players: {}\nGrid Size: {}x{}\nBombs: {}\nIs Creating Room: {}",
-            game_url, name, single_bet_size, min_players, grid, grid, bombs, is_creating_room);
+            "🎮 New game created!\n\nGame URL: {}\nCreator: {}\nBet Size: {}\nMin Players: {}\nGrid: {}\nBombs: {} ({})\nIs Creating Room: {}",
+            game_url, name, single_bet_size.to_decimal(currency), min_players, geometry, bombs, bomb_layout, is_creating_room);
             if let Err(e) = send_telegram_message(&notification_message).await {
                 error!("Failed to send Telegram notification: {}", e);
             }
@@ -453,12 +2069,21 @@ impl GameRegistry {
         let session = GameSession {
             game_id: game_id.clone(),
             server_id: self.server_id.clone(),
+            currency,
             single_bet_size,
             min_players,
             current_players: 1,
-            grid_size: grid,
+            geometry,
+            board_mode,
+            bomb_layout,
+            lives,
+            elimination,
         };
-        self.discovery.register_game_session(session).await?;
+        if let Err(e) = self.discovery.register_game_session(session).await {
+            // The game never opens, so the creator's stake goes back
+            self.release_stakes(&game_id, 0);
+            return Err(e);
+        }
 
         info!("Storing game state in local state");
         info!("--------------------------------");
@@ -493,13 +2118,14 @@ impl GameServer {
 
         Self {
             server_id: server_id.clone(),
-            registry: GameRegistry::new(redis_client, server_id),
+            registry: GameRegistry::new(redis_client, server_id, establish_connection().await),
         }
     }
 
     pub async fn start(&self, addr: &str) -> anyhow::Result<()> {
         let listener = TcpListener::bind(addr).await?;
         info!("Server listening on {}", addr);
+        self.registry.spawn_escrow_sweeper();
 
         while let std::result::Result::Ok((stream, _)) = listener.accept().await {
             let registry = self.registry.clone();
@@ -556,9 +2182,21 @@ impl GameServer {
                 }
             }
         }
-        let ws_stream = ServerBuilder::new().accept(stream).await?;
-        let pool = establish_connection().await;
 
+        // Only signed sessions may open a socket, the token decides who the player is
+        let claims = match extract_session_token(data).map(|token| verify_session_token(&token)) {
+            Some(Ok(claims)) => claims,
+            Some(Err(e)) => {
+                info!("Rejecting websocket upgrade: {}", e);
+                return reject_upgrade(stream).await;
+            }
+            None => {
+                info!("Rejecting websocket upgrade: missing session token");
+                return reject_upgrade(stream).await;
+            }
+        };
+
+        let ws_stream = ServerBuilder::new().accept(stream).await?;
         let (ws_write, mut ws_read) = ws_stream.split();
 
         let ws_write = Arc::new(Mutex::new(ws_write));
@@ -567,11 +2205,17 @@ impl GameServer {
         let (server_tx, mut server_rx) = tokio::sync::mpsc::channel(500);
         let server_tx = Arc::new(server_tx);
 
-        // Keep track of the current player_id for cleanup
-        let current_player_id = Arc::new(RwLock::new(String::new()));
+        // Player this connection is authenticated as, also used for cleanup
+        let current_player_id = Arc::new(RwLock::new(claims.user_id.to_string()));
+        let connection_id = Uuid::new_v4().to_string();
+        registry
+            .connections
+            .write()
+            .await
+            .insert(claims.user_id.to_string(), connection_id.clone());
 
         // Spawn a task to handle incoming WebSocket messages
-        let _: tokio::task::JoinHandle<()> = tokio::spawn({
+        tokio::spawn({
             let server_tx = server_tx.clone();
             let current_player_id = current_player_id.clone();
             let registry_clone = registry.clone();
@@ -582,18 +2226,13 @@ impl GameServer {
 
                     match msg {
                         Ok(message) => {
-                            let current_player_id = current_player_id.clone();
                             tokio::spawn(async move {
-                                match serde_json::from_slice(message.as_payload()) {
+                                match serde_json::from_slice::<GameMessage>(message.as_payload()) {
                                     Ok(game_msg) => {
                                         info!("msg: {:?}", game_msg);
-                                        // Update current_player_id if this is a Play or Join message
-                                        if let GameMessage::Play { player_id, .. } = &game_msg {
-                                            *current_player_id.write().await = player_id.clone();
-                                        } else if let GameMessage::Join { player_id, .. } =
-                                            &game_msg
-                                        {
-                                            *current_player_id.write().await = player_id.clone();
+                                        if game_msg.is_server_only() {
+                                            eprintln!("Dropping server-only message from client");
+                                            return;
                                         }
                                         if let Err(e) = server_tx_inner.send(game_msg).await {
                                             eprintln!("Error sending message: {}", e);
@@ -614,48 +2253,47 @@ impl GameServer {
 
                 // WebSocket connection closed - clean up the player
                 let player_id = current_player_id.read().await.clone();
-                if !player_id.is_empty() {
-                    let server_tx_inner = server_tx.clone();
-                    let active_players_read = registry_clone.active_players.read().await;
-                    let game_id = active_players_read.get(&player_id);
-                    if let Some(game_id) = game_id {
-                        let game_id_clone = game_id.clone();
-                        let game_state = registry_clone.get_game_state(&game_id).await;
-                        if let Some(GameState::RUNNING {
-                            game_id,
-                            players,
-                            board,
-                            single_bet_size,
-                            ..
-                        }) = game_state
-                        {
-                            let loser_idx = players.iter().position(|p| p.id == player_id).unwrap();
-                            let new_game_state = GameState::FINISHED {
-                                game_id,
-                                loser_idx,
-                                board: board.clone(),
-                                players: players.clone(),
-                                single_bet_size: single_bet_size,
-                            };
-
-                            let game_message = GameMessage::GameUpdate(new_game_state);
-
-                            server_tx_inner.send(game_message).await.unwrap();
+                if !player_id.is_empty()
+                    && registry_clone
+                        .release_connection(&player_id, &connection_id)
+                        .await
+                {
+                    let game_id = registry_clone
+                        .active_players
+                        .read()
+                        .await
+                        .get(&player_id)
+                        .cloned();
 
-                            // Clean up broadcast channel since player has left
-                            registry_clone
-                                .cleanup_broadcast_channel(&game_id_clone)
-                                .await;
-                        }
+                    // A running game waits for the player to come back
+                    let seat_held = match game_id {
+                        Some(game_id) => registry_clone.hold_seat(&game_id, &player_id).await,
+                        None => false,
+                    };
+                    if !seat_held {
+                        info!("Cleaning up player: {}", player_id);
+                        registry_clone.cleanup_player(&player_id).await;
                     }
-                    drop(active_players_read);
-                    info!("Cleaning up player: {}", player_id);
-                    registry_clone.cleanup_player(&player_id).await;
                 }
             }
         });
         // Process game messages
         while let Some(message) = server_rx.recv().await {
+            let connection_player_id = current_player_id.read().await.clone();
+            if let Some(claimed) = message.claimed_player_id() {
+                if claimed != connection_player_id {
+                    send_message(
+                        &ws_write,
+                        &GameMessage::Rejected {
+                            game_id: None,
+                            error: GameError::IdentityMismatch,
+                        },
+                    )
+                    .await?;
+                    continue;
+                }
+            }
+
             match message {
                 GameMessage::Ping { game_id, player_id } => {
                     info!("Pong sent from {}", server_id);
@@ -670,9 +2308,27 @@ impl GameServer {
                             .await?;
                     }
 
-                    if let Some(player_id) = player_id {
-                        let mut active_players_write = registry.active_players.write().await;
-                        active_players_write.insert(player_id, game_id.unwrap());
+                    if let (Some(game_id), Some(player_id)) = (game_id, player_id) {
+                        // Only a seated player is active in the game they ping
+                        let games_read = registry.games.read().await;
+                        if games_read
+                            .get(&game_id)
+                            .is_some_and(|game_state| game_state.has_player(&player_id))
+                        {
+                            let mut active_players_write = registry.active_players.write().await;
+                            active_players_write.insert(player_id.clone(), game_id.clone());
+                        }
+                        drop(games_read);
+
+                        // A ping on a held seat counts as coming back
+                        if let Ok(game_state) = registry.resume_player(&game_id, &player_id).await
+                        {
+                            let wrapper = GameMessageWrapper {
+                                server_id: server_id.clone(),
+                                game_message: GameMessage::GameUpdate(game_state),
+                            };
+                            registry.publish_message(game_id, wrapper, false).await?;
+                        }
                     }
                     let response = "Pong".to_string();
                     if let Err(e) = ws_write
@@ -684,6 +2340,37 @@ impl GameServer {
                         eprintln!("Error sending GameUpdate message: {}", e);
                     }
                 }
+                GameMessage::Resume { game_id, player_id } => {
+                    info!("Player {} resuming game {}", player_id, game_id);
+                    match registry.resume_player(&game_id, &player_id).await {
+                        Ok(game_state) => {
+                            registry
+                                .subscribe_to_channel(
+                                    server_id.clone(),
+                                    game_id.clone(),
+                                    ws_write.clone(),
+                                )
+                                .await?;
+                            let mut active_players_write = registry.active_players.write().await;
+                            active_players_write.insert(player_id, game_id.clone());
+                            drop(active_players_write);
+
+                            // Everyone sees the seat taken again, the new socket included
+                            let wrapper = GameMessageWrapper {
+                                server_id: server_id.clone(),
+                                game_message: GameMessage::GameUpdate(game_state),
+                            };
+                            registry.publish_message(game_id, wrapper, false).await?;
+                        }
+                        Err(error) => {
+                            let rejection = GameMessage::Rejected {
+                                game_id: Some(game_id),
+                                error,
+                            };
+                            send_message(&ws_write, &rejection).await?;
+                        }
+                    }
+                }
                 GameMessage::Play {
                     player_id,
                     name,
@@ -691,7 +2378,17 @@ impl GameServer {
                     min_players,
                     bombs,
                     grid,
+                    width,
+                    height,
+                    topology,
                     is_creating_room,
+                    client_seed,
+                    turn_timeout_mode,
+                    currency,
+                    board_mode,
+                    bomb_layout,
+                    lives,
+                    elimination,
                 } => {
                     info!("Play request at machine: {}", server_id);
                     let active_players_read = registry.active_players.read().await;
@@ -709,6 +2406,7 @@ impl GameServer {
                     }
                     drop(active_players_read);
 
+                    let geometry = play_geometry(grid, width, height, topology);
                     // Try to find or create a game using discovery service
                     match registry
                         .handle_play_message(
@@ -717,8 +2415,15 @@ impl GameServer {
                             single_bet_size,
                             min_players,
                             bombs,
-                            grid,
+                            geometry,
                             is_creating_room,
+                            client_seed.unwrap_or_else(generate_client_seed),
+                            turn_timeout_mode,
+                            currency,
+                            board_mode,
+                            bomb_layout,
+                            lives,
+                            elimination,
                         )
                         .await
                     {
@@ -748,7 +2453,7 @@ impl GameServer {
                             info!("Game Message: {:?}", game_message);
                             let wrapper = GameMessageWrapper {
                                 server_id: server_id.clone(),
-                                game_message: game_message,
+                                game_message,
                             };
 
                             registry
@@ -762,7 +2467,16 @@ impl GameServer {
                             // Game exists on another server, send redirect message
                             if let Some(session) = registry
                                 .discovery
-                                .find_game_session(single_bet_size, min_players, grid)
+                                .find_game_session(
+                                    currency,
+                                    single_bet_size,
+                                    min_players,
+                                    geometry,
+                                    board_mode,
+                                    bomb_layout,
+                                    lives,
+                                    elimination,
+                                )
                                 .await?
                             {
                                 let redirect = GameMessage::RedirectToServer {
@@ -802,100 +2516,31 @@ impl GameServer {
                     game_id,
                     player_id,
                     name,
+                    client_seed,
                 } => {
                     info!("Join request at machine: {}", server_id);
                     info!("Request to join:: {:?} game", game_id);
 
-                    // let games_read = registry.games.read().await;
-                    // info!("Game keys: {:?}", games_read.keys().len());
-                    let game_state = registry.get_game_state(&game_id).await;
-                    // let game_state = registry.get_game_state(&game_id).await;
-                    info!("Game state: {:?}", game_state);
+                    let new_player = Player::new(
+                        player_id.clone(),
+                        name.clone(),
+                        client_seed.unwrap_or_else(generate_client_seed),
+                    );
+                    let seated = match registry.seat_player(&game_id, new_player).await {
+                        Ok(seated) => seated,
+                        Err(error) => {
+                            let rejection = GameMessage::Rejected {
+                                game_id: Some(game_id),
+                                error,
+                            };
+                            send_message(&ws_write, &rejection).await?;
+                            continue;
+                        }
+                    };
                     info!("About to join game");
-                    if let Some(GameState::WAITING {
-                        game_id,
-                        creator,
-                        board,
-                        single_bet_size,
-                        min_players,
-                        players,
-                    }) = game_state
-                    {
-                        info!("Inside waiting state");
-                        let new_player = Player::new(player_id.clone(), name.clone());
-                        let mut players = players.clone();
-                        players.push(new_player);
-
-                        // Update player count in Redis
-                        info!("Updating player count in Redis");
-                        registry
-                            .discovery
-                            .update_player_count(&game_id, players.len() as u32)
-                            .await?;
-
-                        let new_game_state = if players.len() < min_players as usize {
-                            GameState::WAITING {
-                                game_id: game_id.clone(),
-                                creator: creator.clone(),
-                                board: board.clone(),
-                                single_bet_size,
-                                min_players,
-                                players,
-                            }
-                        } else {
-                            // Game is transitioning to RUNNING state
-                            // Remove from discovery since it's no longer accepting players
-                            registry.discovery.remove_game_session(&game_id).await?;
-
-                            // Initialize game on blockchain
-                            let registry_clone = registry.clone();
-                            let game_id_clone = game_id.clone();
-                            let grid_size = board.n as u32;
-                            let bomb_positions: Vec<(usize, usize)> = board
-                                .bomb_coordinates
-                                .iter()
-                                .map(|&pos| {
-                                    let x = (pos / board.n as u64) as usize;
-                                    let y = (pos % board.n as u64) as usize;
-                                    (x, y)
-                                })
-                                .collect();
-                            tokio::spawn(async move {
-                                if let Ok(tx_hash) = registry_clone
-                                    .xplode_moves
-                                    .initialize_game(&game_id_clone, grid_size, bomb_positions)
-                                    .await
-                                {
-                                    let update = GameMessage::BlockchainUpdate {
-                                        game_id: game_id_clone.clone(),
-                                        update_type: BlockchainUpdateType::GameInitialized,
-                                        transaction_hash: tx_hash,
-                                    };
-                                    let wrapper = GameMessageWrapper {
-                                        server_id: registry_clone.server_id.clone(),
-                                        game_message: update,
-                                    };
-                                    let _ = registry_clone
-                                        .publish_message(game_id_clone.clone(), wrapper, false)
-                                        .await;
-                                }
-                            });
-
-                            GameState::RUNNING {
-                                game_id: game_id.clone(),
-                                players,
-                                board: board.clone(),
-                                turn_idx: 0,
-                                single_bet_size: single_bet_size,
-                                locks: None,
-                            }
-                        };
-
-                        let mut games_write = registry.games.write().await;
-
-                        games_write.insert(game_id.clone(), new_game_state.clone());
-
-                        drop(games_write);
+                    if let Some(new_game_state) = seated {
+                        info!("Seated in waiting game");
+                        registry.announce_seat(&new_game_state).await?;
 
                         registry
                             .subscribe_to_channel(
@@ -953,26 +2598,34 @@ impl GameServer {
                 }
                 GameMessage::Stop { game_id, abort } => {
                     let mut games_write = registry.games.write().await;
-                    if !abort {
-                        // Meaning other players won
+                    if let Err(error) = games_write
+                        .get(&game_id)
+                        .ok_or(GameError::GameNotFound)
+                        .and_then(|game_state| game_state.check_stop(&connection_player_id, abort))
+                    {
+                        drop(games_write);
+                        let rejection = GameMessage::Rejected {
+                            game_id: Some(game_id),
+                            error,
+                        };
+                        send_message(&ws_write, &rejection).await?;
+                        continue;
+                    }
+                    // Only a lobby is aborted and refunded, once the game runs an abort is
+                    // conceded like any stop so the stakes still settle
+                    let aborting =
+                        matches!(games_write.get(&game_id), Some(GameState::WAITING { .. }));
+                    if !aborting {
+                        // The turn holder gives up. The others win, unless enough of
+                        // them are left to play on
                         if let Some(game_state) = games_write.get_mut(&game_id) {
-                            if let GameState::RUNNING {
-                                players,
-                                board,
-                                turn_idx,
-                                single_bet_size,
-                                ..
-                            } = game_state
-                            {
-                                info!("Hello about to stop the game**************************************");
-                                let loser = turn_idx;
-                                let new_game_state = GameState::FINISHED {
-                                    game_id: game_id.clone(),
-                                    loser_idx: *loser,
-                                    board: board.clone(),
-                                    players: players.clone(),
-                                    single_bet_size: *single_bet_size,
-                                };
+                            let conceding = match game_state {
+                                GameState::RUNNING { turn_idx, .. } => *turn_idx,
+                                _ => continue,
+                            };
+                            if game_state.take_lives(conceding, true) == LifeLost::GameOver {
+                                info!("Player {} gave up game {}", conceding, game_id);
+                                registry.finish_game(game_state).await;
 
                                 // Commit game on blockchain
                                 let registry_clone = registry.clone();
@@ -997,71 +2650,31 @@ impl GameServer {
                                             .await;
                                     }
                                 });
+                            } else {
+                                let turn_deadline = registry.next_turn_deadline();
+                                game_state.advance_turn(turn_deadline);
+                                registry.schedule_turn_timeout(game_id.clone(), turn_deadline);
+                            }
 
-                                // remove players from active state
-                                let mut active_players_write =
-                                    registry.active_players.write().await;
-
-                                let ids = players.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
-
-                                active_players_write.retain(|x, _| !ids.contains(x));
+                            let game_message = GameMessage::GameUpdate(game_state.clone());
+                            let wrapper = GameMessageWrapper {
+                                server_id: server_id.clone(),
+                                game_message,
+                            };
 
-                                // Update discovery service
-                                registry
-                                    .save_game_state(game_id.clone(), new_game_state.clone())
-                                    .await;
-
-                                // UPDATING THE DB AS WELL HERE
-                                let winning_amount =
-                                    *single_bet_size / ((players.clone().len() - 1) as f64);
-
-                                let user_ids: Vec<i32> = players
-                                    .iter()
-                                    .map(|p| p.id.parse::<i32>().unwrap())
-                                    .collect();
-                                db::update_player_balances(
-                                    &pool,
-                                    &user_ids,
-                                    *loser,
-                                    *single_bet_size,
-                                    winning_amount,
-                                    Currency::SOL,
-                                )
+                            registry
+                                .publish_message(game_id.clone(), wrapper, false)
                                 .await?;
-                                *game_state = new_game_state;
-                                let game_message = GameMessage::GameUpdate(game_state.clone());
-
-                                let wrapper = GameMessageWrapper {
-                                    server_id: server_id.clone(),
-                                    game_message,
-                                };
-
-                                registry
-                                    .publish_message(game_id.clone(), wrapper, false)
-                                    .await?;
-                            }
                         }
                     } else {
-                        // Game is being aborted
+                        // The lobby is being aborted
                         if let Some(game_state) = games_write.get_mut(&game_id) {
-                            match game_state {
-                                GameState::RUNNING { players, .. } => {
-                                    let mut active_players_write =
-                                        registry.active_players.write().await;
-                                    let ids =
-                                        players.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
-                                    active_players_write.retain(|x, _| !ids.contains(x));
-                                }
-                                GameState::WAITING { players, .. } => {
-                                    let mut active_players_write =
-                                        registry.active_players.write().await;
-                                    let ids =
-                                        players.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
-                                    active_players_write.retain(|x, _| !ids.contains(x));
-                                }
-                                _ => {
-                                    // Do nothing
-                                }
+                            if let GameState::WAITING { players, .. } = game_state {
+                                let mut active_players_write =
+                                    registry.active_players.write().await;
+                                let ids = players.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+                                active_players_write.retain(|x, _| !ids.contains(x));
+                                registry.release_stakes(&game_id, 0);
                             }
 
                             let aborted_state = GameState::ABORTED {
@@ -1091,6 +2704,19 @@ impl GameServer {
                 }
                 GameMessage::MakeMove { game_id, x, y } => {
                     let mut games_write = registry.games.write().await;
+                    if let Err(error) = games_write
+                        .get(&game_id)
+                        .ok_or(GameError::GameNotFound)
+                        .and_then(|game_state| game_state.check_move(&connection_player_id, x, y))
+                    {
+                        drop(games_write);
+                        let rejection = GameMessage::Rejected {
+                            game_id: Some(game_id),
+                            error,
+                        };
+                        send_message(&ws_write, &rejection).await?;
+                        continue;
+                    }
 
                     if let Some(game_state) = games_write.get_mut(&game_id) {
                         match game_state {
@@ -1098,26 +2724,22 @@ impl GameServer {
                                 players,
                                 board,
                                 turn_idx,
-                                single_bet_size,
-                                locks,
                                 ..
                             } => {
-                                let game_ended = board.mine(x, y);
+                                let hit = board.mine(x, y).bomb;
 
                                 // Clone everything we need before any modifications
                                 let players_clone = players.clone();
                                 let turn_idx_clone = *turn_idx;
-                                let single_bet_size_clone = *single_bet_size;
-
-                                if game_ended {
-                                    let new_game_state = GameState::FINISHED {
-                                        game_id: game_id.clone(),
-                                        loser_idx: turn_idx_clone,
-                                        board: board.clone(),
-                                        players: players_clone.clone(),
-                                        single_bet_size: single_bet_size_clone,
-                                    };
-                                    *game_state = new_game_state.clone();
+
+                                let lost = if hit {
+                                    game_state.take_lives(turn_idx_clone, false)
+                                } else {
+                                    LifeLost::Survived
+                                };
+
+                                if game_state.is_over() {
+                                    registry.finish_game(game_state).await;
 
                                     // Record move and commit game on blockchain
                                     let registry_clone = registry.clone();
@@ -1177,80 +2799,42 @@ impl GameServer {
                                         //         .await;
                                         // }
                                     });
-
-                                    // Async DB operations
-                                    let winning_amount =
-                                        single_bet_size_clone / ((players_clone.len() - 1) as f64);
-                                    let user_ids: Vec<i32> = players_clone
-                                        .iter()
-                                        .map(|p| p.id.parse::<i32>().unwrap())
-                                        .collect();
-
-                                    // remove players from active state
-                                    let mut active_players_write =
-                                        registry.active_players.write().await;
-
-                                    let ids = players_clone
-                                        .iter()
-                                        .map(|p| p.id.clone())
-                                        .collect::<Vec<_>>();
-
-                                    active_players_write.retain(|x, _| !ids.contains(x));
-
-                                    // Update discovery service
-                                    registry
-                                        .save_game_state(game_id.clone(), new_game_state)
-                                        .await;
-
-                                    let pool_clone = pool.clone();
-                                    tokio::spawn(async move {
-                                        let _ = db::update_player_balances(
-                                            &pool_clone,
-                                            &user_ids,
-                                            turn_idx_clone,
-                                            single_bet_size_clone,
-                                            winning_amount,
-                                            Currency::SOL,
-                                        )
-                                        .await;
-                                    });
-                                } else {
+                                } else if lost == LifeLost::Eliminated {
+                                    // Out of lives, the turn passes without a lock phase
+                                    let turn_deadline = registry.next_turn_deadline();
+                                    game_state.advance_turn(turn_deadline);
+                                    registry.schedule_turn_timeout(game_id.clone(), turn_deadline);
+
+                                    registry.record_move_on_chain(
+                                        &game_id,
+                                        players_clone[turn_idx_clone].name.clone(),
+                                        x,
+                                        y,
+                                    );
+                                } else if let GameState::RUNNING {
+                                    locks,
+                                    turn_deadline,
+                                    turn_moved,
+                                    ..
+                                } = game_state
+                                {
                                     // Not needed here as they will be updated in lock complete
                                     // *turn_idx = (*turn_idx + 1) % players.len();
                                     info!("Setting locks to None, befor locks value: {:?}", *locks);
                                     *locks = None;
 
+                                    // Fresh deadline for the lock phase
+                                    *turn_moved = true;
+                                    *turn_deadline = registry.next_turn_deadline();
+                                    registry.schedule_turn_timeout(game_id.clone(), *turn_deadline);
+
                                     // Record move on blockchain
-                                    let registry_clone = registry.clone();
-                                    let game_id_clone = game_id.clone();
-                                    let player_name = players[turn_idx_clone].name.clone();
-                                    let x_clone = x;
-                                    let y_clone = y;
-                                    tokio::spawn(async move {
-                                        if let Ok(tx_hash) = registry_clone
-                                            .xplode_moves
-                                            .record_move(
-                                                &game_id_clone,
-                                                &player_name,
-                                                x_clone,
-                                                y_clone,
-                                            )
-                                            .await
-                                        {
-                                            let update = GameMessage::BlockchainUpdate {
-                                                game_id: game_id_clone.clone(),
-                                                update_type: BlockchainUpdateType::MoveRecorded,
-                                                transaction_hash: tx_hash,
-                                            };
-                                            let wrapper = GameMessageWrapper {
-                                                server_id: registry_clone.server_id.clone(),
-                                                game_message: update,
-                                            };
-                                            let _ = registry_clone
-                                                .publish_message(game_id_clone, wrapper, false)
-                                                .await;
-                                        }
-                                    });
+                                    registry.record_move_on_chain(
+                                        &game_id,
+                                        players_clone[turn_idx_clone].name.clone(),
+                                        x,
+                                        y,
+                                    );
                                 }
 
                                 // Broadcast the update for both cases
@@ -1281,6 +2865,19 @@ impl GameServer {
                 }
                 GameMessage::Lock { x, y, game_id } => {
                     let mut games_write = registry.games.write().await;
+                    if let Err(error) = games_write
+                        .get(&game_id)
+                        .ok_or(GameError::GameNotFound)
+                        .and_then(|game_state| game_state.check_lock(&connection_player_id, x, y))
+                    {
+                        drop(games_write);
+                        let rejection = GameMessage::Rejected {
+                            game_id: Some(game_id),
+                            error,
+                        };
+                        send_message(&ws_write, &rejection).await?;
+                        continue;
+                    }
 
                     if let Some(game_state) = games_write.get_mut(&game_id) {
                         if let GameState::RUNNING { locks, .. } = game_state {
@@ -1303,14 +2900,26 @@ impl GameServer {
                 }
                 GameMessage::LockComplete { game_id } => {
                     let mut games_write = registry.games.write().await;
+                    if let Err(error) = games_write
+                        .get(&game_id)
+                        .ok_or(GameError::GameNotFound)
+                        .and_then(|game_state| {
+                            game_state.check_lock_complete(&connection_player_id)
+                        })
+                    {
+                        drop(games_write);
+                        let rejection = GameMessage::Rejected {
+                            game_id: Some(game_id),
+                            error,
+                        };
+                        send_message(&ws_write, &rejection).await?;
+                        continue;
+                    }
 
                     if let Some(game_state) = games_write.get_mut(&game_id) {
-                        if let GameState::RUNNING {
-                            turn_idx, players, ..
-                        } = game_state
-                        {
-                            *turn_idx = (*turn_idx + 1) % players.len();
-                        }
+                        let turn_deadline = registry.next_turn_deadline();
+                        game_state.advance_turn(turn_deadline);
+                        registry.schedule_turn_timeout(game_id.clone(), turn_deadline);
 
                         let game_message = GameMessage::GameUpdate(game_state.clone());
                         let wrapper = GameMessageWrapper {
@@ -1327,59 +2936,163 @@ impl GameServer {
                 GameMessage::RematchRequest {
                     game_id,
                     requester_id,
+                    client_seed,
                 } => {
                     info!("--------------------------------");
                     info!("Rematch request received");
                     info!("--------------------------------");
-                    let mut games_write = registry.games.write().await;
-                    if let Some(game_state) = games_write.get_mut(&game_id) {
-                        if let GameState::FINISHED {
-                            game_id,
-                            board,
+                    // Checked under the games lock, the stake is reserved without it
+                    let stake = match registry.games.read().await.get(&game_id) {
+                        Some(GameState::FINISHED {
+                            round,
                             players,
                             single_bet_size,
+                            currency,
                             ..
-                        } = game_state
-                        {
-                            let grid = board.n;
-                            let bombs = board.bomb_coordinates.len();
-                            let new_board = Board::new(grid as usize, bombs as usize);
-
-                            let (index, _) = players
-                                .iter()
-                                .enumerate()
-                                .find(|(_, p)| *p.id == requester_id)
-                                .expect("Failed to find player id in player array");
-
-                            let mut rematch_acceptants = vec![0 as usize; players.len()];
-                            rematch_acceptants[index] = 1;
-                            let new_game_state = GameState::REMATCH {
-                                game_id: game_id.clone(),
-                                players: players.clone(),
-                                board: new_board,
-                                single_bet_size: single_bet_size.clone(),
-                                accepted: rematch_acceptants,
+                        }) => {
+                            // Only a seated player may stake on the next round
+                            if players.iter().any(|p| p.id == requester_id) {
+                                Ok((*round + 1, *single_bet_size, *currency))
+                            } else {
+                                Err(GameError::NotInGame)
+                            }
+                        }
+                        _ => continue,
+                    };
+                    let (next_round, single_bet_size, currency) = match stake {
+                        Ok(stake) => stake,
+                        Err(error) => {
+                            let rejection = GameMessage::Rejected {
+                                game_id: Some(game_id),
+                                error,
                             };
+                            send_message(&ws_write, &rejection).await?;
+                            continue;
+                        }
+                    };
+                    if let Err(error) = registry
+                        .reserve_stake(
+                            &game_id,
+                            next_round,
+                            &requester_id,
+                            single_bet_size,
+                            currency,
+                        )
+                        .await
+                    {
+                        let rejection = GameMessage::Rejected {
+                            game_id: Some(game_id),
+                            error,
+                        };
+                        send_message(&ws_write, &rejection).await?;
+                        continue;
+                    }
 
-                            let mut active_players = registry.active_players.write().await;
-                            active_players.insert(requester_id.clone(), game_id.clone());
-
-                            let game_message = GameMessage::RematchRequest {
-                                game_id: game_id.clone(),
-                                requester_id: requester_id.clone(),
-                            };
+                    // The game may have moved on while the stake was being reserved
+                    let mut games_write = registry.games.write().await;
+                    let offers_rematch = matches!(
+                        games_write.get(&game_id),
+                        Some(GameState::FINISHED { round, .. }) if *round + 1 == next_round
+                    );
+                    let joins_rematch = matches!(
+                        games_write.get(&game_id),
+                        Some(GameState::REMATCH { round, .. }) if *round == next_round
+                    );
+                    let Some(game_state) = games_write
+                        .get_mut(&game_id)
+                        .filter(|_| offers_rematch || joins_rematch)
+                    else {
+                        drop(games_write);
+                        registry.release_player_stake(&game_id, next_round, &requester_id);
+                        let rejection = GameMessage::Rejected {
+                            game_id: Some(game_id),
+                            error: GameError::GameNotRunning,
+                        };
+                        send_message(&ws_write, &rejection).await?;
+                        continue;
+                    };
 
+                    if joins_rematch {
+                        // Another player asked first, asking too accepts their rematch
+                        let started =
+                            registry.accept_rematch(game_state, &requester_id, client_seed);
+                        let mut active_players = registry.active_players.write().await;
+                        active_players.insert(requester_id.clone(), game_id.clone());
+                        drop(active_players);
+                        if let Some(started) = started {
                             let wrapper = GameMessageWrapper {
                                 server_id: server_id.clone(),
-                                game_message,
+                                game_message: GameMessage::GameUpdate(started),
                             };
-
                             registry
-                                .publish_message(game_id.clone(), wrapper.clone(), false)
+                                .publish_message(game_id.clone(), wrapper, false)
                                 .await?;
-
-                            *game_state = new_game_state.clone();
                         }
+                        continue;
+                    }
+
+                    if let GameState::FINISHED {
+                        game_id,
+                        round,
+                        board,
+                        players,
+                        single_bet_size,
+                        currency,
+                        turn_timeout_mode,
+                        lives,
+                        elimination,
+                        ..
+                    } = game_state
+                    {
+                        let Some(index) = players.iter().position(|p| p.id == requester_id) else {
+                            continue;
+                        };
+                        let bombs = board.bomb_coordinates.len() as u32;
+                        // Fresh commitment, contributions are collected again
+                        let server_seed = ServerSeed::generate();
+
+                        let mut players = players.clone();
+                        players[index].client_seed =
+                            client_seed.unwrap_or_else(generate_client_seed);
+
+                        let mut rematch_acceptants = vec![0_usize; players.len()];
+                        rematch_acceptants[index] = 1;
+                        let new_game_state = GameState::REMATCH {
+                            game_id: game_id.clone(),
+                            round: *round + 1,
+                            players,
+                            board: Board::hidden(board.geometry, board.mode, board.layout),
+                            single_bet_size: *single_bet_size,
+                            currency: *currency,
+                            accepted: rematch_acceptants,
+                            bombs,
+                            lives: *lives,
+                            elimination: *elimination,
+                            turn_timeout_mode: *turn_timeout_mode,
+                            server_seed_hash: server_seed.hash(),
+                            server_seed,
+                        };
+
+                        let mut active_players = registry.active_players.write().await;
+                        active_players.insert(requester_id.clone(), game_id.clone());
+
+                        let game_message = GameMessage::RematchRequest {
+                            game_id: game_id.clone(),
+                            requester_id: requester_id.clone(),
+                            client_seed: None,
+                        };
+
+                        let wrapper = GameMessageWrapper {
+                            server_id: server_id.clone(),
+                            game_message,
+                        };
+
+                        registry
+                            .publish_message(game_id.clone(), wrapper.clone(), false)
+                            .await?;
+
+                        registry.schedule_rematch_timeout(game_id.clone(), *round + 1);
+                        *game_state = new_game_state.clone();
                     }
                 }
 
@@ -1387,73 +3100,94 @@ impl GameServer {
                     game_id,
                     player_id,
                     want_rematch,
+                    client_seed,
                 } => {
-                    let mut games_write = registry.games.write().await;
-                    if let Some(game_state) = games_write.get_mut(&game_id) {
-                        if let GameState::REMATCH {
-                            game_id,
+                    // Checked under the games lock, the stake is reserved without it
+                    let stake = match registry.games.read().await.get(&game_id) {
+                        Some(GameState::REMATCH {
+                            round,
                             players,
-                            board,
                             single_bet_size,
-                            accepted,
+                            currency,
                             ..
-                        } = game_state
-                        {
-                            if want_rematch {
-                                let (index, _) = players
-                                    .iter()
-                                    .enumerate()
-                                    .find(|(_, p)| *p.id == player_id)
-                                    .expect("Failed to find player id in player array");
-
-                                accepted[index] = 1;
-
-                                let mut active_players = registry.active_players.write().await;
-                                active_players.insert(player_id.clone(), game_id.clone());
-
-                                if accepted.iter().all(|&x| x == 1) {
-                                    let new_game_state = GameState::RUNNING {
-                                        game_id: game_id.clone(),
-                                        players: players.clone(),
-                                        board: board.clone(),
-                                        turn_idx: 0,
-                                        single_bet_size: single_bet_size.clone(),
-                                        locks: None,
-                                    };
-
-                                    let game_message =
-                                        GameMessage::GameUpdate(new_game_state.clone());
-                                    let wrapper = GameMessageWrapper {
-                                        server_id: server_id.clone(),
-                                        game_message,
-                                    };
-
-                                    registry
-                                        .publish_message(game_id.clone(), wrapper.clone(), false)
-                                        .await?;
-                                    *game_state = new_game_state.clone();
-                                }
+                        }) => {
+                            if players.iter().any(|p| p.id == player_id) {
+                                Ok((*round, *single_bet_size, *currency))
                             } else {
-                                let mut active_players = registry.active_players.write().await;
-                                active_players
-                                    .retain(|p, _| !players.iter().any(|player| player.id == *p));
-                                let new_game_state = GameState::RematchRejected {
-                                    game_id: game_id.clone(),
-                                };
-                                let game_message = GameMessage::GameUpdate(new_game_state.clone());
-                                let wrapper = GameMessageWrapper {
-                                    server_id: server_id.clone(),
-                                    game_message,
-                                };
+                                Err(GameError::NotInGame)
+                            }
+                        }
+                        _ => continue,
+                    };
+                    let (round, single_bet_size, currency) = match stake {
+                        Ok(stake) => stake,
+                        Err(error) => {
+                            let rejection = GameMessage::Rejected {
+                                game_id: Some(game_id),
+                                error,
+                            };
+                            send_message(&ws_write, &rejection).await?;
+                            continue;
+                        }
+                    };
 
-                                registry
-                                    .publish_message(game_id.clone(), wrapper.clone(), false)
-                                    .await?;
+                    if want_rematch {
+                        if let Err(error) = registry
+                            .reserve_stake(&game_id, round, &player_id, single_bet_size, currency)
+                            .await
+                        {
+                            let rejection = GameMessage::Rejected {
+                                game_id: Some(game_id),
+                                error,
+                            };
+                            send_message(&ws_write, &rejection).await?;
+                            continue;
+                        }
+                    }
 
-                                // Clean up broadcast channel since rematch was rejected
-                                registry.cleanup_broadcast_channel(&game_id).await;
-                            }
+                    // The rematch may have ended while the stake was being reserved
+                    let mut games_write = registry.games.write().await;
+                    let still_offered = matches!(
+                        games_write.get(&game_id),
+                        Some(GameState::REMATCH { round: offered, .. }) if *offered == round
+                    );
+                    let Some(game_state) = games_write
+                        .get_mut(&game_id)
+                        .filter(|_| still_offered)
+                    else {
+                        drop(games_write);
+                        if want_rematch {
+                            registry.release_player_stake(&game_id, round, &player_id);
+                        }
+                        let rejection = GameMessage::Rejected {
+                            game_id: Some(game_id),
+                            error: GameError::GameNotRunning,
+                        };
+                        send_message(&ws_write, &rejection).await?;
+                        continue;
+                    };
+
+                    if want_rematch {
+                        let started = registry.accept_rematch(game_state, &player_id, client_seed);
+                        let mut active_players = registry.active_players.write().await;
+                        active_players.insert(player_id.clone(), game_id.clone());
+                        drop(active_players);
+                        if let Some(started) = started {
+                            let wrapper = GameMessageWrapper {
+                                server_id: server_id.clone(),
+                                game_message: GameMessage::GameUpdate(started),
+                            };
+                            registry
+                                .publish_message(game_id.clone(), wrapper, false)
+                                .await?;
                         }
+                    } else {
+                        let ids = registry.reject_rematch(game_state);
+                        let mut active_players = registry.active_players.write().await;
+                        active_players.retain(|p, _| !ids.contains(p));
+                        drop(active_players);
+                        drop(games_write);
+                        registry.publish_rematch_rejected(&game_id).await;
                     }
                 }
 
@@ -1465,7 +3199,7 @@ impl GameServer {
                     let game_message = GameMessage::Gif {
                         game_id: game_id.clone(),
                         player_id: player_id.clone(),
-                        gif_id: gif_id.clone(),
+                        gif_id,
                     };
 
                     let wrapper = GameMessageWrapper {
@@ -1494,10 +3228,8 @@ impl GameServer {
                                 .await?;
                         }
                         GameState::FINISHED {
-                            game_id,
-                            loser_idx,
-                            players,
-                            single_bet_size,
+                            ref game_id,
+                            ref players,
                             ..
                         } => {
                             registry
@@ -1511,21 +3243,7 @@ impl GameServer {
 
                             active_players_write.retain(|x, _| !ids.contains(x));
                             // Update the db
-                            let winning_amount = single_bet_size / ((players.len() - 1) as f64);
-
-                            let user_ids: Vec<i32> = players
-                                .iter()
-                                .map(|p| p.id.parse::<i32>().unwrap())
-                                .collect();
-                            db::update_player_balances(
-                                &pool,
-                                &user_ids,
-                                loser_idx,
-                                single_bet_size,
-                                winning_amount,
-                                Currency::SOL,
-                            )
-                            .await?;
+                            registry.settle_game(&msg);
                         }
                         GameState::RematchRejected { game_id } => {
                             registry
@@ -1614,6 +3332,16 @@ impl GameServer {
 //     .await?;
 // }
 
+// Sends a message to this connection only
+async fn send_message(ws_write: &Arc<Mutex<WebSocketSink>>, message: &GameMessage) -> Result<()> {
+    ws_write
+        .lock()
+        .await
+        .send(Message::binary(serde_json::to_vec(message)?))
+        .await?;
+    Ok(())
+}
+
 // Helper function to parse HTTP headers from a byte slice
 fn parse_http_headers(data: &[u8]) -> Result<HashMap<String, HeaderValue>, anyhow::Error> {
     let mut headers = HashMap::new();
@@ -1696,6 +3424,40 @@ fn parse_query_string(query: &str) -> HashMap<String, String> {
     params
 }
 
+// Session token from the `token` query parameter, the session cookie or a bearer header
+fn extract_session_token(data: &[u8]) -> Option<String> {
+    if let Some(uri) = parse_request_uri(data) {
+        if let Some(query_pos) = uri.find('?') {
+            let params = parse_query_string(&uri[query_pos + 1..]);
+            if let Some(token) = params.get("token") {
+                return Some(token.clone());
+            }
+        }
+    }
+
+    let headers = parse_http_headers(data).ok()?;
+    let cookies = parse_cookies(headers.get("cookie"));
+    if let Some(token) = cookies.get("xplode_session") {
+        return Some(token.clone());
+    }
+
+    headers
+        .get("authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.to_string())
+}
+
+async fn reject_upgrade(mut stream: TcpStream) -> anyhow::Result<()> {
+    let response = "HTTP/1.1 401 Unauthorized\r\n\
+             Content-Length: 0\r\n\
+             Connection: close\r\n\r\n";
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
 // Extract the machine ID from a WebSocket request
 fn extract_machine_id(data: &[u8], server_id: &str) -> Option<String> {
     info!("Extracting machine ID");
@@ -1728,3 +3490,490 @@ fn extract_machine_id(data: &[u8], server_id: &str) -> Option<String> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bomb_coordinates(message: &GameMessage, variant: &str) -> serde_json::Value {
+        let json = serde_json::to_value(message).unwrap();
+        json["GameUpdate"][variant]["board"]["bomb_coordinates"].clone()
+    }
+
+    // Nothing listens on these, any call reaching redis or the ledger fails
+    fn offline_registry() -> GameRegistry {
+        let redis = Client::open("redis://127.0.0.1:1").unwrap();
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://xplode@127.0.0.1:1/xplode")
+            .unwrap();
+        GameRegistry::new(redis, "server".into(), pool)
+    }
+
+    fn running_game(board: Board) -> GameState {
+        let server_seed = ServerSeed::generate();
+        GameState::RUNNING {
+            game_id: "game".into(),
+            round: 0,
+            players: vec![
+                Player::new("1".into(), "alice".into(), "a".into()),
+                Player::new("2".into(), "bob".into(), "b".into()),
+            ],
+            board,
+            turn_idx: 0,
+            single_bet_size: Amount::from_units(1_000_000_000),
+            currency: Currency::SOL,
+            locks: None,
+            turn_deadline: 0,
+            turn_timeout_mode: TurnTimeoutMode::Forfeit,
+            turn_moved: false,
+            disconnected: Vec::new(),
+            lives: None,
+            elimination: None,
+            lives_left: vec![1, 1],
+            eliminated: Vec::new(),
+            server_seed_hash: server_seed.hash(),
+            server_seed,
+        }
+    }
+
+    #[test]
+    fn only_turn_holder_may_mine_hidden_cells() {
+        let mut board = Board::hidden(
+            Geometry::square(3),
+            BoardMode::Standard,
+            BombLayout::Uniform,
+        );
+        board.mine(1, 1);
+        let state = running_game(board);
+
+        assert_eq!(state.check_move("1", 0, 0), Ok(()));
+        assert_eq!(state.check_move("2", 0, 0), Err(GameError::NotYourTurn));
+        assert_eq!(state.check_move("3", 0, 0), Err(GameError::NotInGame));
+        assert_eq!(
+            state.check_move("1", 3, 0),
+            Err(GameError::OutOfBounds { x: 3, y: 0 })
+        );
+        assert_eq!(
+            state.check_move("1", 1, 1),
+            Err(GameError::AlreadyMined { x: 1, y: 1 })
+        );
+    }
+
+    #[test]
+    fn turn_holder_mines_once_then_locks() {
+        let mut state = running_game(Board::hidden(
+            Geometry::square(3),
+            BoardMode::Standard,
+            BombLayout::Uniform,
+        ));
+        assert_eq!(state.check_lock("1", 0, 0), Err(GameError::MoveRequired));
+        assert_eq!(state.check_lock_complete("1"), Err(GameError::MoveRequired));
+
+        if let GameState::RUNNING { turn_moved, .. } = &mut state {
+            *turn_moved = true;
+        }
+        assert_eq!(state.check_move("1", 0, 0), Err(GameError::AlreadyMoved));
+        assert_eq!(state.check_lock("1", 0, 0), Ok(()));
+        assert_eq!(state.check_lock_complete("1"), Ok(()));
+
+        state.advance_turn(42);
+        assert_eq!(state.check_move("2", 0, 0), Ok(()));
+        assert!(matches!(
+            state,
+            GameState::RUNNING {
+                turn_deadline: 42,
+                turn_moved: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn only_the_turn_holder_stops_a_running_game() {
+        let mut state = running_game(Board::hidden(
+            Geometry::square(3),
+            BoardMode::Standard,
+            BombLayout::Uniform,
+        ));
+        // An abort is a concession once the game runs, so it's the turn holder's
+        assert_eq!(state.check_stop("2", true), Err(GameError::NotYourTurn));
+        assert_eq!(state.check_stop("1", true), Ok(()));
+
+        assert_eq!(state.take_lives(0, true), LifeLost::GameOver);
+        let Some(GameState::FINISHED { loser_idx, .. }) = state.finished() else {
+            panic!("the game should be finished");
+        };
+        assert_eq!(loser_idx, 0);
+    }
+
+    #[tokio::test]
+    async fn games_with_a_player_who_isnt_a_user_are_left_unsettled() {
+        let registry = offline_registry();
+        let mut state = running_game(Board::hidden(
+            Geometry::square(3),
+            BoardMode::Standard,
+            BombLayout::Uniform,
+        ));
+        if let GameState::RUNNING { players, .. } = &mut state {
+            players[1].id = "guest".into();
+        }
+        state.take_lives(0, true);
+        let finished = state.finished().unwrap();
+
+        // Logged and released instead of panicking with the games lock held
+        registry.settle_game(&finished);
+    }
+
+    #[test]
+    fn only_seated_players_are_in_a_game() {
+        let state = running_game(Board::hidden(
+            Geometry::square(3),
+            BoardMode::Standard,
+            BombLayout::Uniform,
+        ));
+
+        assert!(state.has_player("2"));
+        assert!(!state.has_player("3"));
+        assert!(!GameState::ABORTED {
+            game_id: "game".into()
+        }
+        .has_player("1"));
+    }
+
+    #[test]
+    fn nobody_moves_while_a_seat_is_held() {
+        let mut state = running_game(Board::hidden(
+            Geometry::square(3),
+            BoardMode::Standard,
+            BombLayout::Uniform,
+        ));
+        if let GameState::RUNNING { disconnected, .. } = &mut state {
+            disconnected.push(Disconnection {
+                player_id: "2".into(),
+                reconnect_deadline: 0,
+            });
+        }
+
+        assert_eq!(state.check_move("1", 0, 0), Err(GameError::GamePaused));
+        assert_eq!(state.check_stop("1", false), Err(GameError::GamePaused));
+        assert_eq!(state.check_move("3", 0, 0), Err(GameError::NotInGame));
+    }
+
+    #[test]
+    fn lives_games_rank_players_by_elimination() {
+        let board = Board::hidden(
+            Geometry::square(3),
+            BoardMode::Standard,
+            BombLayout::Uniform,
+        );
+        let mut state = running_game(board);
+        if let GameState::RUNNING {
+            players,
+            lives,
+            lives_left,
+            ..
+        } = &mut state
+        {
+            players.push(Player::new("3".into(), "carol".into(), "c".into()));
+            *lives = Some(2);
+            *lives_left = vec![2, 2, 2];
+        }
+
+        assert_eq!(state.take_lives(0, false), LifeLost::Survived);
+        assert_eq!(state.take_lives(1, true), LifeLost::Eliminated);
+        assert!(!state.is_over());
+
+        // The turn passes over players who are out
+        state.advance_turn(0);
+        assert!(matches!(state, GameState::RUNNING { turn_idx: 2, .. }));
+        assert_eq!(state.ranking(), vec![vec![2], vec![0], vec![1]]);
+
+        assert_eq!(state.take_lives(0, false), LifeLost::GameOver);
+        let Some(GameState::FINISHED {
+            loser_idx, ranking, ..
+        }) = state.finished()
+        else {
+            panic!("the game should be finished");
+        };
+        assert_eq!(ranking, vec![vec![2], vec![0], vec![1]]);
+        assert_eq!(loser_idx, 1);
+    }
+
+    #[test]
+    fn eliminations_can_deal_a_fresh_board() {
+        let server_seed = ServerSeed::generate();
+        let board = Board::new(
+            Geometry::square(5),
+            4,
+            BoardMode::Standard,
+            BombLayout::Uniform,
+            &server_seed,
+            &["a".to_string(), "b".to_string(), "c".to_string()],
+        );
+        let mut state = running_game(board);
+        if let GameState::RUNNING {
+            players,
+            board,
+            lives,
+            elimination,
+            lives_left,
+            ..
+        } = &mut state
+        {
+            players.push(Player::new("3".into(), "carol".into(), "c".into()));
+            board.mine(0, 0);
+            *lives = Some(1);
+            *elimination = Some(Elimination {
+                reset_board: true,
+                payout: Payout::SURVIVOR,
+            });
+            *lives_left = vec![1, 1, 1];
+        }
+
+        assert_eq!(state.take_lives(0, true), LifeLost::Eliminated);
+        let GameState::RUNNING { board, .. } = &state else {
+            panic!("the game should still be running");
+        };
+        assert_eq!(board.hidden_cells().len(), 25);
+        assert_eq!(board.bomb_coordinates.len(), 4);
+
+        let elimination: Elimination = "SURVIVOR:reset".parse().unwrap();
+        assert_eq!(elimination.to_string(), "SURVIVOR:reset");
+        assert_eq!("PLACEMENT".parse::<Elimination>().unwrap(), Elimination::default());
+    }
+
+    #[tokio::test]
+    async fn negative_stakes_are_rejected_before_reserving() {
+        let registry = offline_registry();
+        let played = registry
+            .handle_play_message(
+                "1".into(),
+                "alice".into(),
+                Amount::from_units(-1_000_000_000),
+                2,
+                3,
+                Geometry::square(4),
+                false,
+                "a".into(),
+                TurnTimeoutMode::Forfeit,
+                Currency::SOL,
+                BoardMode::Standard,
+                BombLayout::Uniform,
+                None,
+                None,
+            )
+            .await;
+
+        // Rejected by the check itself, the ledger is never asked to reserve
+        let error = played.unwrap_err().to_string();
+        assert!(error.starts_with("Bet size must be positive"), "{}", error);
+        assert!(registry.games.read().await.is_empty());
+        assert!(registry.active_players.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn oversized_boards_are_rejected() {
+        let registry = offline_registry();
+        for geometry in [
+            play_geometry(0, Some(MAX_BOARD_SIDE as u32 + 1), Some(2), Topology::Square),
+            play_geometry(MAX_BOARD_SIDE as u32, None, None, Topology::Square),
+        ] {
+            let played = registry
+                .handle_play_message(
+                    "1".into(),
+                    "alice".into(),
+                    Amount::from_units(1_000_000_000),
+                    2,
+                    3,
+                    geometry,
+                    false,
+                    "a".into(),
+                    TurnTimeoutMode::Forfeit,
+                    Currency::SOL,
+                    BoardMode::Standard,
+                    BombLayout::Uniform,
+                    None,
+                    None,
+                )
+                .await;
+
+            let error = played.unwrap_err().to_string();
+            assert!(error.starts_with("Invalid board"), "{}", error);
+        }
+        assert!(registry.games.read().await.is_empty());
+    }
+
+    fn waiting_game(min_players: u32) -> GameState {
+        let server_seed = ServerSeed::generate();
+        let creator = Player::new("1".into(), "alice".into(), "a".into());
+        GameState::WAITING {
+            game_id: "game".into(),
+            creator: creator.clone(),
+            board: Board::hidden(
+                Geometry::square(4),
+                BoardMode::Standard,
+                BombLayout::Uniform,
+            ),
+            single_bet_size: Amount::from_units(1_000_000_000),
+            currency: Currency::SOL,
+            min_players,
+            players: vec![creator],
+            seating: Vec::new(),
+            bombs: 3,
+            lives: None,
+            elimination: None,
+            turn_timeout_mode: TurnTimeoutMode::Forfeit,
+            server_seed_hash: server_seed.hash(),
+            server_seed,
+        }
+    }
+
+    #[tokio::test]
+    async fn seated_players_cannot_join_twice() {
+        let registry = offline_registry();
+        registry
+            .games
+            .write()
+            .await
+            .insert("game".into(), waiting_game(3));
+
+        // Turned away before a second stake is reserved
+        let again = Player::new("1".into(), "alice".into(), "b".into());
+        assert_eq!(
+            registry.seat_player("game", again).await.unwrap_err(),
+            GameError::AlreadySeated
+        );
+        let games = registry.games.read().await;
+        let Some(GameState::WAITING { players, .. }) = games.get("game") else {
+            panic!("the game should still be waiting");
+        };
+        assert_eq!(players.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn seats_pending_a_stake_are_kept() {
+        let registry = offline_registry();
+        let mut waiting = waiting_game(2);
+        if let GameState::WAITING { seating, .. } = &mut waiting {
+            seating.push("2".into());
+        }
+        registry.games.write().await.insert("game".into(), waiting);
+
+        let bob = Player::new("2".into(), "bob".into(), "b".into());
+        assert_eq!(
+            registry.seat_player("game", bob).await.unwrap_err(),
+            GameError::AlreadySeated
+        );
+        // The last seat is bob's until his stake is in
+        let carol = Player::new("3".into(), "carol".into(), "c".into());
+        assert!(matches!(registry.seat_player("game", carol).await, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn a_seat_is_given_back_when_its_stake_cant_be_held() {
+        let registry = offline_registry();
+        registry
+            .games
+            .write()
+            .await
+            .insert("game".into(), waiting_game(3));
+
+        let bob = Player::new("2".into(), "bob".into(), "b".into());
+        assert_eq!(
+            registry.seat_player("game", bob).await.unwrap_err(),
+            GameError::StakeUnavailable
+        );
+        let games = registry.games.read().await;
+        let Some(GameState::WAITING {
+            players, seating, ..
+        }) = games.get("game")
+        else {
+            panic!("the game should still be waiting");
+        };
+        assert_eq!(players.len(), 1);
+        assert!(seating.is_empty());
+    }
+
+    #[tokio::test]
+    async fn players_who_leave_a_lobby_give_up_their_seat() {
+        let registry = offline_registry();
+        let mut waiting = waiting_game(4);
+        if let GameState::WAITING {
+            players, seating, ..
+        } = &mut waiting
+        {
+            players.push(Player::new("2".into(), "bob".into(), "b".into()));
+            seating.push("3".into());
+        }
+        registry.games.write().await.insert("game".into(), waiting);
+
+        registry.cleanup_player("2").await;
+        registry.cleanup_player("3").await;
+
+        let games = registry.games.read().await;
+        let Some(GameState::WAITING {
+            players, seating, ..
+        }) = games.get("game")
+        else {
+            panic!("the game should still be waiting");
+        };
+        assert_eq!(players.len(), 1);
+        assert!(seating.is_empty());
+    }
+
+    #[test]
+    fn bombs_are_only_sent_once_finished() {
+        let server_seed = ServerSeed::generate();
+        let players = vec![Player::new("1".into(), "alice".into(), "a".into())];
+        let board = Board::new(
+            Geometry::square(4),
+            3,
+            BoardMode::Standard,
+            BombLayout::Uniform,
+            &server_seed,
+            &["a".to_string()],
+        );
+
+        let running = GameMessage::GameUpdate(GameState::RUNNING {
+            game_id: "game".into(),
+            round: 0,
+            players: players.clone(),
+            board: board.clone(),
+            turn_idx: 0,
+            single_bet_size: Amount::from_units(1_000_000_000),
+            currency: Currency::SOL,
+            locks: None,
+            turn_deadline: 0,
+            turn_timeout_mode: TurnTimeoutMode::Forfeit,
+            turn_moved: false,
+            disconnected: Vec::new(),
+            lives: None,
+            elimination: None,
+            lives_left: vec![1],
+            eliminated: Vec::new(),
+            server_seed_hash: server_seed.hash(),
+            server_seed: server_seed.clone(),
+        });
+        assert_eq!(bomb_coordinates(&running, "RUNNING"), serde_json::json!([]));
+
+        let finished = GameMessage::GameUpdate(GameState::FINISHED {
+            game_id: "game".into(),
+            round: 0,
+            loser_idx: 0,
+            ranking: vec![vec![0]],
+            board: board.clone(),
+            players,
+            lives: None,
+            elimination: None,
+            single_bet_size: Amount::from_units(1_000_000_000),
+            currency: Currency::SOL,
+            turn_timeout_mode: TurnTimeoutMode::Forfeit,
+            server_seed: server_seed.reveal(),
+        });
+        assert_eq!(
+            bomb_coordinates(&finished, "FINISHED"),
+            serde_json::json!(board.bomb_coordinates)
+        );
+    }
+}
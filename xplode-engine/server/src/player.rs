@@ -5,10 +5,17 @@ use serde::{Deserialize, Serialize};
 pub struct Player {
     pub id: String,
     pub name: String,
+    // Contribution folded into the board seed
+    #[serde(default)]
+    pub client_seed: String,
 }
 
 impl Player {
-    pub fn new(id: String, name: String) -> Player {
-        Player { id, name }
+    pub fn new(id: String, name: String, client_seed: String) -> Player {
+        Player {
+            id,
+            name,
+            client_seed,
+        }
     }
 }
@@ -1,47 +1,304 @@
-#![allow(dead_code)]
-use std::collections::HashSet;
-
-use rand::{rngs::StdRng, RngCore, SeedableRng};
 use sha3::{Digest, Sha3_256};
 
-struct DistributedSeedGen {
+use crate::board::{BombLayout, Geometry, Topology};
+
+/// Secret picked by the server when a game is created. Only its hash is shared
+/// while the game is in progress, the seed itself is revealed once it finishes.
+#[derive(Debug, Clone, Default)]
+pub struct ServerSeed([u8; 32]);
+
+impl ServerSeed {
+    pub fn generate() -> Self {
+        ServerSeed(rand::random())
+    }
+
+    pub fn from_hex(seed: &str) -> anyhow::Result<Self> {
+        let bytes: [u8; 32] = hex::decode(seed)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Server seed must be 32 bytes"))?;
+        Ok(ServerSeed(bytes))
+    }
+
+    // Commitment sent to clients before any player contributes
+    pub fn hash(&self) -> String {
+        hex::encode(DistributedSeedGen::new(self).seed_hash)
+    }
+
+    pub fn reveal(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+/// Random contribution used when a client doesn't send its own seed
+pub fn generate_client_seed() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+pub struct DistributedSeedGen {
     pub seed_hash: [u8; 32],
 }
 
 impl DistributedSeedGen {
-    fn new(genesis_contrib: u64) -> Self {
-        let mut hasher = sha3::Sha3_256::new();
+    pub fn new(server_seed: &ServerSeed) -> Self {
+        let mut hasher = Sha3_256::new();
 
-        hasher.update(genesis_contrib.to_be_bytes());
+        hasher.update(server_seed.0);
 
         let seed_hash: [u8; 32] = hasher.finalize().into();
 
         DistributedSeedGen { seed_hash }
     }
 
-    fn update_seed_hash(&mut self, new_contrib: u64) {
+    pub fn update_seed_hash(&mut self, new_contrib: &[u8]) {
         let mut hasher = Sha3_256::new();
         hasher.update(self.seed_hash);
-        hasher.update(new_contrib.to_be_bytes());
+        hasher.update(new_contrib);
 
         self.seed_hash = hasher.finalize().into();
     }
 
-    fn seed(&self) -> u64 {
-        // take first 8 bytes from hash and parse it to u64
+    // n-th draw of the hash chain, parsed from the first 8 bytes
+    fn draw(&self, round: u64) -> u64 {
+        let mut hasher = Sha3_256::new();
+        hasher.update(self.seed_hash);
+        hasher.update(round.to_be_bytes());
+        let digest: [u8; 32] = hasher.finalize().into();
+
+        u64::from_be_bytes(digest[..8].try_into().unwrap())
+    }
+}
+
+// Bombs grown into one blob before starting the next
+const CLUSTER_SIZE: usize = 4;
+// Longest run of bombs a line is drawn with
+const LINE_LENGTH: usize = 4;
 
-        u64::from_be_bytes(self.seed_hash[..8].try_into().unwrap())
+// Consecutive draws of the hash chain, each reduced below a bound
+struct Draws {
+    seed_gen: DistributedSeedGen,
+    round: u64,
+}
+
+impl Draws {
+    fn below(&mut self, bound: u64) -> u64 {
+        let value = self.seed_gen.draw(self.round) % bound;
+        self.round += 1;
+        value
     }
 }
 
-pub fn get_bomb_coords(bombs_needed: usize, dimension: u64) -> Vec<u64> {
-    let seed = rand::random();
-    let mut rng = StdRng::seed_from_u64(seed);
+/// Derives the bomb layout from the server seed and every player's contribution,
+/// folded in player order, as positions on the board. Anyone holding the revealed
+/// seed can re-run this.
+pub fn get_bomb_coords(
+    bombs_needed: usize,
+    geometry: &Geometry,
+    layout: BombLayout,
+    server_seed: &ServerSeed,
+    client_seeds: &[String],
+) -> Vec<u64> {
+    let mut seed_gen = DistributedSeedGen::new(server_seed);
+    for client_seed in client_seeds {
+        seed_gen.update_seed_hash(client_seed.as_bytes());
+    }
+    let mut draws = Draws { seed_gen, round: 0 };
 
-    let mut coords = HashSet::new();
-    while coords.len() < bombs_needed {
-        coords.insert(rng.next_u64() % (dimension * dimension));
+    let cells = geometry.cells() as u64;
+    let bombs_needed = bombs_needed.min(cells as usize);
+    let mut coords = Vec::with_capacity(bombs_needed);
+    match layout {
+        BombLayout::Uniform => {
+            while coords.len() < bombs_needed {
+                let coord = draws.below(cells);
+                if !coords.contains(&coord) {
+                    coords.push(coord);
+                }
+            }
+        }
+        BombLayout::Clustered => {
+            let mut cluster: Vec<u64> = Vec::new();
+            while coords.len() < bombs_needed {
+                // Free cells touching the blob, those touching it more often are
+                // listed more often and so likelier
+                let frontier: Vec<u64> = if cluster.len() < CLUSTER_SIZE {
+                    cluster
+                        .iter()
+                        .flat_map(|&coord| {
+                            let (x, y) = geometry.coordinates(coord);
+                            geometry.neighbors(x, y)
+                        })
+                        .map(|(x, y)| geometry.position(x, y))
+                        .filter(|coord| !coords.contains(coord))
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                let coord = if frontier.is_empty() {
+                    cluster.clear();
+                    draws.below(cells)
+                } else {
+                    frontier[draws.below(frontier.len() as u64) as usize]
+                };
+                if !coords.contains(&coord) {
+                    coords.push(coord);
+                    cluster.push(coord);
+                }
+            }
+        }
+        BombLayout::Lines => {
+            while coords.len() < bombs_needed {
+                let (mut x, mut y) = geometry.coordinates(draws.below(cells));
+                let horizontal = draws.below(2) == 0;
+                for _ in 0..LINE_LENGTH {
+                    if coords.len() == bombs_needed {
+                        break;
+                    }
+                    let coord = geometry.position(x, y);
+                    if !coords.contains(&coord) {
+                        coords.push(coord);
+                    }
+                    if horizontal {
+                        y += 1;
+                    } else {
+                        x += 1;
+                    }
+                    if geometry.topology == Topology::Torus {
+                        (x, y) = (x % geometry.height, y % geometry.width);
+                    } else if x >= geometry.height || y >= geometry.width {
+                        break;
+                    }
+                }
+            }
+        }
+        BombLayout::Gradient => {
+            // Top, bottom, left or right is the sparse edge
+            let sparse_edge = draws.below(4);
+            let (height, width) = (geometry.height as u64, geometry.width as u64);
+            while coords.len() < bombs_needed {
+                let coord = draws.below(cells);
+                let (x, y) = geometry.coordinates(coord);
+                let (x, y) = (x as u64, y as u64);
+                let (distance, span) = match sparse_edge {
+                    0 => (x, height),
+                    1 => (height - 1 - x, height),
+                    2 => (y, width),
+                    _ => (width - 1 - y, width),
+                };
+                // Kept with a chance growing from 1/span on the sparse edge to
+                // certain on the opposite one
+                if draws.below(span) <= distance && !coords.contains(&coord) {
+                    coords.push(coord);
+                }
+            }
+        }
     }
 
-    coords.into_iter().collect()
+    coords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revealed_seed_reproduces_layout() {
+        let server_seed = ServerSeed::generate();
+        let client_seeds = vec!["alice".to_string(), "bob".to_string()];
+        let coords = get_bomb_coords(
+            5,
+            &Geometry::square(4),
+            BombLayout::Uniform,
+            &server_seed,
+            &client_seeds,
+        );
+
+        let revealed = ServerSeed::from_hex(&server_seed.reveal()).unwrap();
+        assert_eq!(revealed.hash(), server_seed.hash());
+        assert_eq!(
+            get_bomb_coords(
+                5,
+                &Geometry::square(4),
+                BombLayout::Uniform,
+                &revealed,
+                &client_seeds
+            ),
+            coords
+        );
+    }
+
+    #[test]
+    fn every_contribution_changes_layout() {
+        let server_seed = ServerSeed::generate();
+        let coords = get_bomb_coords(
+            8,
+            &Geometry::square(8),
+            BombLayout::Uniform,
+            &server_seed,
+            &["alice".to_string()],
+        );
+        let other = get_bomb_coords(
+            8,
+            &Geometry::square(8),
+            BombLayout::Uniform,
+            &server_seed,
+            &["mallory".to_string()],
+        );
+
+        assert_ne!(coords, other);
+        assert_eq!(coords.len(), 8);
+        assert!(coords.iter().all(|&c| c < 64));
+    }
+
+    #[test]
+    fn every_layout_replays_within_the_board() {
+        let server_seed = ServerSeed::from_hex(&"ab".repeat(32)).unwrap();
+        let client_seeds = vec!["alice".to_string()];
+        let layouts = [
+            BombLayout::Uniform,
+            BombLayout::Clustered,
+            BombLayout::Lines,
+            BombLayout::Gradient,
+        ];
+        for topology in [Topology::Square, Topology::Hex, Topology::Torus] {
+            let geometry = Geometry {
+                width: 7,
+                height: 5,
+                topology,
+            };
+            for layout in layouts {
+                let coords = get_bomb_coords(12, &geometry, layout, &server_seed, &client_seeds);
+                let mut distinct = coords.clone();
+                distinct.sort();
+                distinct.dedup();
+
+                assert_eq!(distinct.len(), 12, "{:?} on {}", layout, geometry);
+                assert!(coords.iter().all(|&c| c < 35));
+                assert_eq!(
+                    get_bomb_coords(12, &geometry, layout, &server_seed, &client_seeds),
+                    coords
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn clustered_bombs_touch_each_other() {
+        let server_seed = ServerSeed::from_hex(&"cd".repeat(32)).unwrap();
+        let geometry = Geometry::square(10);
+        let coords = get_bomb_coords(
+            8,
+            &geometry,
+            BombLayout::Clustered,
+            &server_seed,
+            &["alice".to_string()],
+        );
+
+        for &coord in &coords {
+            let (x, y) = geometry.coordinates(coord);
+            assert!(geometry
+                .neighbors(x, y)
+                .into_iter()
+                .any(|(nx, ny)| coords.contains(&geometry.position(nx, ny))));
+        }
+    }
 }
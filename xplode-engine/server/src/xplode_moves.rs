@@ -3,6 +3,8 @@ use reqwest::Client as HttpClient;
 use serde_json::json;
 use tracing::info;
 
+use crate::board::Geometry;
+
 #[derive(Clone)]
 pub struct XplodeMovesClient {
     api_base: String,
@@ -17,10 +19,39 @@ impl XplodeMovesClient {
         }
     }
 
+    // Only the commitment goes on chain while the game runs, contract storage is public
     pub async fn initialize_game(
         &self,
         game_id: &str,
-        grid_size: u32,
+        geometry: Geometry,
+        server_seed_hash: &str,
+    ) -> Result<String> {
+        info!("Hello");
+        let response = self
+            .client
+            .post(format!("{}/initialize", self.api_base))
+            .json(&json!({
+                "gameId": game_id,
+                "width": geometry.width,
+                "height": geometry.height,
+                "topology": geometry.topology,
+                "serverSeedHash": server_seed_hash
+            }))
+            .send()
+            .await?;
+
+        let result = response.json::<serde_json::Value>().await?;
+
+        let tx_hash = result["transaction"].as_str().unwrap_or_default();
+        info!("Tx hash: {}", tx_hash);
+        Ok(tx_hash.to_string())
+    }
+
+    // Publishes the seed and the bombs it placed once the game is over
+    pub async fn reveal_game(
+        &self,
+        game_id: &str,
+        server_seed: &str,
         bomb_positions: Vec<(usize, usize)>,
     ) -> Result<String> {
         let bomb_positions: Vec<_> = bomb_positions
@@ -28,21 +59,18 @@ impl XplodeMovesClient {
             .map(|(x, y)| json!({ "x": x, "y": y }))
             .collect();
 
-        info!("Hello");
         let response = self
             .client
-            .post(&format!("{}/initialize", self.api_base))
+            .post(format!("{}/reveal", self.api_base))
             .json(&json!({
                 "gameId": game_id,
-                "gridSize": grid_size,
+                "serverSeed": server_seed,
                 "bombPositions": bomb_positions
             }))
             .send()
             .await?;
 
         let result = response.json::<serde_json::Value>().await?;
-
-        info!("Tx hash: {}", result["transaction"].as_str().unwrap());
         Ok(result["transaction"]
             .as_str()
             .unwrap_or_default()
@@ -58,7 +86,7 @@ impl XplodeMovesClient {
     ) -> Result<String> {
         let response = self
             .client
-            .post(&format!("{}/move", self.api_base))
+            .post(format!("{}/move", self.api_base))
             .json(&json!({
                 "gameId": game_id,
                 "playerName": player_name,
@@ -78,7 +106,7 @@ impl XplodeMovesClient {
         println!("Committing game on blockchain");
         let response = self
             .client
-            .post(&format!("{}/commit", self.api_base))
+            .post(format!("{}/commit", self.api_base))
             .json(&json!({
                 "gameId": game_id
             }))
@@ -1,12 +1,13 @@
 use stylus_sdk::{
-    alloy_primitives::{U256, Address},
+    alloy_primitives::{Address, FixedBytes, U256, U8},
     alloy_sol_types::sol,
     prelude::*,
     storage::{StorageMap, StorageVec, StorageU256},
 };
 
 sol! {
-    event GameInitialized(string indexed gameId, uint256 gridSize, address gameServer);
+    event GameInitialized(string indexed gameId, uint256 width, uint256 height, uint8 topology, bytes32 serverSeedHash, address gameServer);
+    event GameRevealed(string indexed gameId, bytes32 serverSeed);
     event MoveMade(string indexed gameId, string playerName, uint256 x, uint256 y, uint256 timestamp);
     event GameDelegated(string indexed gameId, address gameServer);
     event GameCommitted(string indexed gameId, address gameServer);
@@ -14,6 +15,8 @@ sol! {
     error GameAlreadyExists();
     error GameNotFound();
     error Unauthorized();
+    error GameAlreadyRevealed();
+    error InvalidGeometry();
 }
 
 #[derive(SolidityError)]
@@ -21,17 +24,28 @@ pub enum XplodeError {
     GameAlreadyExists(GameAlreadyExists),
     GameNotFound(GameNotFound),
     Unauthorized(Unauthorized),
+    GameAlreadyRevealed(GameAlreadyRevealed),
+    InvalidGeometry(InvalidGeometry),
 }
 
 sol_storage! {
     #[entrypoint]
     pub struct XplodeGame {
         mapping(string => bool) game_exists;
-        mapping(string => uint256) grid_sizes;
+        // Board dimensions, topology 0 is Square, 1 Hex and 2 Torus
+        mapping(string => uint256) widths;
+        mapping(string => uint256) heights;
+        mapping(string => uint8) topologies;
         mapping(string => address) game_servers;
         mapping(string => bool) is_delegated;
         mapping(string => uint256) move_counts;
-        
+
+        // Commitment to the server seed, the seed and bombs are only stored once the
+        // game is over
+        mapping(string => bytes32) seed_hashes;
+        mapping(string => bytes32) server_seeds;
+        mapping(string => bool) is_revealed;
+
         // For bomb positions: game_id -> index -> (x, y)
         mapping(string => mapping(uint256 => uint256)) bomb_x;
         mapping(string => mapping(uint256 => uint256)) bomb_y;
@@ -50,36 +64,75 @@ impl XplodeGame {
     pub fn initialize_game(
         &mut self,
         game_id: String,
-        grid_size: U256,
-        bomb_positions: Vec<(U256, U256)>,
+        width: U256,
+        height: U256,
+        topology: u8,
+        server_seed_hash: FixedBytes<32>,
     ) -> Result<(), XplodeError> {
         let sender = msg::sender();
 
         if self.game_exists.get(game_id.clone()) {
             return Err(XplodeError::GameAlreadyExists(GameAlreadyExists {}));
         }
+        if width == U256::ZERO || height == U256::ZERO || topology > 2 {
+            return Err(XplodeError::InvalidGeometry(InvalidGeometry {}));
+        }
 
         // Store game data
         self.game_exists.setter(game_id.clone()).set(true);
-        self.grid_sizes.setter(game_id.clone()).set(grid_size);
+        self.widths.setter(game_id.clone()).set(width);
+        self.heights.setter(game_id.clone()).set(height);
+        self.topologies.setter(game_id.clone()).set(U8::from(topology));
         self.game_servers.setter(game_id.clone()).set(sender);
         self.is_delegated.setter(game_id.clone()).set(false);
         self.move_counts.setter(game_id.clone()).set(U256::ZERO);
+        self.seed_hashes.setter(game_id.clone()).set(server_seed_hash);
+
+        evm::log(GameInitialized {
+            gameId: game_id,
+            width,
+            height,
+            topology,
+            serverSeedHash: server_seed_hash,
+            gameServer: sender,
+        });
+
+        Ok(())
+    }
+
+    // Publishes the seed and the bombs it placed, once the game is over
+    pub fn reveal_game(
+        &mut self,
+        game_id: String,
+        server_seed: FixedBytes<32>,
+        bomb_positions: Vec<(U256, U256)>,
+    ) -> Result<(), XplodeError> {
+        if !self.game_exists.get(game_id.clone()) {
+            return Err(XplodeError::GameNotFound(GameNotFound {}));
+        }
+        if self.game_servers.get(game_id.clone()) != msg::sender() {
+            return Err(XplodeError::Unauthorized(Unauthorized {}));
+        }
+        if self.is_revealed.get(game_id.clone()) {
+            return Err(XplodeError::GameAlreadyRevealed(GameAlreadyRevealed {}));
+        }
+
+        self.is_revealed.setter(game_id.clone()).set(true);
+        self.server_seeds.setter(game_id.clone()).set(server_seed);
 
         // Store bomb positions
         let bomb_count = U256::from(bomb_positions.len());
         self.bomb_counts.setter(game_id.clone()).set(bomb_count);
-        
+
         for (i, (x, y)) in bomb_positions.iter().enumerate() {
             let index = U256::from(i);
             self.bomb_x.setter(game_id.clone()).setter(index).set(*x);
             self.bomb_y.setter(game_id.clone()).setter(index).set(*y);
         }
 
-        evm::log(GameInitialized {
+        evm::log(GameRevealed {
             gameId: game_id,
-            gridSize: grid_size,
-            gameServer: sender,
+            serverSeed: server_seed,
         });
 
         Ok(())
@@ -157,4 +210,13 @@ impl XplodeGame {
     pub fn game_exists(&self, game_id: String) -> bool {
         self.game_exists.get(game_id)
     }
+
+    // Width, height and topology of the game's board
+    pub fn get_geometry(&self, game_id: String) -> (U256, U256, u8) {
+        (
+            self.widths.get(game_id.clone()),
+            self.heights.get(game_id.clone()),
+            self.topologies.get(game_id).to::<u8>(),
+        )
+    }
 }
\ No newline at end of file
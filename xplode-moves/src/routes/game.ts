@@ -15,6 +15,16 @@ router.post("/initialize", async (req, res) => {
   }
 });
 
+router.post("/reveal", async (req, res) => {
+  try {
+    const tx = await blockchainService.revealGame(req.body);
+    res.json({ success: true, transaction: tx });
+  } catch (error: any) {
+    console.error("Error in reveal endpoint:", error);
+    res.status(500).json({ success: false, error: error.message });
+  }
+});
+
 router.post("/move", async (req, res) => {
   try {
     const tx = await blockchainService.recordMove(req.body);
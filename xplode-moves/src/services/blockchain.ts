@@ -1,20 +1,25 @@
 import { ethers } from "ethers";
-import { InitializeGameRequest, RecordMoveRequest } from "../types";
+import { InitializeGameRequest, RecordMoveRequest, RevealGameRequest, Topology } from "../types";
 
 const XPLODE_ABI = [
-  "function initializeGame(string memory gameId, uint256 gridSize, tuple(uint256,uint256)[] memory bombPositions) external",
+  "function initializeGame(string memory gameId, uint256 width, uint256 height, uint8 topology, bytes32 serverSeedHash) external",
+  "function revealGame(string memory gameId, bytes32 serverSeed, tuple(uint256,uint256)[] memory bombPositions) external",
   "function recordMove(string memory gameId, string memory playerName, uint256 x, uint256 y) external",
   "function delegateGame(string memory gameId) external",
   "function commitAndUndelegateGame(string memory gameId) external",
   "function gameExists(string memory gameId) external view returns (bool)",
-  "function getGridSize(string memory gameId) external view returns (uint256)",
+  "function getGeometry(string memory gameId) external view returns (uint256, uint256, uint8)",
   "function getMoveCount(string memory gameId) external view returns (uint256)",
-  "event GameInitialized(string indexed gameId, uint256 gridSize, address gameServer)",
+  "event GameInitialized(string indexed gameId, uint256 width, uint256 height, uint8 topology, bytes32 serverSeedHash, address gameServer)",
+  "event GameRevealed(string indexed gameId, bytes32 serverSeed)",
   "event MoveMade(string indexed gameId, string playerName, uint256 x, uint256 y, uint256 timestamp)",
   "event GameDelegated(string indexed gameId, address gameServer)",
   "event GameCommitted(string indexed gameId, address gameServer)"
 ];
 
+// Topologies as the contract stores them
+const TOPOLOGIES: Record<Topology, number> = { Square: 0, Hex: 1, Torus: 2 };
+
 export class BlockchainService {
   private provider: ethers.Provider;
   private wallet: ethers.Wallet;
@@ -48,21 +53,27 @@ export class BlockchainService {
     try {
       console.log("Initialize game request:", request);
 
-      const bombPositions = request.bombPositions.map(pos => [
-        ethers.toBigInt(pos.x),
-        ethers.toBigInt(pos.y)
-      ]);
+      // Contract storage is public, only the commitment goes on chain while the game runs
+      const serverSeedHash = "0x" + request.serverSeedHash;
+      const topology = TOPOLOGIES[request.topology];
+      if (topology === undefined) {
+        throw new Error(`Unknown topology ${request.topology}`);
+      }
 
       const gasEstimate = await this.contract.initializeGame.estimateGas(
         request.gameId,
-        ethers.toBigInt(request.gridSize),
-        bombPositions
+        ethers.toBigInt(request.width),
+        ethers.toBigInt(request.height),
+        topology,
+        serverSeedHash
       );
 
       const tx = await this.contract.initializeGame(
         request.gameId,
-        ethers.toBigInt(request.gridSize),
-        bombPositions,
+        ethers.toBigInt(request.width),
+        ethers.toBigInt(request.height),
+        topology,
+        serverSeedHash,
         {
           gasLimit: gasEstimate * 120n / 100n
         }
@@ -86,6 +97,42 @@ export class BlockchainService {
     }
   }
 
+  async revealGame(request: RevealGameRequest): Promise<string> {
+    try {
+      console.log("Reveal game request:", request);
+
+      const serverSeed = "0x" + request.serverSeed;
+      const bombPositions = request.bombPositions.map(pos => [
+        ethers.toBigInt(pos.x),
+        ethers.toBigInt(pos.y)
+      ]);
+
+      const gasEstimate = await this.contract.revealGame.estimateGas(
+        request.gameId,
+        serverSeed,
+        bombPositions
+      );
+
+      const tx = await this.contract.revealGame(
+        request.gameId,
+        serverSeed,
+        bombPositions,
+        {
+          gasLimit: gasEstimate * 120n / 100n
+        }
+      );
+
+      console.log("Reveal transaction sent:", tx.hash);
+      const receipt = await tx.wait(1);
+      console.log("Game revealed in block:", receipt.blockNumber);
+
+      return tx.hash;
+    } catch (error) {
+      console.error("Error revealing game:", error);
+      throw error;
+    }
+  }
+
   async recordMove(request: RecordMoveRequest): Promise<string> {
     try {
       console.log("Record move request:", request);
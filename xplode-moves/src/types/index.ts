@@ -3,9 +3,21 @@ export interface Coordinates {
   y: number;
 }
 
+export type Topology = "Square" | "Hex" | "Torus";
+
 export interface InitializeGameRequest {
   gameId: string;
-  gridSize: number;
+  width: number;
+  height: number;
+  topology: Topology;
+  // Hex SHA3-256 of the server seed, the bombs are only revealed once the game ends
+  serverSeedHash: string;
+}
+
+export interface RevealGameRequest {
+  gameId: string;
+  // Hex server seed committed to at initialization
+  serverSeed: string;
   bombPositions: Coordinates[];
 }
 